@@ -0,0 +1,24 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use wirewhale::capture::CaptureReader;
+use wirewhale::context::Context;
+
+// Reads a whole pcap or pcapng file and renders every packet of it.
+fuzz_target!(|data: &[u8]| {
+    async_std::task::block_on(async {
+        let mut read = data;
+        let mut ctx = Context::default();
+        let mut capture = match CaptureReader::open(&mut read).await {
+            Ok(x) => x,
+            Err(_) => return,
+        };
+        while let Ok(Some(packet)) = capture.read_packet(&mut read, &mut ctx).await {
+            packet.text();
+            packet.line();
+            for field in packet.fields().iter().flat_map(|x| x.iter()) {
+                field.label();
+            }
+        }
+    })
+});
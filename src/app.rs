@@ -1,15 +1,40 @@
-use std::collections::VecDeque;
+use std::collections::{BTreeSet, VecDeque};
+use std::io::BufWriter;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, RwLock};
 
+use anyhow::{Context as _, Result};
+
+use crate::capture::CaptureStats;
+use crate::export::{Format, PacketWriter, Selection, TextFormat, TextWriter};
+use crate::field::Field;
 use crate::filtable::FiltableList;
+use crate::hex_view::HexView;
+use crate::protocol_tree::{ProtocolTree, TreeRow};
+use crate::registry::Registry;
 use crate::Packet;
 
+// packets of a file dissected past the selection, so scrolling finds them
+const LOOKAHEAD: usize = 1000;
+
 #[derive(Debug)]
 pub struct App {
     list: FiltableList<Packet>,
     select: Option<usize>,
     view: Option<usize>,
+    tree: ProtocolTree,
+    hex: HexView,
     input_mode: InputMode,
     running: bool,
+    registry: Arc<RwLock<Registry>>,
+    command: Option<String>,
+    status: String,
+    capture_stats: Option<CaptureStats>,
+    marked: BTreeSet<usize>,
+    // packets are still being read from a file
+    loading: bool,
+    // a write or export run once the rest of the file is read
+    pending_command: Option<String>,
 }
 
 impl std::fmt::Display for App {
@@ -28,48 +53,90 @@ impl std::fmt::Display for App {
 
 impl Default for App {
     fn default() -> Self {
+        Self::new(Default::default())
+    }
+}
+
+impl App {
+    pub fn new(registry: Arc<RwLock<Registry>>) -> Self {
         Self {
             list: FiltableList::<Packet>::new(),
             select: None,
             view: None,
+            tree: ProtocolTree::default(),
+            hex: HexView::default(),
             input_mode: InputMode::List,
             running: true,
+            registry,
+            command: None,
+            status: "".to_string(),
+            capture_stats: None,
+            marked: BTreeSet::new(),
+            loading: false,
+            pending_command: None,
         }
     }
-}
-
-impl App {
     pub fn is_running(&self) -> bool {
         self.running
     }
     pub fn exit(&mut self) {
         self.running = false
     }
-    pub fn get_view_text(&self) -> String {
+    pub fn get_view_tree(&mut self, height: u16) -> (Vec<TreeRow>, Option<usize>) {
+        self.tree.rows(height as usize)
+    }
+    pub fn get_view_field(&self) -> Option<&Field> {
+        self.tree.selected()
+    }
+    pub fn tree_mut(&mut self) -> &mut ProtocolTree {
+        &mut self.tree
+    }
+    pub fn get_view_bytes(&self) -> &[u8] {
         match self.view {
-            Some(x) => self.list.get_item(x).text().join("\n"),
-            None => "".to_string(),
+            Some(x) => self.list.get_item(x).data(),
+            None => &[],
         }
     }
+    pub fn hex_mut(&mut self) -> &mut HexView {
+        &mut self.hex
+    }
+    /// Moves the hex cursor and selects the field under the selection.
+    pub fn move_byte_cursor(&mut self, delta: isize) {
+        let len = self.get_view_bytes().len();
+        self.hex.move_cursor(delta, len);
+        self.tree.select_bytes(self.hex.selection());
+    }
+    pub fn toggle_byte_anchor(&mut self) {
+        self.hex.toggle_anchor();
+        self.tree.select_bytes(self.hex.selection());
+    }
     pub fn get_filter(&self) -> String {
         self.list.get_filter()
     }
+    pub fn get_filter_error(&self) -> Option<String> {
+        self.list.get_filter_error()
+    }
 
-    pub fn get_view_list(&self, height: u16, offset: &mut usize) -> (Vec<String>, Option<usize>) {
+    /// Lines of the visible packets, with whether each is marked.
+    pub fn get_view_list(
+        &self,
+        height: u16,
+        offset: &mut usize,
+    ) -> (Vec<(String, bool)>, Option<usize>) {
         let (items, select) = self.get_view_list_index(height, offset);
         let ans = items
             .iter()
-            .map(|x| self.list.get_item(*x).line())
+            .map(|x| (self.list.get_item(*x).line(), self.marked.contains(x)))
             .collect();
         (ans, select)
     }
 
     pub fn add_filter_str(&mut self, st: &str) {
-        self.list.add_filter_str(st);
+        self.list.add_filter_str(st, &self.registry.read().unwrap());
     }
 
     pub fn delete_filter_char(&mut self) {
-        self.list.delete_filter_char();
+        self.list.delete_filter_char(&self.registry.read().unwrap());
     }
 
     fn get_view_list_index(&self, height: u16, offset: &mut usize) -> (Vec<usize>, Option<usize>) {
@@ -127,6 +194,19 @@ impl App {
         self.list.push(p);
     }
 
+    pub fn packet_count(&self) -> usize {
+        self.list.len()
+    }
+
+    /// How many packets of an indexed file should be dissected by now. A
+    /// filter or a write has to see all of them.
+    pub fn wanted_packets(&self) -> usize {
+        if !self.get_filter().is_empty() || self.pending_command.is_some() {
+            return usize::MAX;
+        }
+        self.select.unwrap_or(0) + LOOKAHEAD
+    }
+
     pub fn next(&mut self) {
         if self.select.is_none() {
             self.select = self.list.filterd_last();
@@ -149,6 +229,137 @@ impl App {
 
     pub fn to_view(&mut self) {
         self.view = self.select;
+        self.tree = match self.view {
+            Some(x) => ProtocolTree::new(self.list.get_item(x).fields()),
+            None => ProtocolTree::default(),
+        };
+        self.hex = HexView::default();
+    }
+
+    pub fn toggle_mark(&mut self) {
+        if let Some(x) = self.select {
+            if !self.marked.remove(&x) {
+                self.marked.insert(x);
+            }
+        }
+    }
+
+    pub fn set_loading(&mut self, loading: bool) {
+        self.loading = loading;
+        if loading {
+            return;
+        }
+        if let Some(command) = self.pending_command.take() {
+            self.status = self.execute(&command);
+        }
+    }
+
+    /// Writes the selected packets to `path`, pcapng if it ends with
+    /// ".pcapng", and returns how many were written.
+    pub fn write_packets(&self, path: &Path, selection: &Selection) -> Result<usize> {
+        let file =
+            std::fs::File::create(path).with_context(|| format!("create {}", path.display()))?;
+        let mut writer = PacketWriter::new(BufWriter::new(file), Format::from_path(path))?;
+        let mut count = 0;
+        for i in 0..self.list.len() {
+            let packet = self.list.get_item(i);
+            let selected = match selection {
+                Selection::All => true,
+                Selection::Displayed => self.list.is_match(i),
+                Selection::Marked => self.marked.contains(&i),
+                Selection::Range(first, last) => (*first..=*last).contains(&packet.number()),
+            };
+            if selected {
+                writer.write_packet(packet)?;
+                count += 1;
+            }
+        }
+        writer.finish()?;
+        Ok(count)
+    }
+
+    fn write_status(&self, path: &Path, selection: &Selection) -> String {
+        match self.write_packets(path, selection) {
+            Ok(x) => format!("wrote {} packets to {}", x, path.display()),
+            Err(e) => format!("write: {:#}", e),
+        }
+    }
+
+    fn write_command(&mut self, args: &str) -> String {
+        let mut words = args.split_whitespace();
+        let path = match words.next() {
+            Some(x) => PathBuf::from(x),
+            None => return "usage: write <file> [all|displayed|marked|<first>-<last>]".to_string(),
+        };
+        let selection = match words.next().unwrap_or("displayed").parse() {
+            Ok(x) => x,
+            Err(e) => return format!("write: {:#}", e),
+        };
+        if self.loading {
+            self.pending_command = Some(format!("write {}", args));
+            return format!(
+                "reading the rest of the capture to write {}",
+                path.display()
+            );
+        }
+        self.write_status(&path, &selection)
+    }
+
+    /// Exports the displayed packets with all their fields, or the
+    /// `columns` of them for CSV, and returns how many were written.
+    pub fn export_view(
+        &self,
+        path: &Path,
+        format: TextFormat,
+        columns: Vec<String>,
+    ) -> Result<usize> {
+        let file =
+            std::fs::File::create(path).with_context(|| format!("create {}", path.display()))?;
+        let mut writer = TextWriter::new(BufWriter::new(file), format, columns)?;
+        let mut count = 0;
+        for i in (0..self.list.len()).filter(|x| self.list.is_match(*x)) {
+            writer.write_packet(self.list.get_item(i))?;
+            count += 1;
+        }
+        writer.finish()?;
+        Ok(count)
+    }
+
+    fn export_command(&mut self, args: &str) -> String {
+        let usage = "usage: export <file> [json|ndjson|csv] [<field>,...]";
+        let mut words = args.split_whitespace();
+        let path = match words.next() {
+            Some(x) => PathBuf::from(x),
+            None => return usage.to_string(),
+        };
+        let mut rest: Vec<&str> = words.collect();
+        let format = match rest.first().map(|x| x.parse::<TextFormat>()) {
+            Some(Ok(x)) => {
+                rest.remove(0);
+                x
+            }
+            _ => match TextFormat::from_path(&path) {
+                Some(x) => x,
+                None => return usage.to_string(),
+            },
+        };
+        let columns = rest
+            .iter()
+            .flat_map(|x| x.split(','))
+            .filter(|x| !x.is_empty())
+            .map(|x| x.to_string())
+            .collect();
+        if self.loading {
+            self.pending_command = Some(format!("export {}", args));
+            return format!(
+                "reading the rest of the capture to export {}",
+                path.display()
+            );
+        }
+        match self.export_view(&path, format, columns) {
+            Ok(x) => format!("exported {} packets to {}", x, path.display()),
+            Err(e) => format!("export: {:#}", e),
+        }
     }
 
     pub fn unselect(&mut self) {
@@ -161,6 +372,71 @@ impl App {
     pub fn get_input_mode(&self) -> InputMode {
         self.input_mode
     }
+
+    pub fn get_status(&self) -> String {
+        self.status.clone()
+    }
+
+    pub fn set_status(&mut self, status: String) {
+        self.status = status;
+    }
+
+    pub fn get_capture_stats(&self) -> Option<CaptureStats> {
+        self.capture_stats
+    }
+
+    pub fn set_capture_stats(&mut self, stats: CaptureStats) {
+        self.capture_stats = Some(stats);
+    }
+
+    pub fn get_command(&self) -> Option<String> {
+        self.command.clone()
+    }
+
+    pub fn start_command(&mut self) {
+        self.command = Some("".to_string());
+    }
+
+    pub fn cancel_command(&mut self) {
+        self.command = None;
+    }
+
+    pub fn add_command_str(&mut self, st: &str) {
+        if let Some(x) = self.command.as_mut() {
+            x.push_str(st);
+        }
+    }
+
+    pub fn delete_command_char(&mut self) {
+        if let Some(x) = self.command.as_mut() {
+            x.pop();
+        }
+    }
+
+    pub fn run_command(&mut self) {
+        let command = match self.command.take() {
+            Some(x) => x,
+            None => return,
+        };
+        self.status = self.execute(&command);
+    }
+
+    fn execute(&mut self, command: &str) -> String {
+        let (name, args) = command
+            .trim()
+            .split_once(' ')
+            .unwrap_or((command.trim(), ""));
+        match name {
+            "decode-as" => match self.registry.write().unwrap().decode_as(args) {
+                Ok(()) => format!("decoding {} from the next packet on", args.trim()),
+                Err(e) => format!("decode-as: {:#}", e),
+            },
+            "write" | "w" => self.write_command(args),
+            "export" => self.export_command(args),
+            "" => "".to_string(),
+            x => format!("unknown command: {}", x),
+        }
+    }
 }
 
 #[derive(Debug, Copy, Clone)]
@@ -168,6 +444,7 @@ pub enum InputMode {
     Filter,
     List,
     View,
+    Bytes,
 }
 
 impl InputMode {
@@ -175,7 +452,8 @@ impl InputMode {
         match self {
             InputMode::Filter => *self = InputMode::List,
             InputMode::List => *self = InputMode::View,
-            InputMode::View => *self = InputMode::Filter,
+            InputMode::View => *self = InputMode::Bytes,
+            InputMode::Bytes => *self = InputMode::Filter,
         };
     }
 }
@@ -183,7 +461,9 @@ impl InputMode {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::context::Context;
     use crate::packet::read_packet;
+    use crate::pcap::PcapHeader;
     #[test]
     fn it_works() {
         let mut app = App::default();
@@ -222,14 +502,15 @@ mod tests {
         ];
 
         async_std::task::block_on(async {
+            let mut ctx = Context::default();
             for i in 0..20 {
                 let mut packet_read = if i % 2 == 0 {
                     &tcp_packet[..]
                 } else {
                     &udp_packet[..]
                 };
-                let packet = read_packet(&mut packet_read).await;
-                if let Ok(packet) = packet {
+                let packet = read_packet(&mut packet_read, &PcapHeader::default(), &mut ctx).await;
+                if let Ok(Some(packet)) = packet {
                     app.add_packet(packet);
                 }
             }
@@ -238,7 +519,7 @@ mod tests {
         let height = 5;
         let (view_list, _) = app.get_view_list(height, &mut offset);
         assert_eq!(view_list.len(), height as usize);
-        app.list.add_filter_str("UDP");
+        app.add_filter_str("udp");
         let (view_list, _) = app.get_view_list(height, &mut offset);
         assert_eq!(view_list.len(), height as usize);
         app.next();
@@ -257,4 +538,43 @@ mod tests {
         let (_view_list, select) = app.get_view_list(height, &mut offset);
         assert_eq!(select, Some(1));
     }
+
+    #[test]
+    fn test_decode_as_command() {
+        let mut app = App::default();
+        app.start_command();
+        app.add_command_str("decode-as tcp.port==8443,http");
+        app.run_command();
+        assert_eq!(app.get_command(), None);
+        assert_eq!(
+            app.get_status(),
+            "decoding tcp.port==8443,http from the next packet on"
+        );
+        app.start_command();
+        app.add_command_str("decode-as tcp.port==8443,gopher");
+        app.run_command();
+        assert_eq!(app.get_status(), "decode-as: unknown protocol \"gopher\"");
+    }
+
+    #[test]
+    fn test_export_command() {
+        let mut app = App::default();
+        let path = std::env::temp_dir().join(format!("wirewhale-{}.csv", std::process::id()));
+        let command = format!("export {} ip.src,ip.dst", path.display());
+        // waits for the file being read
+        app.set_loading(true);
+        app.start_command();
+        app.add_command_str(&command);
+        app.run_command();
+        assert!(app.get_status().starts_with("reading the rest"));
+        assert_eq!(app.wanted_packets(), usize::MAX);
+        app.set_loading(false);
+        assert!(app.get_status().starts_with("exported 0 packets"));
+        assert_eq!(std::fs::read_to_string(&path).unwrap(), "ip.src,ip.dst\n");
+        std::fs::remove_file(&path).unwrap();
+        app.start_command();
+        app.add_command_str("export out.txt");
+        app.run_command();
+        assert!(app.get_status().starts_with("usage: export"));
+    }
 }
@@ -0,0 +1,403 @@
+mod compile;
+
+use crate::packet::PacketHeader;
+use anyhow::{bail, Context as _, Result};
+use compile::Expr;
+use std::collections::HashMap;
+
+// instruction classes
+pub(crate) const LD: u16 = 0x00;
+pub(crate) const LDX: u16 = 0x01;
+pub(crate) const ST: u16 = 0x02;
+pub(crate) const STX: u16 = 0x03;
+pub(crate) const ALU: u16 = 0x04;
+pub(crate) const JMP: u16 = 0x05;
+pub(crate) const RET: u16 = 0x06;
+pub(crate) const MISC: u16 = 0x07;
+// load sizes and modes
+pub(crate) const W: u16 = 0x00;
+pub(crate) const H: u16 = 0x08;
+pub(crate) const B: u16 = 0x10;
+pub(crate) const IMM: u16 = 0x00;
+pub(crate) const ABS: u16 = 0x20;
+pub(crate) const IND: u16 = 0x40;
+pub(crate) const MEM: u16 = 0x60;
+pub(crate) const LEN: u16 = 0x80;
+pub(crate) const MSH: u16 = 0xa0;
+// alu operations
+pub(crate) const ADD: u16 = 0x00;
+pub(crate) const SUB: u16 = 0x10;
+pub(crate) const MUL: u16 = 0x20;
+pub(crate) const DIV: u16 = 0x30;
+pub(crate) const OR: u16 = 0x40;
+pub(crate) const AND: u16 = 0x50;
+pub(crate) const LSH: u16 = 0x60;
+pub(crate) const RSH: u16 = 0x70;
+pub(crate) const NEG: u16 = 0x80;
+pub(crate) const MOD: u16 = 0x90;
+pub(crate) const XOR: u16 = 0xa0;
+// jumps
+pub(crate) const JA: u16 = 0x00;
+pub(crate) const JEQ: u16 = 0x10;
+pub(crate) const JGT: u16 = 0x20;
+pub(crate) const JGE: u16 = 0x30;
+pub(crate) const JSET: u16 = 0x40;
+// operand sources, also used by RET
+pub(crate) const K: u16 = 0x00;
+pub(crate) const X: u16 = 0x08;
+pub(crate) const A: u16 = 0x10;
+// misc operations
+pub(crate) const TAX: u16 = 0x00;
+pub(crate) const TXA: u16 = 0x80;
+
+const MEMWORDS: usize = 16;
+
+/// A classic BPF instruction, laid out like the kernel's `struct sock_filter`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Instruction {
+    pub code: u16,
+    pub jt: u8,
+    pub jf: u8,
+    pub k: u32,
+}
+
+impl Instruction {
+    pub(crate) fn new(code: u16, k: u32) -> Self {
+        Self {
+            code,
+            jt: 0,
+            jf: 0,
+            k,
+        }
+    }
+}
+
+fn load(data: &[u8], offset: u32, size: u16) -> Option<u32> {
+    let start = offset as usize;
+    let bytes = match size {
+        W => data.get(start..start.checked_add(4)?)?,
+        H => data.get(start..start.checked_add(2)?)?,
+        B => data.get(start..start.checked_add(1)?)?,
+        _ => return None,
+    };
+    Some(bytes.iter().fold(0, |x, y| x << 8 | *y as u32))
+}
+
+/// A cBPF program that can be attached to a socket.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Program {
+    instructions: Vec<Instruction>,
+}
+
+impl Program {
+    pub fn new(instructions: Vec<Instruction>) -> Self {
+        Self { instructions }
+    }
+
+    pub fn instructions(&self) -> &[Instruction] {
+        &self.instructions
+    }
+
+    /// Runs the program over a frame the way the kernel does and returns
+    /// how many bytes of it to keep, 0 to drop it. `wire_len` is what
+    /// `len` loads.
+    pub fn run(&self, data: &[u8], wire_len: u32) -> u32 {
+        self.execute(data, wire_len).unwrap_or(0)
+    }
+
+    // None wherever the kernel would drop the frame, like loads out of bounds
+    fn execute(&self, data: &[u8], wire_len: u32) -> Option<u32> {
+        let (mut a, mut x) = (0u32, 0u32);
+        let mut mem = [0u32; MEMWORDS];
+        let mut pc = 0;
+        loop {
+            let insn = self.instructions.get(pc)?;
+            pc += 1;
+            let k = insn.k;
+            match insn.code & 0x07 {
+                LD => {
+                    a = match insn.code & 0xe0 {
+                        IMM => k,
+                        ABS => load(data, k, insn.code & 0x18)?,
+                        IND => load(data, x.checked_add(k)?, insn.code & 0x18)?,
+                        MEM => *mem.get(k as usize)?,
+                        LEN => wire_len,
+                        _ => return None,
+                    }
+                }
+                LDX => {
+                    x = match insn.code & 0xe0 {
+                        IMM => k,
+                        MEM => *mem.get(k as usize)?,
+                        LEN => wire_len,
+                        MSH => (load(data, k, B)? & 0x0f) * 4,
+                        _ => return None,
+                    }
+                }
+                ST => *mem.get_mut(k as usize)? = a,
+                STX => *mem.get_mut(k as usize)? = x,
+                ALU => {
+                    let operand = if insn.code & X != 0 { x } else { k };
+                    a = match insn.code & 0xf0 {
+                        ADD => a.wrapping_add(operand),
+                        SUB => a.wrapping_sub(operand),
+                        MUL => a.wrapping_mul(operand),
+                        DIV => a.checked_div(operand)?,
+                        MOD => a.checked_rem(operand)?,
+                        OR => a | operand,
+                        AND => a & operand,
+                        XOR => a ^ operand,
+                        LSH => a.checked_shl(operand).unwrap_or(0),
+                        RSH => a.checked_shr(operand).unwrap_or(0),
+                        NEG => a.wrapping_neg(),
+                        _ => return None,
+                    }
+                }
+                JMP => {
+                    let operand = if insn.code & X != 0 { x } else { k };
+                    let taken = match insn.code & 0xf0 {
+                        JA => {
+                            pc = pc.checked_add(k as usize)?;
+                            continue;
+                        }
+                        JEQ => a == operand,
+                        JGT => a > operand,
+                        JGE => a >= operand,
+                        JSET => a & operand != 0,
+                        _ => return None,
+                    };
+                    pc += if taken { insn.jt } else { insn.jf } as usize;
+                }
+                RET => {
+                    return match insn.code & 0x18 {
+                        K => Some(k),
+                        A => Some(a),
+                        _ => None,
+                    }
+                }
+                MISC => match insn.code & 0xf8 {
+                    TAX => x = a,
+                    TXA => a = x,
+                    _ => return None,
+                },
+                _ => return None,
+            }
+        }
+    }
+
+    /// Parses the decimal listing printed by `tcpdump -ddd`: the number of
+    /// instructions, then one "code jt jf k" per line. Commas may separate
+    /// the instructions instead of newlines.
+    pub fn parse(s: &str) -> Result<Self> {
+        let mut lines = s
+            .split(['\n', ','])
+            .map(|x| x.trim())
+            .filter(|x| !x.is_empty());
+        let count: usize = lines
+            .next()
+            .context("empty bpf program")?
+            .parse()
+            .context("invalid instruction count")?;
+        let mut instructions = vec![];
+        for line in lines {
+            let numbers: Vec<u32> = line
+                .split_whitespace()
+                .map(|x| x.parse())
+                .collect::<std::result::Result<_, _>>()
+                .with_context(|| format!("invalid instruction {:?}", line))?;
+            let [code, jt, jf, k] = numbers[..] else {
+                bail!("expected code jt jf k in {:?}", line);
+            };
+            instructions.push(Instruction {
+                code: u16::try_from(code).context("code out of range")?,
+                jt: u8::try_from(jt).context("jt out of range")?,
+                jf: u8::try_from(jf).context("jf out of range")?,
+                k,
+            });
+        }
+        if instructions.len() != count {
+            bail!(
+                "expected {} instructions, found {}",
+                count,
+                instructions.len()
+            );
+        }
+        Ok(Self::new(instructions))
+    }
+}
+
+/// A tcpdump style capture filter, e.g. "tcp port 80 and host 10.0.0.1",
+/// compiled for each link type it meets.
+#[derive(Debug, Clone)]
+pub struct CaptureFilter {
+    expr: Expr,
+    programs: HashMap<u32, Option<Program>>,
+}
+
+impl CaptureFilter {
+    pub fn parse(s: &str) -> Result<Self> {
+        Ok(Self {
+            expr: compile::parse(s)?,
+            programs: HashMap::new(),
+        })
+    }
+
+    /// The filter as cBPF for frames of `link_type`.
+    pub fn compile(&self, link_type: u32) -> Result<Program> {
+        compile::compile(&self.expr, link_type)
+    }
+
+    /// Runs the filter over a frame before it is dissected. Frames of link
+    /// types the filter can't be compiled for never match.
+    pub(crate) fn is_match(&mut self, header: &PacketHeader, data: &[u8]) -> bool {
+        let link_type = header.link_type();
+        let program = self
+            .programs
+            .entry(link_type)
+            .or_insert_with(|| compile::compile(&self.expr, link_type).ok());
+        match program {
+            Some(x) => x.run(data, header.len()) != 0,
+            None => false,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        // tcpdump -ddd ip
+        let program = Program::parse("4\n40 0 0 12\n21 0 1 2048\n6 0 0 262144\n6 0 0 0\n").unwrap();
+        assert_eq!(program.instructions().len(), 4);
+        assert_eq!(
+            program.instructions()[1],
+            Instruction {
+                code: 21,
+                jt: 0,
+                jf: 1,
+                k: 2048
+            }
+        );
+        assert_eq!(
+            Program::parse("2,6 0 0 1,6 0 0 0")
+                .unwrap()
+                .instructions()
+                .len(),
+            2
+        );
+        assert!(Program::parse("2\n6 0 0 1").is_err());
+        assert!(Program::parse("1\n6 0 1").is_err());
+    }
+
+    #[test]
+    fn test_run() {
+        let program = Program::parse("4\n40 0 0 12\n21 0 1 2048\n6 0 0 262144\n6 0 0 0\n").unwrap();
+        let mut frame = [0u8; 14];
+        frame[12] = 0x08;
+        assert_eq!(program.run(&frame, 14), 262144);
+        frame[12] = 0x86;
+        assert_eq!(program.run(&frame, 14), 0);
+        // loads past the end drop the frame
+        assert_eq!(program.run(&frame[..13], 13), 0);
+        // division by zero too, A / X with X = 0
+        let program = Program::new(vec![
+            Instruction::new(ALU | DIV | X, 0),
+            Instruction::new(RET | K, 1),
+        ]);
+        assert_eq!(program.run(&frame, 14), 0);
+        // a program that runs off its end
+        assert_eq!(Program::new(vec![]).run(&frame, 14), 0);
+    }
+
+    // ethernet + ipv4 + the first bytes of a tcp or udp header
+    fn ipv4_frame(protocol: u8, src: [u8; 4], dst: [u8; 4], sport: u16, dport: u16) -> Vec<u8> {
+        let mut frame = vec![0u8; 12];
+        frame.extend([
+            0x08, 0x00, 0x45, 0, 0, 40, 0, 1, 0x40, 0, 64, protocol, 0, 0,
+        ]);
+        frame.extend(src);
+        frame.extend(dst);
+        frame.extend(sport.to_be_bytes());
+        frame.extend(dport.to_be_bytes());
+        frame.extend([0u8; 16]);
+        frame
+    }
+
+    fn matches(filter: &str, link_type: u32, frame: &[u8]) -> bool {
+        let program = CaptureFilter::parse(filter)
+            .unwrap()
+            .compile(link_type)
+            .unwrap();
+        program.run(frame, frame.len() as u32) != 0
+    }
+
+    #[test]
+    fn test_capture_filter() {
+        let filter = "tcp port 80 and host 10.0.0.1";
+        let http = ipv4_frame(6, [10, 0, 0, 1], [10, 0, 0, 2], 80, 50000);
+        assert!(matches(filter, 1, &http));
+        // udp, another host and another port
+        assert!(!matches(
+            filter,
+            1,
+            &ipv4_frame(17, [10, 0, 0, 1], [10, 0, 0, 2], 80, 50000)
+        ));
+        assert!(!matches(
+            filter,
+            1,
+            &ipv4_frame(6, [10, 0, 0, 3], [10, 0, 0, 2], 80, 50000)
+        ));
+        assert!(!matches(
+            filter,
+            1,
+            &ipv4_frame(6, [10, 0, 0, 1], [10, 0, 0, 2], 81, 50000)
+        ));
+        // the same filter over raw ip
+        assert!(matches(filter, 101, &http[14..]));
+        assert!(matches("src 10.0.0.1 && dst port 50000", 1, &http));
+        assert!(!matches("dst 10.0.0.1", 1, &http));
+        assert!(matches("host 10.0.0.9 or 10.0.0.2", 1, &http));
+        assert!(matches("net 10.0.0.0/8 and not udp", 1, &http));
+        assert!(!matches("net 192.168", 1, &http));
+        assert!(matches("tcp portrange 1-1024", 1, &http));
+        assert!(!matches("portrange 81-1024", 1, &http));
+        assert!(matches("ip proto 6 and len >= 54", 1, &http));
+        assert!(matches(
+            "ether proto ip and ip and !ip6 and not arp",
+            1,
+            &http
+        ));
+        assert!(matches("ether src 00:00:00:00:00:00", 1, &http));
+        assert!(matches("port http", 1, &http));
+        assert!(!matches("greater 55", 1, &http));
+        // later fragments have no ports
+        let mut fragment = http.clone();
+        fragment[21] = 1;
+        assert!(!matches("port 80", 1, &fragment));
+        // ipv6 + udp
+        let mut frame = vec![0u8; 12];
+        frame.extend([0x86, 0xdd, 0x60, 0, 0, 0, 0, 8, 17, 64]);
+        frame.extend(std::net::Ipv6Addr::LOCALHOST.octets());
+        frame.extend(std::net::Ipv6Addr::LOCALHOST.octets());
+        frame.extend([0, 53, 0x30, 0x39, 0, 8, 0, 0]);
+        assert!(matches("ip6 and udp port 53 and host ::1", 1, &frame));
+        assert!(!matches("tcp or ip or host ::2", 1, &frame));
+        assert!(matches(
+            "udp dst port 12345",
+            276,
+            &[&[0x86, 0xdd], &[0; 18][..], &frame[14..]].concat()
+        ));
+
+        assert!(CaptureFilter::parse("tcp port").is_err());
+        assert!(CaptureFilter::parse("tcp host 10.0.0.1").is_err());
+        assert!(CaptureFilter::parse("host example.com").is_err());
+        assert!(CaptureFilter::parse("(tcp").is_err());
+        assert!(CaptureFilter::parse("port 70000").is_err());
+        let filter = CaptureFilter::parse("ether host 01:02:03:04:05:06").unwrap();
+        assert!(filter.compile(1).is_ok());
+        assert!(filter.compile(101).is_err());
+    }
+}
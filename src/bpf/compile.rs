@@ -0,0 +1,775 @@
+//! Compiles tcpdump style capture filters to classic BPF.
+
+use super::*;
+use crate::l2data::{
+    DLT_RAW_BSD, DLT_RAW_OPENBSD, LINKTYPE_ETHERNET, LINKTYPE_IPV4, LINKTYPE_IPV6,
+    LINKTYPE_LINUX_SLL, LINKTYPE_LINUX_SLL2, LINKTYPE_RAW,
+};
+use std::net::{Ipv4Addr, Ipv6Addr};
+
+/// What accepted frames return, as much as anyone captures.
+const ACCEPT: u32 = 262144;
+
+const ETHERTYPE_IPV4: u32 = 0x0800;
+const ETHERTYPE_ARP: u32 = 0x0806;
+const ETHERTYPE_IPV6: u32 = 0x86dd;
+
+const PORTS: [(&str, u16); 12] = [
+    ("ftp", 21),
+    ("ssh", 22),
+    ("telnet", 23),
+    ("smtp", 25),
+    ("domain", 53),
+    ("http", 80),
+    ("pop3", 110),
+    ("ntp", 123),
+    ("imap", 143),
+    ("snmp", 161),
+    ("https", 443),
+    ("mdns", 5353),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Proto {
+    Ether,
+    Ip,
+    Ip6,
+    Arp,
+    Tcp,
+    Udp,
+    Icmp,
+    Icmp6,
+}
+
+impl Proto {
+    fn from_name(s: &str) -> Option<Self> {
+        match s {
+            "ether" => Some(Proto::Ether),
+            "ip" => Some(Proto::Ip),
+            "ip6" => Some(Proto::Ip6),
+            "arp" => Some(Proto::Arp),
+            "tcp" => Some(Proto::Tcp),
+            "udp" => Some(Proto::Udp),
+            "icmp" => Some(Proto::Icmp),
+            "icmp6" => Some(Proto::Icmp6),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Dir {
+    Src,
+    Dst,
+    Any,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Kind {
+    Host,
+    Net,
+    Port,
+    PortRange,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Relop {
+    Eq,
+    Ne,
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Host {
+    V4(Ipv4Addr),
+    V6(Ipv6Addr),
+    Mac([u8; 6]),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Primitive {
+    Proto(Proto),
+    Host(Option<Proto>, Dir, Host),
+    Net(Option<Proto>, Dir, Ipv4Addr, u32),
+    Port(Option<Proto>, Dir, u16, u16),
+    EtherProto(u16),
+    IpProto(Option<Proto>, u8),
+    Len(Relop, u32),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Primitive(Primitive),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    Word(String),
+    LParen,
+    RParen,
+    And,
+    Or,
+    Not,
+    Op(Relop),
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut ans = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '&' | '|' => {
+                if chars.next() != Some(c) {
+                    bail!("expected {}{}", c, c);
+                }
+                if c == '&' {
+                    Token::And
+                } else {
+                    Token::Or
+                }
+            }
+            '!' | '<' | '>' | '=' => {
+                let eq = chars.next_if_eq(&'=').is_some();
+                match (c, eq) {
+                    ('!', false) => Token::Not,
+                    ('!', true) => Token::Op(Relop::Ne),
+                    ('<', false) => Token::Op(Relop::Lt),
+                    ('<', true) => Token::Op(Relop::Le),
+                    ('>', false) => Token::Op(Relop::Gt),
+                    ('>', true) => Token::Op(Relop::Ge),
+                    _ => Token::Op(Relop::Eq),
+                }
+            }
+            c => {
+                let mut word = c.to_string();
+                while let Some(x) =
+                    chars.next_if(|x| !x.is_whitespace() && !"()&|!<>=".contains(*x))
+                {
+                    word.push(x);
+                }
+                match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    _ => Token::Word(word),
+                }
+            }
+        };
+        ans.push(token);
+    }
+    Ok(ans)
+}
+
+fn parse_number(s: &str) -> Result<u32> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u32::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .with_context(|| format!("invalid number {:?}", s))
+}
+
+fn parse_port(s: &str) -> Result<u16> {
+    if let Some((_, port)) = PORTS.iter().find(|(name, _)| *name == s) {
+        return Ok(*port);
+    }
+    u16::try_from(parse_number(s)?).with_context(|| format!("port {} out of range", s))
+}
+
+fn parse_mac(s: &str) -> Option<[u8; 6]> {
+    let octets: Vec<u8> = s
+        .split([':', '-'])
+        .map(|x| u8::from_str_radix(x, 16).ok())
+        .collect::<Option<_>>()?;
+    octets.try_into().ok()
+}
+
+fn parse_host(proto: Option<Proto>, s: &str) -> Result<Host> {
+    if proto == Some(Proto::Ether) {
+        return parse_mac(s)
+            .map(Host::Mac)
+            .with_context(|| format!("invalid MAC address {:?}", s));
+    }
+    if let Ok(x) = s.parse() {
+        return Ok(Host::V4(x));
+    }
+    if let Ok(x) = s.parse() {
+        return Ok(Host::V6(x));
+    }
+    bail!("unknown host {:?}, host names are not resolved", s)
+}
+
+// "10.0.0.0/8", or "10" which is the same as tcpdump reads it
+fn parse_net(s: &str) -> Result<(Ipv4Addr, u32)> {
+    let (addr, prefix) = match s.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(parse_number(prefix)?)),
+        None => (s, None),
+    };
+    let octets: Vec<u8> = addr
+        .split('.')
+        .map(|x| x.parse().ok())
+        .collect::<Option<_>>()
+        .with_context(|| format!("invalid network {:?}", s))?;
+    if octets.is_empty() || octets.len() > 4 {
+        bail!("invalid network {:?}", s);
+    }
+    let prefix = prefix.unwrap_or(8 * octets.len() as u32);
+    if prefix > 32 {
+        bail!("invalid prefix length in {:?}", s);
+    }
+    let mut bytes = [0u8; 4];
+    bytes[..octets.len()].copy_from_slice(&octets);
+    Ok((bytes.into(), prefix))
+}
+
+struct Parser {
+    tokens: Vec<Token>,
+    pos: usize,
+    // a bare id like "b" in "host a or b" reuses the qualifiers before it
+    last: Option<(Option<Proto>, Dir, Kind)>,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.pos)
+    }
+    fn peek_word(&self) -> Option<&str> {
+        match self.peek() {
+            Some(Token::Word(x)) => Some(x),
+            _ => None,
+        }
+    }
+    fn word(&mut self) -> Result<String> {
+        match self.tokens.get(self.pos) {
+            Some(Token::Word(x)) => {
+                self.pos += 1;
+                Ok(x.clone())
+            }
+            Some(x) => bail!("unexpected {:?}", x),
+            None => bail!("unexpected end of filter"),
+        }
+    }
+    fn eat_word(&mut self, words: &[&str]) -> Option<String> {
+        let word = self.peek_word().filter(|x| words.contains(x))?.to_string();
+        self.pos += 1;
+        Some(word)
+    }
+
+    fn or(&mut self) -> Result<Expr> {
+        let mut ans = self.and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.pos += 1;
+            ans = Expr::Or(Box::new(ans), Box::new(self.and()?));
+        }
+        Ok(ans)
+    }
+    fn and(&mut self) -> Result<Expr> {
+        let mut ans = self.unary()?;
+        while self.peek() == Some(&Token::And) {
+            self.pos += 1;
+            ans = Expr::And(Box::new(ans), Box::new(self.unary()?));
+        }
+        Ok(ans)
+    }
+    fn unary(&mut self) -> Result<Expr> {
+        match self.peek() {
+            Some(Token::Not) => {
+                self.pos += 1;
+                Ok(Expr::Not(Box::new(self.unary()?)))
+            }
+            Some(Token::LParen) => {
+                self.pos += 1;
+                let ans = self.or()?;
+                if self.peek() != Some(&Token::RParen) {
+                    bail!("expected )");
+                }
+                self.pos += 1;
+                Ok(ans)
+            }
+            _ => Ok(Expr::Primitive(self.primitive()?)),
+        }
+    }
+
+    fn primitive(&mut self) -> Result<Primitive> {
+        if let Some(word) = self.eat_word(&["len", "greater", "less"]) {
+            let op = match word.as_str() {
+                "greater" => Relop::Ge,
+                "less" => Relop::Le,
+                _ => match self.peek() {
+                    Some(Token::Op(x)) => {
+                        let op = *x;
+                        self.pos += 1;
+                        op
+                    }
+                    _ => bail!("expected a comparison after len"),
+                },
+            };
+            return Ok(Primitive::Len(op, parse_number(&self.word()?)?));
+        }
+        let proto = self.peek_word().and_then(Proto::from_name);
+        if proto.is_some() {
+            self.pos += 1;
+        }
+        if self.eat_word(&["proto"]).is_some() {
+            let id = self.word()?;
+            return match proto {
+                Some(Proto::Ether) => {
+                    let ethertype = match id.as_str() {
+                        "ip" => ETHERTYPE_IPV4,
+                        "ip6" => ETHERTYPE_IPV6,
+                        "arp" => ETHERTYPE_ARP,
+                        x => parse_number(x)?,
+                    };
+                    let ethertype = u16::try_from(ethertype).context("ethertype out of range")?;
+                    Ok(Primitive::EtherProto(ethertype))
+                }
+                None | Some(Proto::Ip) | Some(Proto::Ip6) => {
+                    let protocol = match id.as_str() {
+                        "icmp" => 1,
+                        "tcp" => 6,
+                        "udp" => 17,
+                        "icmp6" => 58,
+                        x => u8::try_from(parse_number(x)?).context("protocol out of range")?,
+                    };
+                    Ok(Primitive::IpProto(proto, protocol))
+                }
+                Some(x) => bail!("'{:?}' modifier applied to proto", x),
+            };
+        }
+        let dir = self.eat_word(&["src", "dst"]);
+        let kind = self.eat_word(&["host", "net", "port", "portrange"]);
+        let (proto, dir, kind) = match (proto, dir, kind) {
+            (Some(x), None, None) => return Ok(Primitive::Proto(x)),
+            (None, None, None) => self.last.context("expected a filter primitive")?,
+            (proto, dir, kind) => {
+                let dir = match dir.as_deref() {
+                    Some("src") => Dir::Src,
+                    Some("dst") => Dir::Dst,
+                    _ => Dir::Any,
+                };
+                let kind = match kind.as_deref() {
+                    Some("net") => Kind::Net,
+                    Some("port") => Kind::Port,
+                    Some("portrange") => Kind::PortRange,
+                    _ => Kind::Host,
+                };
+                (proto, dir, kind)
+            }
+        };
+        self.last = Some((proto, dir, kind));
+        let id = self.word()?;
+        match kind {
+            Kind::Host => match proto {
+                None | Some(Proto::Ether) | Some(Proto::Ip) | Some(Proto::Ip6)
+                | Some(Proto::Arp) => Ok(Primitive::Host(proto, dir, parse_host(proto, &id)?)),
+                Some(x) => bail!("'{:?}' modifier applied to host", x),
+            },
+            Kind::Net => match proto {
+                None | Some(Proto::Ip) | Some(Proto::Arp) => {
+                    let (addr, prefix) = parse_net(&id)?;
+                    Ok(Primitive::Net(proto, dir, addr, prefix))
+                }
+                Some(x) => bail!("'{:?}' modifier applied to net", x),
+            },
+            Kind::Port | Kind::PortRange => {
+                match proto {
+                    None | Some(Proto::Ip) | Some(Proto::Ip6) | Some(Proto::Tcp)
+                    | Some(Proto::Udp) => {}
+                    Some(x) => bail!("'{:?}' modifier applied to port", x),
+                }
+                let (low, high) = match kind {
+                    Kind::PortRange => {
+                        let (low, high) = id
+                            .split_once('-')
+                            .with_context(|| format!("expected <port>-<port> in {:?}", id))?;
+                        (parse_port(low)?, parse_port(high)?)
+                    }
+                    _ => (parse_port(&id)?, parse_port(&id)?),
+                };
+                Ok(Primitive::Port(proto, dir, low.min(high), low.max(high)))
+            }
+        }
+    }
+}
+
+pub(crate) fn parse(s: &str) -> Result<Expr> {
+    let mut parser = Parser {
+        tokens: tokenize(s)?,
+        pos: 0,
+        last: None,
+    };
+    let ans = parser.or()?;
+    if let Some(x) = parser.peek() {
+        bail!("unexpected {:?}", x);
+    }
+    Ok(ans)
+}
+
+/// A tree of comparisons against the accumulator, each after its loads.
+#[derive(Debug, Clone)]
+enum Node {
+    True,
+    False,
+    Test(Vec<Instruction>, u16, u32),
+    And(Box<Node>, Box<Node>),
+    Or(Box<Node>, Box<Node>),
+    Not(Box<Node>),
+}
+
+fn and(a: Node, b: Node) -> Node {
+    match (a, b) {
+        (Node::False, _) | (_, Node::False) => Node::False,
+        (Node::True, x) | (x, Node::True) => x,
+        (a, b) => Node::And(Box::new(a), Box::new(b)),
+    }
+}
+
+fn or(a: Node, b: Node) -> Node {
+    match (a, b) {
+        (Node::True, _) | (_, Node::True) => Node::True,
+        (Node::False, x) | (x, Node::False) => x,
+        (a, b) => Node::Or(Box::new(a), Box::new(b)),
+    }
+}
+
+fn not(a: Node) -> Node {
+    match a {
+        Node::True => Node::False,
+        Node::False => Node::True,
+        x => Node::Not(Box::new(x)),
+    }
+}
+
+fn ld(size: u16, offset: u32) -> Instruction {
+    Instruction::new(LD | size | ABS, offset)
+}
+
+fn eq(size: u16, offset: u32, k: u32) -> Node {
+    Node::Test(vec![ld(size, offset)], JMP | JEQ | K, k)
+}
+
+fn by_dir(dir: Dir, test: impl Fn(Dir) -> Node) -> Node {
+    match dir {
+        Dir::Any => or(test(Dir::Src), test(Dir::Dst)),
+        x => test(x),
+    }
+}
+
+/// Where the network layer is in frames of a link type.
+struct Link {
+    // offset of the ethertype, raw IP has none
+    ethertype: Option<u32>,
+    l3: u32,
+    is_ethernet: bool,
+}
+
+impl Link {
+    fn new(link_type: u32) -> Result<Self> {
+        let (ethertype, l3) = match link_type {
+            LINKTYPE_ETHERNET => (Some(12), 14),
+            LINKTYPE_LINUX_SLL => (Some(14), 16),
+            LINKTYPE_LINUX_SLL2 => (Some(0), 20),
+            LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 | DLT_RAW_BSD | DLT_RAW_OPENBSD => {
+                (None, 0)
+            }
+            x => bail!("capture filters are not supported on link type {}", x),
+        };
+        Ok(Self {
+            ethertype,
+            l3,
+            is_ethernet: link_type == LINKTYPE_ETHERNET,
+        })
+    }
+
+    fn ethertype(&self, ethertype: u32) -> Node {
+        match (self.ethertype, ethertype) {
+            (Some(offset), x) => eq(H, offset, x),
+            // raw IP tells the versions apart by the first nibble
+            (None, ETHERTYPE_IPV4) => Node::Test(
+                vec![ld(B, 0), Instruction::new(ALU | AND | K, 0xf0)],
+                JMP | JEQ | K,
+                0x40,
+            ),
+            (None, ETHERTYPE_IPV6) => Node::Test(
+                vec![ld(B, 0), Instruction::new(ALU | AND | K, 0xf0)],
+                JMP | JEQ | K,
+                0x60,
+            ),
+            (None, _) => Node::False,
+        }
+    }
+    fn ipv4(&self) -> Node {
+        self.ethertype(ETHERTYPE_IPV4)
+    }
+    fn ipv6(&self) -> Node {
+        self.ethertype(ETHERTYPE_IPV6)
+    }
+    fn arp(&self) -> Node {
+        self.ethertype(ETHERTYPE_ARP)
+    }
+
+    fn ip_proto(&self, family: Option<Proto>, protocol: u8) -> Node {
+        let v4 = and(self.ipv4(), eq(B, self.l3 + 9, protocol.into()));
+        let v6 = and(self.ipv6(), eq(B, self.l3 + 6, protocol.into()));
+        match family {
+            Some(Proto::Ip) => v4,
+            Some(Proto::Ip6) => v6,
+            _ => or(v4, v6),
+        }
+    }
+
+    fn ports(&self, family: Option<Proto>, protocol: u8, dir: Dir, low: u16, high: u16) -> Node {
+        let l3 = self.l3;
+        let range = |loads: Vec<Instruction>| {
+            if low == high {
+                return Node::Test(loads, JMP | JEQ | K, low.into());
+            }
+            and(
+                Node::Test(loads.clone(), JMP | JGE | K, low.into()),
+                not(Node::Test(loads, JMP | JGT | K, high.into())),
+            )
+        };
+        let offset = |dir| if dir == Dir::Src { 0 } else { 2 };
+        // only first fragments carry the transport header
+        let first_fragment = not(Node::Test(vec![ld(H, l3 + 6)], JMP | JSET | K, 0x1fff));
+        let v4 = and(
+            and(self.ip_proto(Some(Proto::Ip), protocol), first_fragment),
+            by_dir(dir, |dir| {
+                range(vec![
+                    Instruction::new(LDX | B | MSH, l3),
+                    Instruction::new(LD | H | IND, l3 + offset(dir)),
+                ])
+            }),
+        );
+        let v6 = and(
+            self.ip_proto(Some(Proto::Ip6), protocol),
+            by_dir(dir, |dir| range(vec![ld(H, l3 + 40 + offset(dir))])),
+        );
+        match family {
+            Some(Proto::Ip) => v4,
+            Some(Proto::Ip6) => v6,
+            _ => or(v4, v6),
+        }
+    }
+
+    fn host(&self, proto: Option<Proto>, dir: Dir, host: Host) -> Result<Node> {
+        let l3 = self.l3;
+        Ok(match host {
+            Host::V4(addr) => {
+                let addr = u32::from(addr);
+                let ip = and(
+                    self.ipv4(),
+                    by_dir(dir, |dir| {
+                        eq(W, l3 + if dir == Dir::Src { 12 } else { 16 }, addr)
+                    }),
+                );
+                let arp = and(
+                    self.arp(),
+                    by_dir(dir, |dir| {
+                        eq(W, l3 + if dir == Dir::Src { 14 } else { 24 }, addr)
+                    }),
+                );
+                match proto {
+                    Some(Proto::Ip) => ip,
+                    Some(Proto::Arp) => arp,
+                    Some(Proto::Ip6) => bail!("{} is not an IPv6 address", Ipv4Addr::from(addr)),
+                    _ => or(ip, arp),
+                }
+            }
+            Host::V6(addr) => {
+                if proto.is_some_and(|x| x != Proto::Ip6) {
+                    bail!("{} is not an IPv4 address", addr);
+                }
+                let words = addr
+                    .octets()
+                    .chunks(4)
+                    .map(|x| u32::from_be_bytes(x.try_into().expect("chunks of 4")))
+                    .collect::<Vec<_>>();
+                let matches = |dir| {
+                    let start = l3 + if dir == Dir::Src { 8 } else { 24 };
+                    words
+                        .iter()
+                        .zip((start..).step_by(4))
+                        .fold(Node::True, |x, (word, offset)| and(x, eq(W, offset, *word)))
+                };
+                and(self.ipv6(), by_dir(dir, matches))
+            }
+            Host::Mac(mac) => {
+                if !self.is_ethernet {
+                    bail!("ether host only works on Ethernet links");
+                }
+                let word = u32::from_be_bytes([mac[0], mac[1], mac[2], mac[3]]);
+                let half = u32::from(u16::from_be_bytes([mac[4], mac[5]]));
+                by_dir(dir, |dir| {
+                    let start = if dir == Dir::Src { 6 } else { 0 };
+                    and(eq(W, start, word), eq(H, start + 4, half))
+                })
+            }
+        })
+    }
+
+    fn net(&self, proto: Option<Proto>, dir: Dir, addr: Ipv4Addr, prefix: u32) -> Node {
+        let l3 = self.l3;
+        let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+        let net = u32::from(addr) & mask;
+        let matches = |offset| {
+            let mut loads = vec![ld(W, offset)];
+            if mask == 0 {
+                return Node::True;
+            }
+            if mask != u32::MAX {
+                loads.push(Instruction::new(ALU | AND | K, mask));
+            }
+            Node::Test(loads, JMP | JEQ | K, net)
+        };
+        let ip = and(
+            self.ipv4(),
+            by_dir(dir, |dir| {
+                matches(l3 + if dir == Dir::Src { 12 } else { 16 })
+            }),
+        );
+        let arp = and(
+            self.arp(),
+            by_dir(dir, |dir| {
+                matches(l3 + if dir == Dir::Src { 14 } else { 24 })
+            }),
+        );
+        match proto {
+            Some(Proto::Ip) => ip,
+            Some(Proto::Arp) => arp,
+            _ => or(ip, arp),
+        }
+    }
+
+    fn primitive(&self, primitive: &Primitive) -> Result<Node> {
+        Ok(match *primitive {
+            Primitive::Proto(Proto::Ether) => {
+                if !self.is_ethernet {
+                    bail!("ether only works on Ethernet links");
+                }
+                Node::True
+            }
+            Primitive::Proto(Proto::Ip) => self.ipv4(),
+            Primitive::Proto(Proto::Ip6) => self.ipv6(),
+            Primitive::Proto(Proto::Arp) => self.arp(),
+            Primitive::Proto(Proto::Tcp) => self.ip_proto(None, 6),
+            Primitive::Proto(Proto::Udp) => self.ip_proto(None, 17),
+            Primitive::Proto(Proto::Icmp) => self.ip_proto(Some(Proto::Ip), 1),
+            Primitive::Proto(Proto::Icmp6) => self.ip_proto(Some(Proto::Ip6), 58),
+            Primitive::Host(proto, dir, host) => self.host(proto, dir, host)?,
+            Primitive::Net(proto, dir, addr, prefix) => self.net(proto, dir, addr, prefix),
+            Primitive::Port(proto, dir, low, high) => match proto {
+                Some(Proto::Tcp) => self.ports(None, 6, dir, low, high),
+                Some(Proto::Udp) => self.ports(None, 17, dir, low, high),
+                family => or(
+                    self.ports(family, 6, dir, low, high),
+                    self.ports(family, 17, dir, low, high),
+                ),
+            },
+            Primitive::EtherProto(x) => self.ethertype(x.into()),
+            Primitive::IpProto(family, x) => self.ip_proto(family, x),
+            Primitive::Len(op, k) => {
+                let loads = vec![Instruction::new(LD | W | LEN, 0)];
+                let test = |code| Node::Test(loads.clone(), JMP | code | K, k);
+                match op {
+                    Relop::Eq => test(JEQ),
+                    Relop::Ne => not(test(JEQ)),
+                    Relop::Gt => test(JGT),
+                    Relop::Ge => test(JGE),
+                    Relop::Lt => not(test(JGE)),
+                    Relop::Le => not(test(JGT)),
+                }
+            }
+        })
+    }
+
+    fn node(&self, expr: &Expr) -> Result<Node> {
+        Ok(match expr {
+            Expr::And(a, b) => and(self.node(a)?, self.node(b)?),
+            Expr::Or(a, b) => or(self.node(a)?, self.node(b)?),
+            Expr::Not(a) => not(self.node(a)?),
+            Expr::Primitive(x) => self.primitive(x)?,
+        })
+    }
+}
+
+/// Lays nodes out as instructions whose jumps point at labels, resolved
+/// once every label has a place.
+#[derive(Default)]
+struct Emitter {
+    code: Vec<(Instruction, Option<(usize, usize)>)>,
+    labels: Vec<usize>,
+}
+
+impl Emitter {
+    fn label(&mut self) -> usize {
+        self.labels.push(usize::MAX);
+        self.labels.len() - 1
+    }
+    fn place(&mut self, label: usize) {
+        self.labels[label] = self.code.len();
+    }
+    fn emit(&mut self, node: &Node, t: usize, f: usize) {
+        match node {
+            Node::True => self
+                .code
+                .push((Instruction::new(JMP | JA, 0), Some((t, t)))),
+            Node::False => self
+                .code
+                .push((Instruction::new(JMP | JA, 0), Some((f, f)))),
+            Node::Test(loads, code, k) => {
+                self.code.extend(loads.iter().map(|x| (*x, None)));
+                self.code.push((Instruction::new(*code, *k), Some((t, f))));
+            }
+            Node::And(a, b) => {
+                let middle = self.label();
+                self.emit(a, middle, f);
+                self.place(middle);
+                self.emit(b, t, f);
+            }
+            Node::Or(a, b) => {
+                let middle = self.label();
+                self.emit(a, t, middle);
+                self.place(middle);
+                self.emit(b, t, f);
+            }
+            Node::Not(a) => self.emit(a, f, t),
+        }
+    }
+    fn finish(self) -> Result<Program> {
+        let mut ans = vec![];
+        for (pc, (mut insn, targets)) in self.code.into_iter().enumerate() {
+            if let Some((t, f)) = targets {
+                let jump = |label: usize| self.labels[label] - pc - 1;
+                if insn.code == JMP | JA {
+                    insn.k = jump(t) as u32;
+                } else {
+                    insn.jt = u8::try_from(jump(t)).context("filter is too long")?;
+                    insn.jf = u8::try_from(jump(f)).context("filter is too long")?;
+                }
+            }
+            ans.push(insn);
+        }
+        Ok(Program::new(ans))
+    }
+}
+
+pub(crate) fn compile(expr: &Expr, link_type: u32) -> Result<Program> {
+    let node = Link::new(link_type)?.node(expr)?;
+    let mut emitter = Emitter::default();
+    let (accept, reject) = (emitter.label(), emitter.label());
+    emitter.emit(&node, accept, reject);
+    emitter.place(accept);
+    emitter.code.push((Instruction::new(RET | K, ACCEPT), None));
+    emitter.place(reject);
+    emitter.code.push((Instruction::new(RET | K, 0), None));
+    emitter.finish()
+}
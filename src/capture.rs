@@ -0,0 +1,83 @@
+use crate::context::Context;
+use crate::packet::{dissect_packet, read_frame, Packet, PacketHeader};
+use crate::pcap::{read_pcap_header_after_magic, PcapHeader};
+use crate::pcapng::{is_pcapng_magic, PcapngReader};
+use anyhow::Result;
+use async_std::io::ReadExt;
+
+/// A captured frame before dissection.
+#[derive(Debug)]
+pub(crate) struct Frame {
+    pub(crate) header: PacketHeader,
+    pub(crate) data: Vec<u8>,
+    /// Where `data` starts in the input.
+    pub(crate) offset: u64,
+}
+
+/// Kernel counters of a live capture.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct CaptureStats {
+    pub received: u64,
+    pub dropped: u64,
+}
+
+impl std::fmt::Display for CaptureStats {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "received {}, dropped {}", self.received, self.dropped)
+    }
+}
+
+#[derive(Debug)]
+pub enum CaptureReader {
+    Pcap { header: PcapHeader, position: u64 },
+    Pcapng(PcapngReader),
+}
+
+impl CaptureReader {
+    pub async fn open(read: &mut (impl ReadExt + Unpin)) -> Result<Self> {
+        let mut magic = [0u8; 4];
+        read.read_exact(&mut magic).await?;
+        if is_pcapng_magic(&magic) {
+            return Ok(Self::Pcapng(PcapngReader::open_after_magic(read).await?));
+        }
+        Ok(Self::Pcap {
+            header: read_pcap_header_after_magic(magic, read).await?,
+            position: 24,
+        })
+    }
+
+    pub async fn read_packet(
+        &mut self,
+        read: &mut (impl ReadExt + Unpin),
+        ctx: &mut Context,
+    ) -> Result<Option<Packet>> {
+        let packet = self
+            .read_frame(read)
+            .await?
+            .map(|x| dissect_packet(x.header, x.data, ctx));
+        Ok(packet)
+    }
+
+    /// Reads the next frame, or returns `None` at the end of the input.
+    pub(crate) async fn read_frame(
+        &mut self,
+        read: &mut (impl ReadExt + Unpin),
+    ) -> Result<Option<Frame>> {
+        match self {
+            Self::Pcap { header, position } => {
+                let (header, data) = match read_frame(read, header).await? {
+                    Some(x) => x,
+                    None => return Ok(None),
+                };
+                let offset = *position + 16;
+                *position = offset + data.len() as u64;
+                Ok(Some(Frame {
+                    header,
+                    data,
+                    offset,
+                }))
+            }
+            Self::Pcapng(reader) => reader.read_frame(read).await,
+        }
+    }
+}
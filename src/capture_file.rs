@@ -0,0 +1,162 @@
+use crate::bpf::CaptureFilter;
+use crate::capture::CaptureReader;
+use crate::context::Context;
+use crate::packet::{dissect_packet, Packet, PacketHeader};
+use anyhow::{Context as _, Result};
+use async_std::fs::File;
+use async_std::io::prelude::SeekExt;
+use async_std::io::{BufReader, ReadExt, SeekFrom};
+use std::path::{Path, PathBuf};
+
+#[derive(Debug)]
+struct Record {
+    header: PacketHeader,
+    offset: u64,
+    len: usize,
+}
+
+/// A capture file with an index of where its frames are, so that they can
+/// be counted before they are dissected. Packets are dissected in order,
+/// since each may be linked to the ones before it.
+#[derive(Debug)]
+pub struct CaptureFile {
+    path: PathBuf,
+    file: File,
+    records: Vec<Record>,
+    // the record read_packet dissects next
+    next: usize,
+    // why indexing stopped before the end of the file
+    error: Option<anyhow::Error>,
+}
+
+impl CaptureFile {
+    /// Opens a pcap or pcapng file and indexes its frames without
+    /// dissecting them.
+    pub async fn open(path: impl AsRef<Path>) -> Result<Self> {
+        Self::open_filtered(path, None).await
+    }
+
+    /// Like `open`, but only frames `filter` matches are indexed. A broken
+    /// record ends the index there and is kept as `error`.
+    pub async fn open_filtered(
+        path: impl AsRef<Path>,
+        mut filter: Option<&mut CaptureFilter>,
+    ) -> Result<Self> {
+        let path = path.as_ref().to_path_buf();
+        let file = File::open(&path)
+            .await
+            .with_context(|| format!("open {}", path.display()))?;
+        let mut read = BufReader::new(file.clone());
+        let mut capture = CaptureReader::open(&mut read).await?;
+        let mut records = vec![];
+        let mut error = None;
+        loop {
+            let frame = match capture.read_frame(&mut read).await {
+                Ok(Some(x)) => x,
+                Ok(None) => break,
+                Err(e) => {
+                    error = Some(e.context(format!("read {}", path.display())));
+                    break;
+                }
+            };
+            if let Some(filter) = filter.as_deref_mut() {
+                if !filter.is_match(&frame.header, &frame.data) {
+                    continue;
+                }
+            }
+            records.push(Record {
+                header: frame.header,
+                offset: frame.offset,
+                len: frame.data.len(),
+            });
+        }
+        Ok(Self {
+            path,
+            file,
+            records,
+            next: 0,
+            error,
+        })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.records.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.records.is_empty()
+    }
+
+    /// Why the file could not be indexed to its end, if it could not.
+    pub fn error(&self) -> Option<&anyhow::Error> {
+        self.error.as_ref()
+    }
+
+    /// Reads and dissects the next packet of the file, or returns `None`
+    /// after the last one. A packet that can't be read is skipped after
+    /// its error is returned.
+    pub async fn read_packet(&mut self, ctx: &mut Context) -> Result<Option<Packet>> {
+        let Some(record) = self.records.get(self.next) else {
+            return Ok(None);
+        };
+        self.next += 1;
+        self.file.seek(SeekFrom::Start(record.offset)).await?;
+        let mut data = vec![0u8; record.len];
+        self.file.read_exact(&mut data).await?;
+        Ok(Some(dissect_packet(record.header.clone(), data, ctx)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_capture_file() {
+        let frame = [
+            0x01u8, 0x00, 0x5e, 0x00, 0x00, 0x01, 0xc4, 0x3c, 0xea, 0x06, 0xdd, 0x00, 0x08, 0x00,
+            0x45, 0x00, 0x00, 0x20, 0xd9, 0xa6, 0x40, 0x00, 0x01, 0x11, 0xe5, 0x4d, 0xc0, 0xa8,
+            0x0b, 0x01, 0xef, 0x00, 0x00, 0x01, 0xeb, 0x70, 0x17, 0x70, 0x00, 0x0c, 0x6c, 0xa2,
+            b't', b'i', b'm', b'e',
+        ];
+        let mut bytes = vec![
+            0xd4, 0xc3, 0xb2, 0xa1, 0x02, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0xff, 0xff, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+        ];
+        for seconds in [1u32, 2, 3] {
+            bytes.extend_from_slice(&seconds.to_le_bytes());
+            bytes.extend_from_slice(&0u32.to_le_bytes());
+            bytes.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&frame);
+        }
+        let path = std::env::temp_dir().join(format!("wirewhale-{}.pcap", std::process::id()));
+        std::fs::write(&path, &bytes).unwrap();
+        async_std::task::block_on(async {
+            let mut file = CaptureFile::open(&path).await.unwrap();
+            assert_eq!(file.len(), 3);
+            let mut ctx = Context::default();
+            for number in 1..=3 {
+                let packet = file.read_packet(&mut ctx).await.unwrap().unwrap();
+                assert_eq!(packet.number(), number);
+                assert_eq!(packet.data(), frame);
+                assert!(packet.field("udp").is_some());
+            }
+            assert!(file.read_packet(&mut ctx).await.unwrap().is_none());
+            assert!(file.error().is_none());
+        });
+        // a record cut off by the end of the file
+        bytes.extend_from_slice(&[0; 5]);
+        std::fs::write(&path, &bytes).unwrap();
+        async_std::task::block_on(async {
+            let file = CaptureFile::open(&path).await.unwrap();
+            assert_eq!(file.len(), 3);
+            assert!(file.error().is_some());
+        });
+        std::fs::remove_file(&path).unwrap();
+    }
+}
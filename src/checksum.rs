@@ -0,0 +1,142 @@
+use crate::context::Context;
+use crate::field::Field;
+use std::net::IpAddr;
+
+/// The ones' complement sum of RFC 1071 over 16 bit words. Only the last
+/// part added may have an odd length.
+#[derive(Debug, Default)]
+pub(crate) struct Checksum(u32);
+
+impl Checksum {
+    pub(crate) fn add(&mut self, data: &[u8]) {
+        for word in data.chunks(2) {
+            let word = match word {
+                [high, low] => u16::from_be_bytes([*high, *low]),
+                [high] => u16::from_be_bytes([*high, 0]),
+                _ => unreachable!(),
+            };
+            self.0 += word as u32;
+            self.0 = (self.0 & 0xffff) + (self.0 >> 16);
+        }
+    }
+    /// The value the checksum field should hold, when it was left out of
+    /// the sum.
+    pub(crate) fn finish(&self) -> u16 {
+        !(self.0 as u16)
+    }
+}
+
+/// Sums the pseudo header of the packet being dissected, or returns `None`
+/// when its transport checksum can't or shouldn't be verified.
+pub(crate) fn pseudo_header(ctx: &Context, protocol: u8, len: usize) -> Option<Checksum> {
+    // fragments and cut packets lack part of the segment
+    if !ctx.registry().verify_checksums() || ctx.truncated || ctx.fragmented {
+        return None;
+    }
+    let mut sum = Checksum::default();
+    match (ctx.src?, ctx.dst?) {
+        (IpAddr::V4(src), IpAddr::V4(dst)) => {
+            sum.add(&src.octets());
+            sum.add(&dst.octets());
+            sum.add(&[0, protocol]);
+            sum.add(&u16::try_from(len).ok()?.to_be_bytes());
+        }
+        (IpAddr::V6(src), IpAddr::V6(dst)) => {
+            sum.add(&src.octets());
+            sum.add(&dst.octets());
+            sum.add(&u32::try_from(len).ok()?.to_be_bytes());
+            sum.add(&[0, 0, 0, protocol]);
+        }
+        _ => return None,
+    }
+    Some(sum)
+}
+
+/// A checksum read at `offset`, with whether it matches `expected` when
+/// it was verified.
+pub(crate) fn checksum_field(
+    name: &'static str,
+    status_name: &'static str,
+    value: u16,
+    expected: Option<u16>,
+    offset: usize,
+) -> Field {
+    let field = Field::new(name, value).at(offset, 2);
+    let (note, status) = match expected {
+        None => return field,
+        Some(x) if x == value => ("correct".to_string(), "good"),
+        Some(x) => (format!("incorrect (expected {:#06x})", x), "bad"),
+    };
+    field
+        .with_note(note)
+        .with_children(vec![Field::new(status_name, status).at(offset, 2)])
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::packet::{dissect_packet, Packet, PacketHeader};
+    use crate::registry::Registry;
+    use std::sync::{Arc, RwLock};
+
+    fn dissect(frame: &[u8], verify: bool) -> Packet {
+        let mut registry = Registry::default();
+        registry.set_verify_checksums(verify);
+        let mut ctx = Context::new(Arc::new(RwLock::new(registry)));
+        let len = frame.len() as u32;
+        let header = PacketHeader::new(0, 0, len, len, 1).unwrap();
+        dissect_packet(header, frame.to_vec(), &mut ctx)
+    }
+
+    #[test]
+    fn test_checksum() {
+        // the example of RFC 1071
+        let mut sum = Checksum::default();
+        sum.add(&[0x00, 0x01, 0xf2, 0x03, 0xf4, 0xf5, 0xf6, 0xf7]);
+        assert_eq!(sum.finish(), !0xddf2);
+        let field = checksum_field(
+            "ip.checksum",
+            "ip.checksum.status",
+            0x1234,
+            Some(0x1235),
+            10,
+        );
+        assert_eq!(
+            field.label(),
+            "Header Checksum: 0x1234 [incorrect (expected 0x1235)]"
+        );
+        assert_eq!(field.children()[0].value().to_string(), "bad");
+    }
+
+    #[test]
+    fn test_verify() {
+        // the dns query over ethernet, ipv4 and udp of the fuzzing corpus
+        let file = include_bytes!("../fuzz/corpus/read_packet/ethernet.pcap");
+        let mut frame = file[40..115].to_vec();
+        let status = |packet: &Packet, name| packet.field(name).map(|x| x.value().to_string());
+        let packet = dissect(&frame, true);
+        assert_eq!(status(&packet, "ip.checksum.status").unwrap(), "good");
+        assert_eq!(status(&packet, "udp.checksum.status").unwrap(), "good");
+        assert!(packet
+            .field("ip.checksum")
+            .unwrap()
+            .label()
+            .ends_with("[correct]"));
+        // a changed ttl breaks the ip checksum only
+        frame[22] -= 1;
+        let packet = dissect(&frame, true);
+        assert_eq!(status(&packet, "ip.checksum.status").unwrap(), "bad");
+        assert_eq!(status(&packet, "udp.checksum.status").unwrap(), "good");
+        assert_eq!(
+            packet.field("ip.checksum").unwrap().label(),
+            "Header Checksum: 0x26ad [incorrect (expected 0x27ad)]"
+        );
+        // a changed payload breaks the udp checksum
+        frame[60] ^= 1;
+        let packet = dissect(&frame, true);
+        assert_eq!(status(&packet, "udp.checksum.status").unwrap(), "bad");
+        let packet = dissect(&frame, false);
+        assert!(packet.field("ip.checksum.status").is_none());
+        assert!(packet.field("udp.checksum.status").is_none());
+    }
+}
@@ -0,0 +1,226 @@
+use crate::l3data::arp::ArpTable;
+use crate::l3data::ipv4data::icmp::EchoTable;
+use crate::l3data::reassembly::FragmentTable;
+use crate::l7data::dns::DnsTable;
+use crate::registry::{Registry, Transport};
+use anyhow::Result;
+use chrono::{DateTime, Duration, Local};
+use std::collections::{HashMap, VecDeque};
+use std::hash::Hash;
+use std::io::ErrorKind;
+use std::net::IpAddr;
+use std::sync::{Arc, RwLock, RwLockReadGuard};
+
+/// State carried across packets while dissecting a capture in order.
+#[derive(Debug, Default)]
+pub struct Context {
+    number: usize,
+    timestamp: DateTime<Local>,
+    // network layer addresses of the packet being dissected
+    pub(crate) src: Option<IpAddr>,
+    pub(crate) dst: Option<IpAddr>,
+    // transport layer of the packet being dissected
+    pub(crate) transport: Option<Transport>,
+    pub(crate) src_port: Option<u16>,
+    pub(crate) dst_port: Option<u16>,
+    // whether the packet being dissected was cut short by the snaplen
+    pub(crate) truncated: bool,
+    // whether the packet being dissected is an IP fragment
+    pub(crate) fragmented: bool,
+    // whether a later packet may still add fields to the one being
+    // dissected, like the frame that reassembles a fragment
+    pub(crate) pending: bool,
+    pub(crate) arp: ArpTable,
+    pub(crate) echo: EchoTable,
+    pub(crate) dns: DnsTable,
+    pub(crate) fragments: FragmentTable,
+    // names the capture file resolved addresses to
+    pub(crate) names: Arc<HashMap<IpAddr, String>>,
+    registry: Arc<RwLock<Registry>>,
+}
+
+impl Context {
+    /// Dissects with `registry`, which may be changed while reading.
+    pub fn new(registry: Arc<RwLock<Registry>>) -> Self {
+        Self {
+            registry,
+            ..Default::default()
+        }
+    }
+    /// Forgets what links packets to earlier ones before the next of several
+    /// files, whose packets are numbered on from the last one.
+    pub(crate) fn next_file(&mut self) {
+        self.arp = ArpTable::default();
+        self.echo = EchoTable::default();
+        self.dns = DnsTable::default();
+        self.fragments = FragmentTable::default();
+    }
+    /// Starts dissecting the next packet and returns its 1-based number.
+    pub(crate) fn begin(&mut self, timestamp: DateTime<Local>) -> usize {
+        self.number += 1;
+        self.timestamp = timestamp;
+        self.src = None;
+        self.dst = None;
+        self.transport = None;
+        self.src_port = None;
+        self.dst_port = None;
+        self.fragmented = false;
+        self.pending = false;
+        self.number
+    }
+    pub fn number(&self) -> usize {
+        self.number
+    }
+    pub fn timestamp(&self) -> DateTime<Local> {
+        self.timestamp
+    }
+    pub fn src(&self) -> Option<IpAddr> {
+        self.src
+    }
+    pub fn dst(&self) -> Option<IpAddr> {
+        self.dst
+    }
+    pub fn transport(&self) -> Option<Transport> {
+        self.transport
+    }
+    pub fn src_port(&self) -> Option<u16> {
+        self.src_port
+    }
+    pub fn dst_port(&self) -> Option<u16> {
+        self.dst_port
+    }
+    /// The name the capture file resolved `addr` to, if any.
+    pub(crate) fn host(&self, addr: IpAddr) -> Option<String> {
+        self.names.get(&addr).cloned()
+    }
+    pub(crate) fn registry(&self) -> RwLockReadGuard<'_, Registry> {
+        self.registry.read().unwrap()
+    }
+    /// Dissects `read`, or keeps its raw bytes with `truncated` when the
+    /// packet was cut short by the snaplen before the dissector was done.
+    pub(crate) fn or_truncated<T>(
+        &mut self,
+        read: VecDeque<u8>,
+        dissect: impl FnOnce(VecDeque<u8>, &mut Self) -> Result<T>,
+        truncated: impl FnOnce(VecDeque<u8>) -> T,
+    ) -> Result<T> {
+        let copy = self.truncated.then(|| read.clone());
+        match (dissect(read, self), copy) {
+            (Err(e), Some(copy)) if is_out_of_bytes(&e) => Ok(truncated(copy)),
+            (result, _) => result,
+        }
+    }
+}
+
+fn is_out_of_bytes(e: &anyhow::Error) -> bool {
+    e.chain()
+        .filter_map(|x| x.downcast_ref::<std::io::Error>())
+        .any(|x| x.kind() == ErrorKind::UnexpectedEof)
+}
+
+/// Formats the time between two linked packets in milliseconds, e.g.
+/// "1.500ms". A reply captured before its request comes out negative.
+pub(crate) fn fmt_latency(latency: Duration) -> String {
+    let micros = latency.num_microseconds().unwrap_or(i64::MAX);
+    let sign = if micros < 0 { "-" } else { "" };
+    let micros = micros.unsigned_abs();
+    format!("{}{}.{:03}ms", sign, micros / 1000, micros % 1000)
+}
+
+/// How long a request waits for its response before it is forgotten.
+const REQUEST_TIMEOUT_SECS: i64 = 60;
+/// How many requests may wait at once, so that requests which are never
+/// answered can't exhaust memory. The oldest go first.
+const MAX_REQUESTS: usize = 4096;
+
+/// Requests waiting for their response, with the frame and time each was
+/// seen in.
+#[derive(Debug)]
+pub(crate) struct RequestTable<K> {
+    requests: HashMap<K, (usize, DateTime<Local>)>,
+}
+
+impl<K> Default for RequestTable<K> {
+    fn default() -> Self {
+        Self {
+            requests: HashMap::new(),
+        }
+    }
+}
+
+impl<K: Copy + Eq + Hash> RequestTable<K> {
+    pub(crate) fn insert(&mut self, key: K, frame: usize, timestamp: DateTime<Local>) {
+        let timeout = Duration::seconds(REQUEST_TIMEOUT_SECS);
+        self.requests.retain(|_, (_, x)| timestamp - *x <= timeout);
+        if self.requests.len() >= MAX_REQUESTS && !self.requests.contains_key(&key) {
+            let oldest = self
+                .requests
+                .iter()
+                .min_by_key(|(_, (_, x))| *x)
+                .map(|(key, _)| *key);
+            if let Some(x) = oldest {
+                self.requests.remove(&x);
+            }
+        }
+        self.requests.insert(key, (frame, timestamp));
+    }
+    /// Removes the request a response answers, only the first response is
+    /// linked to it.
+    pub(crate) fn take(&mut self, key: &K) -> Option<(usize, DateTime<Local>)> {
+        self.requests.remove(key)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use anyhow::{bail, Context as _};
+    use byteorder::{NetworkEndian, ReadBytesExt};
+
+    #[test]
+    fn test_fmt_latency() {
+        assert_eq!(fmt_latency(Duration::microseconds(1500)), "1.500ms");
+        assert_eq!(fmt_latency(Duration::microseconds(-1500)), "-1.500ms");
+        assert_eq!(fmt_latency(Duration::microseconds(-500)), "-0.500ms");
+        assert_eq!(fmt_latency(Duration::zero()), "0.000ms");
+    }
+
+    #[test]
+    fn test_or_truncated() {
+        let mut ctx = Context::default();
+        let eof = |mut read: VecDeque<u8>, _: &mut Context| {
+            read.read_u16::<NetworkEndian>().context("read")
+        };
+        let invalid = |_, _: &mut Context| -> Result<u16> { bail!("invalid") };
+        let raw = |read: VecDeque<u8>| read.len() as u16;
+        assert!(ctx.or_truncated(vec![1].into(), eof, raw).is_err());
+        ctx.truncated = true;
+        assert_eq!(ctx.or_truncated(vec![1].into(), eof, raw).unwrap(), 1);
+        assert_eq!(
+            ctx.or_truncated(vec![1, 2].into(), eof, raw).unwrap(),
+            0x102
+        );
+        assert!(ctx.or_truncated(vec![1].into(), invalid, raw).is_err());
+    }
+
+    #[test]
+    fn test_request_table() {
+        use chrono::TimeZone;
+        let time = |secs| Local.timestamp_opt(secs, 0).unwrap();
+        let mut table = RequestTable::default();
+        table.insert(1, 1, time(0));
+        assert_eq!(table.take(&1), Some((1, time(0))));
+        assert_eq!(table.take(&1), None);
+        // unanswered requests expire
+        table.insert(2, 2, time(0));
+        table.insert(3, 3, time(REQUEST_TIMEOUT_SECS + 1));
+        assert_eq!(table.take(&2), None);
+        // and the oldest make room for new ones
+        for i in 0..MAX_REQUESTS {
+            table.insert(4 + i, 4 + i, time(REQUEST_TIMEOUT_SECS + 2));
+        }
+        assert_eq!(table.requests.len(), MAX_REQUESTS);
+        assert_eq!(table.take(&3), None);
+        assert!(table.take(&4).is_some());
+    }
+}
@@ -0,0 +1,431 @@
+use crate::field::{Field, FieldType, FieldValue};
+use crate::registry::Registry;
+use anyhow::{bail, Context as _, Result};
+use regex::Regex;
+use std::cmp::Ordering;
+use std::net::IpAddr;
+
+use FieldType::*;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Op {
+    Eq,
+    Ne,
+    Gt,
+    Ge,
+    Lt,
+    Le,
+    Contains,
+    Matches,
+}
+
+impl std::fmt::Display for Op {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Op::Eq => "==",
+            Op::Ne => "!=",
+            Op::Gt => ">",
+            Op::Ge => ">=",
+            Op::Lt => "<",
+            Op::Le => "<=",
+            Op::Contains => "contains",
+            Op::Matches => "matches",
+        };
+        write!(f, "\"{}\"", s)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum Token {
+    LParen,
+    RParen,
+    Not,
+    And,
+    Or,
+    Op(Op),
+    Word(String),
+    Quoted(String),
+}
+
+impl std::fmt::Display for Token {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Token::LParen => write!(f, "\"(\""),
+            Token::RParen => write!(f, "\")\""),
+            Token::Not => write!(f, "\"!\""),
+            Token::And => write!(f, "\"&&\""),
+            Token::Or => write!(f, "\"||\""),
+            Token::Op(x) => write!(f, "{}", x),
+            Token::Word(x) => write!(f, "\"{}\"", x),
+            Token::Quoted(x) => write!(f, "{:?}", x),
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Value {
+    Int(u64),
+    // an address with a prefix length, full length for plain addresses
+    Net(IpAddr, u32),
+    Mac([u8; 6]),
+    Bytes(Vec<u8>),
+    Str(String),
+    Regex(Regex),
+}
+
+#[derive(Debug, Clone)]
+enum Expr {
+    Exists(String),
+    Compare(String, Op, Value),
+    Not(Box<Expr>),
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+}
+
+/// A compiled Wireshark style display filter, e.g.
+/// `ip.src == 10.0.0.0/8 && (udp.dstport == 53 || tcp)`.
+#[derive(Debug, Clone, Default)]
+pub struct DisplayFilter {
+    // an empty filter matches everything
+    expr: Option<Expr>,
+}
+
+fn is_word_char(c: char) -> bool {
+    !c.is_whitespace() && !"()!=<>&|\"".contains(c)
+}
+
+fn tokenize(s: &str) -> Result<Vec<Token>> {
+    let mut tokens = vec![];
+    let mut chars = s.chars().peekable();
+    while let Some(c) = chars.next() {
+        let token = match c {
+            c if c.is_whitespace() => continue,
+            '(' => Token::LParen,
+            ')' => Token::RParen,
+            '!' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ne),
+            '!' => Token::Not,
+            '=' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Eq),
+            '>' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Ge),
+            '>' => Token::Op(Op::Gt),
+            '<' if chars.next_if_eq(&'=').is_some() => Token::Op(Op::Le),
+            '<' => Token::Op(Op::Lt),
+            '&' if chars.next_if_eq(&'&').is_some() => Token::And,
+            '|' if chars.next_if_eq(&'|').is_some() => Token::Or,
+            '"' => {
+                let mut quoted = String::new();
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => quoted.push(chars.next().context("unterminated string")?),
+                        Some(x) => quoted.push(x),
+                        None => bail!("unterminated string"),
+                    }
+                }
+                Token::Quoted(quoted)
+            }
+            c if is_word_char(c) => {
+                let mut word = c.to_string();
+                while let Some(x) = chars.next_if(|x| is_word_char(*x)) {
+                    word.push(x);
+                }
+                match word.as_str() {
+                    "and" => Token::And,
+                    "or" => Token::Or,
+                    "not" => Token::Not,
+                    "eq" => Token::Op(Op::Eq),
+                    "ne" => Token::Op(Op::Ne),
+                    "gt" => Token::Op(Op::Gt),
+                    "ge" => Token::Op(Op::Ge),
+                    "lt" => Token::Op(Op::Lt),
+                    "le" => Token::Op(Op::Le),
+                    "contains" => Token::Op(Op::Contains),
+                    "matches" => Token::Op(Op::Matches),
+                    _ => Token::Word(word),
+                }
+            }
+            c => bail!("unexpected \"{}\"", c),
+        };
+        tokens.push(token);
+    }
+    Ok(tokens)
+}
+
+fn parse_int(s: &str) -> Result<u64> {
+    match s.strip_prefix("0x") {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    }
+    .with_context(|| format!("\"{}\" is not a valid number", s))
+}
+
+fn parse_net(s: &str) -> Result<Value> {
+    let (addr, prefix) = match s.split_once('/') {
+        Some((addr, prefix)) => (addr, Some(prefix)),
+        None => (s, None),
+    };
+    let addr: IpAddr = addr
+        .parse()
+        .with_context(|| format!("\"{}\" is not a valid address", addr))?;
+    let max = if addr.is_ipv4() { 32 } else { 128 };
+    let prefix = match prefix {
+        Some(x) => x
+            .parse()
+            .ok()
+            .filter(|x| *x <= max)
+            .with_context(|| format!("\"{}\" is not a valid prefix length", x))?,
+        None => max,
+    };
+    Ok(Value::Net(addr, prefix))
+}
+
+fn parse_bytes(s: &str) -> Result<Vec<u8>> {
+    s.split([':', '-', '.'])
+        .map(|x| u8::from_str_radix(x, 16).ok().filter(|_| x.len() == 2))
+        .collect::<Option<Vec<u8>>>()
+        .with_context(|| format!("\"{}\" is not a valid byte string", s))
+}
+
+fn parse_value(name: &str, ty: FieldType, op: Op, token: Token) -> Result<Value> {
+    let (text, quoted) = match token {
+        Token::Word(x) => (x, false),
+        Token::Quoted(x) => (x, true),
+        x => bail!("expected a value after {} but got {}", op, x),
+    };
+    let value = match (ty, op) {
+        (Protocol, _) => bail!("{} can only be tested for presence", name),
+        (Str | Bytes, Op::Matches) => Value::Regex(Regex::new(&text)?),
+        (_, Op::Matches) => bail!("{} cannot be used with matches", name),
+        (Str, _) => Value::Str(text),
+        (Bytes, _) if quoted => Value::Bytes(text.into_bytes()),
+        (Bytes, _) => Value::Bytes(parse_bytes(&text)?),
+        (_, Op::Contains) => bail!("{} cannot be used with contains", name),
+        (Int, _) => Value::Int(parse_int(&text)?),
+        (Addr, _) => parse_net(&text)?,
+        (Mac, Op::Eq | Op::Ne) => {
+            let bytes = parse_bytes(&text)?;
+            Value::Mac(
+                bytes
+                    .try_into()
+                    .ok()
+                    .with_context(|| format!("\"{}\" is not a valid mac address", text))?,
+            )
+        }
+        (Mac, _) => bail!("{} can only be compared with == or !=", name),
+    };
+    Ok(value)
+}
+
+struct Parser<'a> {
+    tokens: Vec<Token>,
+    position: usize,
+    registry: &'a Registry,
+}
+
+impl Parser<'_> {
+    fn peek(&self) -> Option<&Token> {
+        self.tokens.get(self.position)
+    }
+    fn next(&mut self) -> Option<Token> {
+        let token = self.tokens.get(self.position).cloned();
+        self.position += 1;
+        token
+    }
+    fn parse_or(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_and()?;
+        while self.peek() == Some(&Token::Or) {
+            self.next();
+            expr = Expr::Or(Box::new(expr), Box::new(self.parse_and()?));
+        }
+        Ok(expr)
+    }
+    fn parse_and(&mut self) -> Result<Expr> {
+        let mut expr = self.parse_not()?;
+        while self.peek() == Some(&Token::And) {
+            self.next();
+            expr = Expr::And(Box::new(expr), Box::new(self.parse_not()?));
+        }
+        Ok(expr)
+    }
+    fn parse_not(&mut self) -> Result<Expr> {
+        if self.peek() == Some(&Token::Not) {
+            self.next();
+            return Ok(Expr::Not(Box::new(self.parse_not()?)));
+        }
+        self.parse_primary()
+    }
+    fn parse_primary(&mut self) -> Result<Expr> {
+        let name = match self.next() {
+            Some(Token::LParen) => {
+                let expr = self.parse_or()?;
+                if self.next() != Some(Token::RParen) {
+                    bail!("missing \")\"");
+                }
+                return Ok(expr);
+            }
+            Some(Token::Word(x)) => x,
+            Some(x) => bail!("unexpected {}", x),
+            None => bail!("unexpected end of filter"),
+        };
+        let ty = self
+            .registry
+            .field(&name)
+            .with_context(|| format!("\"{}\" is not a valid field", name))?
+            .ty;
+        let op = match self.peek() {
+            Some(Token::Op(x)) => *x,
+            _ => return Ok(Expr::Exists(name)),
+        };
+        self.next();
+        let token = self.next().context("unexpected end of filter")?;
+        if op == Op::Ne {
+            // like Wireshark's !==, none of the occurrences may be equal
+            let value = parse_value(&name, ty, Op::Eq, token)?;
+            return Ok(Expr::Not(Box::new(Expr::Compare(name, Op::Eq, value))));
+        }
+        let value = parse_value(&name, ty, op, token)?;
+        Ok(Expr::Compare(name, op, value))
+    }
+}
+
+fn is_ordered(op: Op, ordering: Ordering) -> bool {
+    match op {
+        Op::Eq => ordering == Ordering::Equal,
+        Op::Ne => ordering != Ordering::Equal,
+        Op::Gt => ordering == Ordering::Greater,
+        Op::Ge => ordering != Ordering::Less,
+        Op::Lt => ordering == Ordering::Less,
+        Op::Le => ordering != Ordering::Greater,
+        Op::Contains | Op::Matches => false,
+    }
+}
+
+fn in_network(addr: &IpAddr, network: &IpAddr, prefix: u32) -> bool {
+    match (addr, network) {
+        (IpAddr::V4(x), IpAddr::V4(y)) => {
+            let mask = u32::MAX.checked_shl(32 - prefix).unwrap_or(0);
+            u32::from(*x) & mask == u32::from(*y) & mask
+        }
+        (IpAddr::V6(x), IpAddr::V6(y)) => {
+            let mask = u128::MAX.checked_shl(128 - prefix).unwrap_or(0);
+            u128::from(*x) & mask == u128::from(*y) & mask
+        }
+        _ => false,
+    }
+}
+
+fn compare(field: &FieldValue, op: Op, value: &Value) -> bool {
+    match (field, value) {
+        (FieldValue::Int(x), Value::Int(y)) => is_ordered(op, x.cmp(y)),
+        (FieldValue::Addr(x), Value::Net(y, prefix)) => match op {
+            Op::Eq => in_network(x, y, *prefix),
+            _ => is_ordered(op, x.cmp(y)),
+        },
+        (FieldValue::Mac(x), Value::Mac(y)) => is_ordered(op, x.cmp(y)),
+        (FieldValue::Bytes(x), Value::Bytes(y)) if op == Op::Contains => {
+            y.is_empty() || x.windows(y.len()).any(|w| w == y.as_slice())
+        }
+        (FieldValue::Bytes(x), Value::Bytes(y)) => is_ordered(op, x.cmp(y)),
+        (FieldValue::Bytes(x), Value::Regex(y)) => y.is_match(&String::from_utf8_lossy(x)),
+        (FieldValue::Str(x), Value::Str(y)) if op == Op::Contains => x.contains(y.as_str()),
+        (FieldValue::Str(x), Value::Str(y)) => is_ordered(op, x.cmp(y)),
+        (FieldValue::Str(x), Value::Regex(y)) => y.is_match(x),
+        _ => false,
+    }
+}
+
+impl Expr {
+    fn is_match(&self, fields: &[&Field]) -> bool {
+        match self {
+            Expr::Exists(name) => fields.iter().any(|x| x.name() == name),
+            Expr::Compare(name, op, value) => fields
+                .iter()
+                .filter(|x| x.name() == name)
+                .any(|x| compare(x.value(), *op, value)),
+            Expr::Not(x) => !x.is_match(fields),
+            Expr::And(x, y) => x.is_match(fields) && y.is_match(fields),
+            Expr::Or(x, y) => x.is_match(fields) || y.is_match(fields),
+        }
+    }
+}
+
+impl DisplayFilter {
+    /// Parses a filter on the fields of the built in dissectors.
+    pub fn parse(s: &str) -> Result<Self> {
+        Self::parse_with(s, &Registry::default())
+    }
+    /// Parses a filter that may also test the fields of the dissectors
+    /// plugged into `registry`.
+    pub fn parse_with(s: &str, registry: &Registry) -> Result<Self> {
+        let tokens = tokenize(s)?;
+        if tokens.is_empty() {
+            return Ok(Self { expr: None });
+        }
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+            registry,
+        };
+        let expr = parser.parse_or()?;
+        if let Some(x) = parser.peek() {
+            bail!("unexpected {}", x);
+        }
+        Ok(Self { expr: Some(expr) })
+    }
+    pub fn is_match(&self, fields: &[Field]) -> bool {
+        let fields: Vec<&Field> = fields.iter().flat_map(|x| x.iter()).collect();
+        match &self.expr {
+            Some(x) => x.is_match(&fields),
+            None => true,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use std::net::Ipv4Addr;
+
+    #[test]
+    fn test_display_filter() {
+        let fields = vec![
+            Field::new("frame.time", "2024-01-02T03:04:05.000000000+09:00"),
+            Field::new("frame.len", 60u32),
+            Field::protocol("ip"),
+            Field::new("ip.src", Ipv4Addr::new(10, 1, 2, 3)),
+            Field::new("ip.addr", Ipv4Addr::new(10, 1, 2, 3)),
+            Field::new("ip.addr", Ipv4Addr::new(192, 168, 0, 1)),
+            Field::protocol("udp"),
+            Field::new("udp.dstport", 53u16),
+            Field::new("udp.payload", &b"\x12\x34example"[..]),
+        ];
+        let is_match = |s: &str| DisplayFilter::parse(s).unwrap().is_match(&fields);
+        assert!(is_match(""));
+        assert!(is_match("udp"));
+        assert!(!is_match("tcp"));
+        assert!(is_match("ip.src == 10.0.0.0/8 && udp.dstport == 53"));
+        assert!(is_match(
+            "ip.addr == 192.168.0.1 and not ip.src eq 192.168.0.1"
+        ));
+        assert!(!is_match("ip.addr != 192.168.0.1"));
+        assert!(is_match("frame.len > 50 && (tcp || udp.dstport <= 0x35)"));
+        assert!(!is_match("!(frame.len >= 60)"));
+        assert!(is_match("udp.payload contains \"ample\""));
+        assert!(is_match("udp.payload contains 12:34"));
+        assert!(is_match("udp.payload matches \"ex.mple$\""));
+        assert!(is_match("frame.time contains \"2024\""));
+        assert!(!is_match("frame.time contains \"2017\""));
+        for invalid in [
+            "ip.src ==",
+            "ip.scr == 10.0.0.1",
+            "ip.src == 10.0.0.1/33",
+            "(udp",
+            "udp.dstport == dns",
+            "tcp == 1",
+            "udp.dstport contains 1",
+            "udp = 1",
+            "udp udp",
+        ] {
+            assert!(DisplayFilter::parse(invalid).is_err(), "{}", invalid);
+        }
+    }
+}
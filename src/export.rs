@@ -0,0 +1,488 @@
+use crate::field::{Field, FieldValue};
+use crate::packet::Packet;
+use anyhow::{bail, Context as _, Result};
+use byteorder::{LittleEndian, WriteBytesExt};
+use std::io::Write;
+use std::path::Path;
+use std::str::FromStr;
+
+const SNAPLEN: u32 = 262144;
+
+/// File formats packets can be written in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Pcap,
+    Pcapng,
+}
+
+impl Format {
+    /// pcapng for ".pcapng" files, pcap otherwise.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|x| x.to_str()) {
+            Some(x) if x.eq_ignore_ascii_case("pcapng") || x.eq_ignore_ascii_case("ntar") => {
+                Format::Pcapng
+            }
+            _ => Format::Pcap,
+        }
+    }
+}
+
+/// Which packets of the list to export.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Selection {
+    All,
+    /// The packets matching the display filter.
+    Displayed,
+    Marked,
+    /// Frame numbers, both ends included.
+    Range(usize, usize),
+}
+
+impl FromStr for Selection {
+    type Err = anyhow::Error;
+    /// "all", "displayed", "marked", "10-20" or "10".
+    fn from_str(s: &str) -> Result<Self> {
+        let number = |x: &str| -> Result<usize> {
+            x.trim()
+                .parse()
+                .with_context(|| format!("invalid frame number {:?}", x))
+        };
+        Ok(match s {
+            "all" => Selection::All,
+            "displayed" => Selection::Displayed,
+            "marked" => Selection::Marked,
+            x => match x.split_once('-') {
+                Some((first, last)) => Selection::Range(number(first)?, number(last)?),
+                None => Selection::Range(number(x)?, number(x)?),
+            },
+        })
+    }
+}
+
+/// Writes packets as they were captured, with their timestamps, lengths
+/// and link types.
+pub struct PacketWriter<W: Write> {
+    write: W,
+    format: Format,
+    // pcap has one link type per file, known from the first packet
+    link_type: Option<u32>,
+    // pcapng interfaces written so far
+    interfaces: Vec<(u32, Option<u32>, Option<String>)>,
+}
+
+fn pad4(len: usize) -> usize {
+    (4 - len % 4) % 4
+}
+
+impl<W: Write> PacketWriter<W> {
+    pub fn new(mut write: W, format: Format) -> Result<Self> {
+        if format == Format::Pcapng {
+            // section header block, of unknown length
+            write.write_u32::<LittleEndian>(0x0a0d0d0a)?;
+            write.write_u32::<LittleEndian>(28)?;
+            write.write_u32::<LittleEndian>(0x1a2b3c4d)?;
+            write.write_u16::<LittleEndian>(1)?;
+            write.write_u16::<LittleEndian>(0)?;
+            write.write_i64::<LittleEndian>(-1)?;
+            write.write_u32::<LittleEndian>(28)?;
+        }
+        Ok(Self {
+            write,
+            format,
+            link_type: None,
+            interfaces: vec![],
+        })
+    }
+
+    fn write_pcap_header(&mut self, link_type: u32) -> Result<()> {
+        // nanosecond timestamps, so none are rounded
+        self.write.write_u32::<LittleEndian>(0xa1b23c4d)?;
+        self.write.write_u16::<LittleEndian>(2)?;
+        self.write.write_u16::<LittleEndian>(4)?;
+        self.write.write_u32::<LittleEndian>(0)?;
+        self.write.write_u32::<LittleEndian>(0)?;
+        self.write.write_u32::<LittleEndian>(SNAPLEN)?;
+        self.write.write_u32::<LittleEndian>(link_type)?;
+        self.link_type = Some(link_type);
+        Ok(())
+    }
+
+    // index of the interface the packet was captured on, described first
+    // if it is new
+    fn interface(&mut self, packet: &Packet) -> Result<u32> {
+        let header = packet.header();
+        let key = (
+            header.link_type(),
+            header.interface_id(),
+            header.interface_name().map(|x| x.to_string()),
+        );
+        if let Some(i) = self.interfaces.iter().position(|x| *x == key) {
+            return Ok(i as u32);
+        }
+        let link_type = u16::try_from(key.0).context("link type out of range for pcapng")?;
+        let mut options = vec![];
+        if let Some(name) = &key.2 {
+            options.write_u16::<LittleEndian>(2)?;
+            options.write_u16::<LittleEndian>(name.len() as u16)?;
+            options.extend_from_slice(name.as_bytes());
+            options.resize(options.len() + pad4(name.len()), 0);
+        }
+        // if_tsresol, nanoseconds
+        options.extend_from_slice(&[9, 0, 1, 0, 9, 0, 0, 0]);
+        options.extend_from_slice(&[0, 0, 0, 0]);
+        let len = 20 + options.len() as u32;
+        self.write.write_u32::<LittleEndian>(0x00000001)?;
+        self.write.write_u32::<LittleEndian>(len)?;
+        self.write.write_u16::<LittleEndian>(link_type)?;
+        self.write.write_u16::<LittleEndian>(0)?;
+        self.write.write_u32::<LittleEndian>(SNAPLEN)?;
+        self.write.write_all(&options)?;
+        self.write.write_u32::<LittleEndian>(len)?;
+        self.interfaces.push(key);
+        Ok(self.interfaces.len() as u32 - 1)
+    }
+
+    pub fn write_packet(&mut self, packet: &Packet) -> Result<()> {
+        let header = packet.header();
+        let data = packet.data();
+        let timestamp = header.timestamp();
+        match self.format {
+            Format::Pcap => {
+                match self.link_type {
+                    None => self.write_pcap_header(header.link_type())?,
+                    Some(x) if x != header.link_type() => bail!(
+                        "pcap holds a single link type, frame {} is {} rather than {}, use pcapng",
+                        packet.number(),
+                        header.link_type(),
+                        x
+                    ),
+                    Some(_) => {}
+                }
+                let secs =
+                    u32::try_from(timestamp.timestamp()).context("timestamp out of range")?;
+                self.write.write_u32::<LittleEndian>(secs)?;
+                self.write
+                    .write_u32::<LittleEndian>(timestamp.timestamp_subsec_nanos())?;
+                self.write.write_u32::<LittleEndian>(data.len() as u32)?;
+                self.write.write_u32::<LittleEndian>(header.len())?;
+                self.write.write_all(data)?;
+            }
+            Format::Pcapng => {
+                let interface = self.interface(packet)?;
+                let nanos = u64::try_from(timestamp.timestamp())
+                    .ok()
+                    .and_then(|x| x.checked_mul(1_000_000_000))
+                    .and_then(|x| x.checked_add(timestamp.timestamp_subsec_nanos().into()))
+                    .context("timestamp out of range")?;
+                let len = 32 + (data.len() + pad4(data.len())) as u32;
+                self.write.write_u32::<LittleEndian>(0x00000006)?;
+                self.write.write_u32::<LittleEndian>(len)?;
+                self.write.write_u32::<LittleEndian>(interface)?;
+                self.write.write_u32::<LittleEndian>((nanos >> 32) as u32)?;
+                self.write.write_u32::<LittleEndian>(nanos as u32)?;
+                self.write.write_u32::<LittleEndian>(data.len() as u32)?;
+                self.write.write_u32::<LittleEndian>(header.len())?;
+                self.write.write_all(data)?;
+                self.write.write_all(&[0; 3][..pad4(data.len())])?;
+                self.write.write_u32::<LittleEndian>(len)?;
+            }
+        }
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.write.flush()?)
+    }
+
+    /// Completes the file, a pcap file with no packets still gets a header.
+    pub fn finish(mut self) -> Result<W> {
+        if self.format == Format::Pcap && self.link_type.is_none() {
+            self.write_pcap_header(crate::l2data::LINKTYPE_ETHERNET)?;
+        }
+        self.write.flush()?;
+        Ok(self.write)
+    }
+}
+
+/// Formats dissected packets can be exported in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TextFormat {
+    /// A JSON array with an object per packet.
+    Json,
+    /// An object per line, written as packets come.
+    Ndjson,
+    /// A row per packet of the selected columns.
+    Csv,
+}
+
+impl TextFormat {
+    /// Guesses the format from ".json", ".ndjson", ".jsonl" or ".csv".
+    pub fn from_path(path: &Path) -> Option<Self> {
+        let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+        extension.parse().ok()
+    }
+}
+
+impl FromStr for TextFormat {
+    type Err = anyhow::Error;
+    fn from_str(s: &str) -> Result<Self> {
+        Ok(match s {
+            "json" => TextFormat::Json,
+            "ndjson" | "jsonl" => TextFormat::Ndjson,
+            "csv" => TextFormat::Csv,
+            x => bail!("unknown format {:?}, expected json, ndjson or csv", x),
+        })
+    }
+}
+
+/// Columns of a CSV export when none are chosen.
+pub const DEFAULT_COLUMNS: [&str; 5] = [
+    "frame.number",
+    "frame.time",
+    "frame.cap_len",
+    "frame.len",
+    "frame.interface_name",
+];
+
+fn json_string(s: &str) -> String {
+    let mut ans = String::with_capacity(s.len() + 2);
+    ans.push('"');
+    for c in s.chars() {
+        match c {
+            '"' => ans.push_str("\\\""),
+            '\\' => ans.push_str("\\\\"),
+            '\n' => ans.push_str("\\n"),
+            '\r' => ans.push_str("\\r"),
+            '\t' => ans.push_str("\\t"),
+            c if (c as u32) < 0x20 => ans.push_str(&format!("\\u{:04x}", c as u32)),
+            c => ans.push(c),
+        }
+    }
+    ans.push('"');
+    ans
+}
+
+fn json_value(value: &FieldValue) -> String {
+    match value {
+        FieldValue::Int(x) => x.to_string(),
+        x => json_string(&x.to_string()),
+    }
+}
+
+// The fields as an object keyed by field name. Protocols become nested
+// objects, the children of other fields go under "<name>_tree", and names
+// seen more than once hold an array of their values.
+fn json_object(fields: &[Field]) -> String {
+    let mut entries: Vec<(String, Vec<String>)> = vec![];
+    let mut add = |name: String, value: String| match entries.iter_mut().find(|(x, _)| *x == name) {
+        Some((_, values)) => values.push(value),
+        None => entries.push((name, vec![value])),
+    };
+    for field in fields {
+        match field.value() {
+            FieldValue::Protocol => add(field.name().to_string(), json_object(field.children())),
+            x => {
+                add(field.name().to_string(), json_value(x));
+                if !field.children().is_empty() {
+                    add(
+                        format!("{}_tree", field.name()),
+                        json_object(field.children()),
+                    );
+                }
+            }
+        }
+    }
+    let members: Vec<String> = entries
+        .iter()
+        .map(|(name, values)| match &values[..] {
+            [x] => format!("{}:{}", json_string(name), x),
+            x => format!("{}:[{}]", json_string(name), x.join(",")),
+        })
+        .collect();
+    format!("{{{}}}", members.join(","))
+}
+
+fn csv_cell(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        return format!("\"{}\"", s.replace('"', "\"\""));
+    }
+    s.to_string()
+}
+
+/// Writes dissected packets as JSON, NDJSON or CSV, every layer's fields
+/// included.
+pub struct TextWriter<W: Write> {
+    write: W,
+    format: TextFormat,
+    columns: Vec<String>,
+    count: usize,
+}
+
+impl<W: Write> TextWriter<W> {
+    /// `columns` are the field names of the CSV columns, the defaults if
+    /// empty.
+    pub fn new(mut write: W, format: TextFormat, columns: Vec<String>) -> Result<Self> {
+        let columns = match columns.is_empty() {
+            true => DEFAULT_COLUMNS.iter().map(|x| x.to_string()).collect(),
+            false => columns,
+        };
+        match format {
+            TextFormat::Json => write!(write, "[")?,
+            TextFormat::Ndjson => {}
+            TextFormat::Csv => {
+                let header: Vec<String> = columns.iter().map(|x| csv_cell(x)).collect();
+                writeln!(write, "{}", header.join(","))?;
+            }
+        }
+        Ok(Self {
+            write,
+            format,
+            columns,
+            count: 0,
+        })
+    }
+
+    pub fn write_packet(&mut self, packet: &Packet) -> Result<()> {
+        let fields = packet.fields();
+        match self.format {
+            TextFormat::Json => {
+                let separator = if self.count == 0 { "\n" } else { ",\n" };
+                write!(self.write, "{}{}", separator, json_object(&fields))?;
+            }
+            TextFormat::Ndjson => writeln!(self.write, "{}", json_object(&fields))?,
+            TextFormat::Csv => {
+                // every occurrence of a field, e.g. both ip.addr
+                let row: Vec<String> = self
+                    .columns
+                    .iter()
+                    .map(|column| {
+                        let values: Vec<String> = fields
+                            .iter()
+                            .flat_map(|x| x.iter())
+                            .filter(|x| x.name() == column)
+                            .map(|x| x.value().to_string())
+                            .collect();
+                        csv_cell(&values.join(";"))
+                    })
+                    .collect();
+                writeln!(self.write, "{}", row.join(","))?;
+            }
+        }
+        self.count += 1;
+        Ok(())
+    }
+
+    pub fn flush(&mut self) -> Result<()> {
+        Ok(self.write.flush()?)
+    }
+
+    pub fn finish(mut self) -> Result<W> {
+        if self.format == TextFormat::Json {
+            let separator = if self.count == 0 { "" } else { "\n" };
+            writeln!(self.write, "{}]", separator)?;
+        }
+        self.write.flush()?;
+        Ok(self.write)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::capture::CaptureReader;
+    use crate::context::Context;
+    use crate::packet::{dissect_packet, PacketHeader};
+    use chrono::{Local, SecondsFormat, TimeZone};
+
+    fn packet(link_type: u32, data: Vec<u8>, ctx: &mut Context) -> Packet {
+        let header =
+            PacketHeader::new(1_500_000_000, 123_456_789, data.len() as u32, 60, link_type)
+                .unwrap();
+        dissect_packet(header, data, ctx)
+    }
+
+    fn read_back(bytes: &[u8]) -> Vec<Packet> {
+        async_std::task::block_on(async {
+            let mut read = bytes;
+            let mut capture = CaptureReader::open(&mut read).await.unwrap();
+            let mut ctx = Context::default();
+            let mut ans = vec![];
+            while let Some(x) = capture.read_packet(&mut read, &mut ctx).await.unwrap() {
+                ans.push(x);
+            }
+            ans
+        })
+    }
+
+    #[test]
+    fn test_write() {
+        let mut ctx = Context::default();
+        // an unknown ethertype, over ethernet and sll2
+        let ethernet = [&[0; 12][..], &[0x88, 0xb5, 1]].concat();
+        let sll2 = [&[0x88, 0xb5][..], &[0; 18], &[2]].concat();
+        let packets = [packet(1, ethernet, &mut ctx), packet(276, sll2, &mut ctx)];
+        for format in [Format::Pcap, Format::Pcapng] {
+            let mut writer = PacketWriter::new(vec![], format).unwrap();
+            writer.write_packet(&packets[0]).unwrap();
+            let result = writer.write_packet(&packets[1]);
+            assert_eq!(result.is_ok(), format == Format::Pcapng);
+            let read = read_back(&writer.finish().unwrap());
+            assert_eq!(read.len(), if format == Format::Pcap { 1 } else { 2 });
+            for (x, y) in read.iter().zip(&packets) {
+                assert_eq!(x.header().timestamp(), y.header().timestamp());
+                assert_eq!(x.header().len(), 60);
+                assert_eq!(x.header().link_type(), y.header().link_type());
+                assert_eq!(x.data(), y.data());
+            }
+        }
+        let empty = PacketWriter::new(vec![], Format::Pcap).unwrap().finish();
+        assert_eq!(empty.unwrap().len(), 24);
+        assert_eq!(Format::from_path(Path::new("a.PCAPNG")), Format::Pcapng);
+        assert_eq!("3-5".parse::<Selection>().unwrap(), Selection::Range(3, 5));
+        assert!("3-x".parse::<Selection>().is_err());
+    }
+
+    #[test]
+    fn test_text_writer() {
+        let mut ctx = Context::default();
+        // udp from 10.0.0.3:6000 to 10.0.0.4:7000
+        let mut data = vec![0; 12];
+        data.extend([0x08, 0x00, 0x45, 0, 0, 28, 0, 1, 0, 0, 64, 17, 0, 0]);
+        data.extend([10, 0, 0, 3, 10, 0, 0, 4, 0x17, 0x70, 0x1b, 0x58, 0, 8, 0, 0]);
+        let udp = packet(1, data, &mut ctx);
+        let export = |format, columns: &[&str]| {
+            let columns = columns.iter().map(|x| x.to_string()).collect();
+            let mut writer = TextWriter::new(vec![], format, columns).unwrap();
+            writer.write_packet(&udp).unwrap();
+            writer.write_packet(&udp).unwrap();
+            String::from_utf8(writer.finish().unwrap()).unwrap()
+        };
+        let json = export(TextFormat::Json, &[]);
+        // the time is written in the local time zone
+        let time = Local
+            .timestamp_opt(1_500_000_000, 123_456_789)
+            .unwrap()
+            .to_rfc3339_opts(SecondsFormat::Nanos, false);
+        let start = format!("[\n{{\"frame\":{{\"frame.time\":\"{}\",", time);
+        assert!(json.starts_with(&start), "{}", json);
+        assert!(json.contains("\"ip.addr\":[\"10.0.0.3\",\"10.0.0.4\"]"));
+        assert!(json.contains("\"udp\":{\"udp.srcport\":6000,"));
+        assert!(json.ends_with("}\n]\n"));
+        let ndjson = export(TextFormat::Ndjson, &[]);
+        assert_eq!(ndjson.lines().count(), 2);
+        assert!(ndjson
+            .lines()
+            .all(|x| x.starts_with('{') && x.ends_with('}')));
+        let csv = export(TextFormat::Csv, &["frame.number", "ip.addr", "udp.dstport"]);
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines[0], "frame.number,ip.addr,udp.dstport");
+        assert_eq!(lines[2], "1,10.0.0.3;10.0.0.4,7000");
+        assert_eq!(export(TextFormat::Json, &[]).len(), json.len());
+        assert_eq!(csv_cell("a,\"b\""), "\"a,\"\"b\"\"\"");
+        assert_eq!(json_string("a\"\\\n\u{1}"), "\"a\\\"\\\\\\n\\u0001\"");
+        assert_eq!(
+            TextFormat::from_path(Path::new("x.JSONL")),
+            Some(TextFormat::Ndjson)
+        );
+    }
+}
@@ -0,0 +1,308 @@
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FieldValue {
+    /// Marks that a protocol is present in the packet.
+    Protocol,
+    Int(u64),
+    Addr(IpAddr),
+    Mac([u8; 6]),
+    Bytes(Vec<u8>),
+    Str(String),
+}
+
+impl From<bool> for FieldValue {
+    fn from(x: bool) -> Self {
+        FieldValue::Int(x.into())
+    }
+}
+
+impl From<u8> for FieldValue {
+    fn from(x: u8) -> Self {
+        FieldValue::Int(x.into())
+    }
+}
+
+impl From<u16> for FieldValue {
+    fn from(x: u16) -> Self {
+        FieldValue::Int(x.into())
+    }
+}
+
+impl From<u32> for FieldValue {
+    fn from(x: u32) -> Self {
+        FieldValue::Int(x.into())
+    }
+}
+
+impl From<u64> for FieldValue {
+    fn from(x: u64) -> Self {
+        FieldValue::Int(x)
+    }
+}
+
+impl From<usize> for FieldValue {
+    fn from(x: usize) -> Self {
+        FieldValue::Int(x as u64)
+    }
+}
+
+impl From<IpAddr> for FieldValue {
+    fn from(x: IpAddr) -> Self {
+        FieldValue::Addr(x)
+    }
+}
+
+impl From<Ipv4Addr> for FieldValue {
+    fn from(x: Ipv4Addr) -> Self {
+        FieldValue::Addr(x.into())
+    }
+}
+
+impl From<Ipv6Addr> for FieldValue {
+    fn from(x: Ipv6Addr) -> Self {
+        FieldValue::Addr(x.into())
+    }
+}
+
+impl From<&[u8]> for FieldValue {
+    fn from(x: &[u8]) -> Self {
+        FieldValue::Bytes(x.to_vec())
+    }
+}
+
+impl From<&str> for FieldValue {
+    fn from(x: &str) -> Self {
+        FieldValue::Str(x.to_string())
+    }
+}
+
+impl From<String> for FieldValue {
+    fn from(x: String) -> Self {
+        FieldValue::Str(x)
+    }
+}
+
+impl std::fmt::Display for FieldValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FieldValue::Protocol => Ok(()),
+            FieldValue::Int(x) => write!(f, "{}", x),
+            FieldValue::Addr(x) => write!(f, "{}", x),
+            FieldValue::Mac(x) => {
+                let octets: Vec<String> = x.iter().map(|x| format!("{:02x}", x)).collect();
+                write!(f, "{}", octets.join(":"))
+            }
+            FieldValue::Bytes(x) => {
+                let octets: Vec<String> = x.iter().map(|x| format!("{:02x}", x)).collect();
+                write!(f, "{}", octets.join(":"))
+            }
+            FieldValue::Str(x) => write!(f, "{}", x),
+        }
+    }
+}
+
+/// What the values of a field are, which display filter values parse to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldType {
+    Protocol,
+    Int,
+    Addr,
+    Mac,
+    Bytes,
+    Str,
+}
+
+/// A field a dissector can emit, declared so display filters know its name
+/// and the protocol tree its title.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FieldInfo {
+    pub name: &'static str,
+    pub title: &'static str,
+    pub ty: FieldType,
+    // shown in hex in the protocol tree
+    pub hex: bool,
+}
+
+impl FieldInfo {
+    pub const fn new(name: &'static str, title: &'static str, ty: FieldType) -> Self {
+        Self {
+            name,
+            title,
+            ty,
+            hex: false,
+        }
+    }
+    pub const fn hex(mut self) -> Self {
+        self.hex = true;
+        self
+    }
+}
+
+/// Fields of bytes no dissector understood, emitted by every layer.
+const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("truncated", "[truncated]", FieldType::Protocol),
+    FieldInfo::new("data", "Data", FieldType::Protocol),
+    FieldInfo::new("data.data", "Data", FieldType::Bytes),
+];
+
+const BUILTIN_FIELDS: &[&[FieldInfo]] = &[
+    crate::packet::FIELDS,
+    FIELDS,
+    crate::l2data::ethernet::FIELDS,
+    crate::l2data::llc::FIELDS,
+    crate::l2data::sll::FIELDS,
+    crate::l2data::null::FIELDS,
+    crate::l2data::raw::FIELDS,
+    crate::l2data::radiotap::FIELDS,
+    crate::l2data::ieee80211::FIELDS,
+    crate::l3data::arp::FIELDS,
+    crate::l3data::ipv4::FIELDS,
+    crate::l3data::ipv6::FIELDS,
+    crate::l3data::ipv4data::icmp::FIELDS,
+    crate::l3data::ipv4data::icmpv6::FIELDS,
+    crate::l3data::ipv4data::tcp::FIELDS,
+    crate::l3data::ipv4data::udp::FIELDS,
+    crate::l7data::dns::FIELDS,
+    crate::l7data::http::FIELDS,
+];
+
+/// Looks up a field the built in dissectors emit. Fields of dissectors
+/// plugged in later are looked up through the registry.
+pub fn builtin_field(name: &str) -> Option<&'static FieldInfo> {
+    BUILTIN_FIELDS
+        .iter()
+        .flat_map(|x| x.iter())
+        .find(|x| x.name == name)
+}
+
+/// A named value dissected from a packet, e.g. "udp.srcport", with where
+/// it was read from. Protocols are fields whose children are their header
+/// fields.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Field {
+    name: &'static str,
+    value: FieldValue,
+    offset: usize,
+    len: usize,
+    children: Vec<Field>,
+    // shown after the value, e.g. whether a checksum is correct
+    note: Option<String>,
+}
+
+impl Field {
+    pub fn new(name: &'static str, value: impl Into<FieldValue>) -> Self {
+        Self {
+            name,
+            value: value.into(),
+            offset: 0,
+            len: 0,
+            children: vec![],
+            note: None,
+        }
+    }
+    pub fn protocol(name: &'static str) -> Self {
+        Self::new(name, FieldValue::Protocol)
+    }
+    /// Places the field at `len` bytes from `offset` of its layer. Fields
+    /// computed rather than read keep a length of 0.
+    pub fn at(mut self, offset: usize, len: usize) -> Self {
+        self.offset = offset;
+        self.len = len;
+        self
+    }
+    pub fn with_children(mut self, children: Vec<Field>) -> Self {
+        self.children = children;
+        self
+    }
+    pub fn with_note(mut self, note: impl Into<String>) -> Self {
+        self.note = Some(note.into());
+        self
+    }
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    pub fn value(&self) -> &FieldValue {
+        &self.value
+    }
+    /// Offset from the start of the frame once the packet is dissected.
+    pub fn offset(&self) -> usize {
+        self.offset
+    }
+    pub fn len(&self) -> usize {
+        self.len
+    }
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+    pub fn children(&self) -> &[Field] {
+        &self.children
+    }
+    /// Human readable form for the protocol tree, e.g. "Source Port: 53".
+    pub fn label(&self) -> String {
+        let (title, hex) = match builtin_field(self.name) {
+            Some(x) => (x.title, x.hex),
+            None => (self.name, false),
+        };
+        let label = match &self.value {
+            FieldValue::Protocol => title.to_string(),
+            FieldValue::Int(x) if hex => format!("{}: {:#06x}", title, x),
+            FieldValue::Bytes(x) if x.len() > 16 => {
+                let head = FieldValue::Bytes(x[..16].to_vec());
+                format!("{}: {}… ({} bytes)", title, head, x.len())
+            }
+            x => format!("{}: {}", title, x),
+        };
+        match &self.note {
+            Some(note) => format!("{} [{}]", label, note),
+            None => label,
+        }
+    }
+    /// The field and all of its descendants, depth first.
+    pub fn iter(&self) -> FieldIter<'_> {
+        FieldIter { stack: vec![self] }
+    }
+    fn shift(&mut self, by: usize) {
+        self.offset += by;
+        for child in &mut self.children {
+            child.shift(by);
+        }
+    }
+    fn detach(&mut self) {
+        self.offset = 0;
+        self.len = 0;
+        for child in &mut self.children {
+            child.detach();
+        }
+    }
+}
+
+pub struct FieldIter<'a> {
+    stack: Vec<&'a Field>,
+}
+
+impl<'a> Iterator for FieldIter<'a> {
+    type Item = &'a Field;
+    fn next(&mut self) -> Option<&'a Field> {
+        let field = self.stack.pop()?;
+        self.stack.extend(field.children.iter().rev());
+        Some(field)
+    }
+}
+
+/// Moves the fields of a payload to where it starts in the enclosing layer.
+pub(crate) fn shifted(mut fields: Vec<Field>, by: usize) -> Vec<Field> {
+    for field in &mut fields {
+        field.shift(by);
+    }
+    fields
+}
+
+/// Unplaces the fields of bytes that aren't in the frame as they are, e.g.
+/// a reassembled datagram.
+pub(crate) fn detached(mut fields: Vec<Field>) -> Vec<Field> {
+    for field in &mut fields {
+        field.detach();
+    }
+    fields
+}
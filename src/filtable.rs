@@ -1,5 +1,9 @@
 pub trait Filtable {
-    fn is_match(&self, s: &str) -> bool;
+    type Filter: Default + std::fmt::Debug;
+    // what compiling a filter needs besides its text
+    type Env;
+    fn compile(s: &str, env: &Self::Env) -> anyhow::Result<Self::Filter>;
+    fn is_match(&self, filter: &Self::Filter) -> bool;
 }
 
 #[derive(Debug)]
@@ -13,6 +17,9 @@ struct FiltableListItem<T: Filtable> {
 pub struct FiltableList<T: Filtable> {
     list: Vec<FiltableListItem<T>>,
     filter: String,
+    // the last filter that compiled, kept while the text is invalid
+    compiled: T::Filter,
+    error: Option<String>,
 }
 
 impl<T: Filtable> FiltableList<T> {
@@ -20,6 +27,8 @@ impl<T: Filtable> FiltableList<T> {
         Self {
             list: vec![],
             filter: "".to_string(),
+            compiled: T::Filter::default(),
+            error: None,
         }
     }
     pub fn is_empty(&self) -> bool {
@@ -37,11 +46,14 @@ impl<T: Filtable> FiltableList<T> {
     pub fn get_filter(&self) -> String {
         self.filter.clone()
     }
+    pub fn get_filter_error(&self) -> Option<String> {
+        self.error.clone()
+    }
     pub fn get_item(&self, i: usize) -> &T {
         &self.list[i].item
     }
     pub fn is_match(&self, i: usize) -> bool {
-        self.list[i].item.is_match(&self.filter)
+        self.list[i].item.is_match(&self.compiled)
     }
     pub fn len(&self) -> usize {
         self.list.len()
@@ -58,7 +70,7 @@ impl<T: Filtable> FiltableList<T> {
             self.list.push(item);
             return;
         };
-        if last.item.is_match(&self.filter) {
+        if last.item.is_match(&self.compiled) {
             let item = FiltableListItem {
                 item,
                 previous: Some(self.list.len() - 1),
@@ -73,7 +85,7 @@ impl<T: Filtable> FiltableList<T> {
             };
             self.list.push(item);
         }
-        if !self.list[self.list.len() - 1].item.is_match(&self.filter) {
+        if !self.list[self.list.len() - 1].item.is_match(&self.compiled) {
             return;
         }
         let mut count = self.list.len() - 2;
@@ -86,37 +98,47 @@ impl<T: Filtable> FiltableList<T> {
             }
         }
     }
-    fn update_filter(&mut self) {
+    fn update_filter(&mut self, env: &T::Env) {
+        match T::compile(&self.filter, env) {
+            Ok(x) => {
+                self.compiled = x;
+                self.error = None;
+            }
+            Err(e) => {
+                self.error = Some(format!("{:#}", e));
+                return;
+            }
+        }
         let mut last_true = None;
         for i in 0..self.list.len() {
             self.list[i].previous = last_true;
-            if self.list[i].item.is_match(&self.filter) {
+            if self.list[i].item.is_match(&self.compiled) {
                 last_true = Some(i)
             }
         }
         last_true = None;
         for i in (0..self.list.len()).rev() {
             self.list[i].next = last_true;
-            if self.list[i].item.is_match(&self.filter) {
+            if self.list[i].item.is_match(&self.compiled) {
                 last_true = Some(i);
             }
         }
     }
 
-    pub fn add_filter_str(&mut self, st: &str) {
+    pub fn add_filter_str(&mut self, st: &str, env: &T::Env) {
         self.filter.push_str(st);
-        self.update_filter();
+        self.update_filter(env);
     }
 
-    pub fn delete_filter_char(&mut self) {
+    pub fn delete_filter_char(&mut self, env: &T::Env) {
         self.filter.pop();
-        self.update_filter();
+        self.update_filter(env);
     }
     pub fn filterd_first(&self) -> Option<usize> {
         if self.list.is_empty() {
             return None;
         }
-        if self.list[0].item.is_match(&self.filter) {
+        if self.list[0].item.is_match(&self.compiled) {
             return Some(0);
         }
         self.list[0].next
@@ -126,7 +148,7 @@ impl<T: Filtable> FiltableList<T> {
         if self.list.is_empty() {
             return None;
         }
-        if self.list[self.list.len() - 1].item.is_match(&self.filter) {
+        if self.list[self.list.len() - 1].item.is_match(&self.compiled) {
             return Some(self.list.len() - 1);
         }
         self.list[self.list.len() - 1].previous
@@ -153,8 +175,13 @@ mod test {
         s: String,
     }
     impl Filtable for Mock {
-        fn is_match(&self, s: &str) -> bool {
-            self.s.contains(s)
+        type Filter = String;
+        type Env = ();
+        fn compile(s: &str, _env: &()) -> anyhow::Result<String> {
+            Ok(s.to_string())
+        }
+        fn is_match(&self, s: &String) -> bool {
+            self.s.contains(s.as_str())
         }
     }
 
@@ -176,16 +203,16 @@ mod test {
         }
         assert_eq!(li.filterd_first(), Some(0));
         assert_eq!(li.filterd_last(), Some(19));
-        li.add_filter_str("tcp");
+        li.add_filter_str("tcp", &());
         assert_eq!(li.filterd_first(), Some(0));
         assert_eq!(li.filterd_last(), Some(18));
         li.push(Mock {
             s: "ucp".to_string(),
         });
-        li.delete_filter_char();
-        li.delete_filter_char();
-        li.delete_filter_char();
-        li.add_filter_str("udp");
+        li.delete_filter_char(&());
+        li.delete_filter_char(&());
+        li.delete_filter_char(&());
+        li.add_filter_str("udp", &());
         assert_eq!(li.filterd_first(), Some(1));
         assert_eq!(li.next(1), Some(3));
         assert_eq!(li.next(0), Some(1));
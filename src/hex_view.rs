@@ -0,0 +1,67 @@
+use std::ops::Range;
+
+pub const BYTES_PER_LINE: usize = 16;
+
+/// Cursor and selection of the hex pane. A selection runs from the anchor
+/// set with `toggle_anchor` to the cursor.
+#[derive(Debug, Default)]
+pub struct HexView {
+    cursor: usize,
+    anchor: Option<usize>,
+    // first visible line
+    offset: usize,
+}
+
+impl HexView {
+    pub fn cursor(&self) -> usize {
+        self.cursor
+    }
+
+    pub fn move_cursor(&mut self, delta: isize, len: usize) {
+        let last = len.saturating_sub(1);
+        self.cursor = self.cursor.saturating_add_signed(delta).min(last);
+    }
+
+    pub fn toggle_anchor(&mut self) {
+        self.anchor = match self.anchor {
+            Some(_) => None,
+            None => Some(self.cursor),
+        };
+    }
+
+    pub fn selection(&self) -> Range<usize> {
+        let anchor = self.anchor.unwrap_or(self.cursor);
+        anchor.min(self.cursor)..anchor.max(self.cursor) + 1
+    }
+
+    /// The first line to draw so that the line of byte `focus` is visible.
+    pub fn scroll(&mut self, focus: usize, height: usize) -> usize {
+        let line = focus / BYTES_PER_LINE;
+        if line < self.offset {
+            self.offset = line;
+        } else if height > 0 && line >= self.offset + height {
+            self.offset = line + 1 - height;
+        }
+        self.offset
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_hex_view() {
+        let mut view = HexView::default();
+        view.move_cursor(-1, 40);
+        assert_eq!(view.cursor(), 0);
+        view.move_cursor(BYTES_PER_LINE as isize * 2, 40);
+        view.toggle_anchor();
+        view.move_cursor(100, 40);
+        assert_eq!(view.selection(), 32..40);
+        view.move_cursor(-10, 40);
+        assert_eq!(view.selection(), 29..33);
+        assert_eq!(view.scroll(view.cursor(), 1), 1);
+        assert_eq!(view.scroll(0, 1), 0);
+    }
+}
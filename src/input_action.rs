@@ -1,3 +1,4 @@
+use crate::hex_view::BYTES_PER_LINE;
 use crate::App;
 use crossterm::event::KeyCode;
 
@@ -9,7 +10,34 @@ pub fn allmode_input(app: &mut App, keycode: KeyCode) {
         app.exit();
     }
 }
-pub fn viewmode_input(_app: &mut App, _keycode: KeyCode) {}
+pub fn viewmode_input(app: &mut App, keycode: KeyCode) {
+    match keycode {
+        KeyCode::Down => app.tree_mut().next(),
+        KeyCode::Up => app.tree_mut().previous(),
+        KeyCode::PageDown => app.tree_mut().page_down(),
+        KeyCode::PageUp => app.tree_mut().page_up(),
+        KeyCode::Right => app.tree_mut().expand(),
+        KeyCode::Left => app.tree_mut().collapse(),
+        KeyCode::Char('e') => app.tree_mut().expand_all(),
+        KeyCode::Char('c') => app.tree_mut().collapse_all(),
+        KeyCode::Char(':') => app.start_command(),
+        _ => {}
+    }
+}
+pub fn bytesmode_input(app: &mut App, keycode: KeyCode) {
+    let line = BYTES_PER_LINE as isize;
+    match keycode {
+        KeyCode::Left => app.move_byte_cursor(-1),
+        KeyCode::Right => app.move_byte_cursor(1),
+        KeyCode::Up => app.move_byte_cursor(-line),
+        KeyCode::Down => app.move_byte_cursor(line),
+        KeyCode::PageUp => app.move_byte_cursor(-line * 8),
+        KeyCode::PageDown => app.move_byte_cursor(line * 8),
+        KeyCode::Char('v') => app.toggle_byte_anchor(),
+        KeyCode::Char(':') => app.start_command(),
+        _ => {}
+    }
+}
 pub fn listmode_input(app: &mut App, keycode: KeyCode) {
     match keycode {
         KeyCode::Left => {
@@ -24,6 +52,12 @@ pub fn listmode_input(app: &mut App, keycode: KeyCode) {
         KeyCode::Right => {
             app.to_view();
         }
+        KeyCode::Char('m') => {
+            app.toggle_mark();
+        }
+        KeyCode::Char(':') => {
+            app.start_command();
+        }
         _ => {}
     }
 }
@@ -42,3 +76,12 @@ pub fn filtermode_input(app: &mut App, keycode: KeyCode) {
     }
     app.add_filter_str(&key_char.to_string());
 }
+pub fn commandmode_input(app: &mut App, keycode: KeyCode) {
+    match keycode {
+        KeyCode::Esc => app.cancel_command(),
+        KeyCode::Enter => app.run_command(),
+        KeyCode::Backspace => app.delete_command_char(),
+        KeyCode::Char(x) if x.is_ascii() => app.add_command_str(&x.to_string()),
+        _ => {}
+    }
+}
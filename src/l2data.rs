@@ -0,0 +1,265 @@
+pub mod ethernet;
+pub mod ieee80211;
+pub mod llc;
+pub mod null;
+pub mod other;
+pub mod radiotap;
+pub mod raw;
+pub mod sll;
+pub mod sll2;
+
+use crate::context::Context;
+use crate::field::{Field, FieldValue};
+use anyhow::{Context as _, Result};
+use ethernet::read_ethernet;
+use ieee80211::read_ieee80211;
+use null::read_null;
+use other::{read_otherl2data, read_truncated_l2data};
+use radiotap::read_radiotap;
+use raw::read_raw;
+use sll::read_sll;
+use sll2::read_sll2;
+use std::fmt::Debug;
+
+pub const LINKTYPE_NULL: u32 = 0;
+pub const LINKTYPE_ETHERNET: u32 = 1;
+pub const LINKTYPE_RAW: u32 = 101;
+pub const LINKTYPE_IEEE802_11: u32 = 105;
+pub const LINKTYPE_LOOP: u32 = 108;
+pub const LINKTYPE_LINUX_SLL: u32 = 113;
+pub const LINKTYPE_IEEE802_11_RADIOTAP: u32 = 127;
+pub const LINKTYPE_IPV4: u32 = 228;
+pub const LINKTYPE_IPV6: u32 = 229;
+pub const LINKTYPE_LINUX_SLL2: u32 = 276;
+// DLT_RAW as written by some BSDs and old libpcap
+pub(crate) const DLT_RAW_BSD: u32 = 12;
+pub(crate) const DLT_RAW_OPENBSD: u32 = 14;
+
+#[derive(Clone, Copy, PartialEq, Eq, Hash)]
+pub struct Macaddr(pub [u8; 6]);
+
+impl std::fmt::Display for Macaddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:0>2x}:{:0>2x}:{:0>2x}:{:0>2x}:{:0>2x}:{:0>2x}",
+            self.0[0], self.0[1], self.0[2], self.0[3], self.0[4], self.0[5]
+        )
+    }
+}
+
+impl std::fmt::Debug for Macaddr {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}]", self)
+    }
+}
+
+impl From<Macaddr> for FieldValue {
+    fn from(x: Macaddr) -> Self {
+        FieldValue::Mac(x.0)
+    }
+}
+
+pub trait L2data: Debug + Send + Sync {
+    fn text(&self) -> Vec<String>;
+    fn line(&self) -> String;
+    fn fields(&self) -> Vec<Field>;
+}
+
+pub fn read_l2data(
+    read: std::collections::VecDeque<u8>,
+    link_type: u32,
+    ctx: &mut Context,
+) -> Result<Box<dyn L2data>> {
+    ctx.or_truncated(
+        read,
+        |read, ctx| dissect_l2data(read, link_type, ctx),
+        |read| Box::new(read_truncated_l2data(read, link_type)),
+    )
+}
+
+fn dissect_l2data(
+    read: std::collections::VecDeque<u8>,
+    link_type: u32,
+    ctx: &mut Context,
+) -> Result<Box<dyn L2data>> {
+    match link_type {
+        LINKTYPE_ETHERNET => Ok(Box::new(read_ethernet(read, ctx).context("read ethernet")?)),
+        LINKTYPE_NULL | LINKTYPE_LOOP => Ok(Box::new(
+            read_null(read, link_type == LINKTYPE_LOOP, ctx).context("read null")?,
+        )),
+        LINKTYPE_RAW | LINKTYPE_IPV4 | LINKTYPE_IPV6 | DLT_RAW_BSD | DLT_RAW_OPENBSD => {
+            read_raw(read, link_type, ctx).context("read raw")
+        }
+        LINKTYPE_LINUX_SLL => Ok(Box::new(read_sll(read, ctx).context("read sll")?)),
+        LINKTYPE_LINUX_SLL2 => Ok(Box::new(read_sll2(read, ctx).context("read sll2")?)),
+        LINKTYPE_IEEE802_11 => Ok(Box::new(
+            read_ieee80211(read, false, ctx).context("read ieee802.11")?,
+        )),
+        LINKTYPE_IEEE802_11_RADIOTAP => {
+            Ok(Box::new(read_radiotap(read, ctx).context("read radiotap")?))
+        }
+        _ => Ok(Box::new(read_otherl2data(read, link_type)?)),
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    const IPV4_UDP: [u8; 28] = [
+        0x45, 0x00, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00,
+        0x01, 0x0a, 0x00, 0x00, 0x02, 0x00, 0x35, 0xd4, 0x31, 0x00, 0x08, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn test_read_l2data_link_types() {
+        let expected = "10.0.0.1:53 → 10.0.0.2:54321 UDP";
+        let raw = read_l2data(
+            IPV4_UDP.to_vec().into(),
+            LINKTYPE_RAW,
+            &mut Context::default(),
+        )
+        .unwrap();
+        assert_eq!(raw.line(), expected);
+
+        let mut sll = vec![0x00, 0x00, 0x00, 0x01, 0x00, 0x06];
+        sll.extend_from_slice(&[0x02, 0x42, 0xac, 0x11, 0x00, 0x02, 0x00, 0x00, 0x08, 0x00]);
+        sll.extend_from_slice(&IPV4_UDP);
+        let sll = read_l2data(sll.into(), LINKTYPE_LINUX_SLL, &mut Context::default()).unwrap();
+        assert_eq!(sll.line(), expected);
+
+        let mut null = vec![0x02, 0x00, 0x00, 0x00];
+        null.extend_from_slice(&IPV4_UDP);
+        let null = read_l2data(null.into(), LINKTYPE_NULL, &mut Context::default()).unwrap();
+        assert_eq!(null.line(), expected);
+
+        let other = read_l2data(IPV4_UDP.to_vec().into(), 147, &mut Context::default()).unwrap();
+        assert_eq!(other.line(), "linktype(147) 28 bytes");
+
+        let mut not_ip = IPV4_UDP;
+        not_ip[0] = 0x55;
+        let raw = read_l2data(
+            not_ip.to_vec().into(),
+            LINKTYPE_RAW,
+            &mut Context::default(),
+        );
+        assert_eq!(raw.unwrap().line(), "linktype(101) 28 bytes");
+    }
+
+    fn value(fields: &[Field], name: &str) -> Option<(String, usize)> {
+        let field = fields
+            .iter()
+            .flat_map(|x| x.iter())
+            .find(|x| x.name() == name)?;
+        Some((field.value().to_string(), field.offset()))
+    }
+
+    // a data frame to the distribution system carrying IPV4_UDP over LLC/SNAP
+    fn ieee80211_data() -> Vec<u8> {
+        let mut frame = vec![0x08, 0x01, 0x00, 0x00];
+        for x in 1..=3 {
+            frame.extend_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x00, x]);
+        }
+        frame.extend_from_slice(&[0x10, 0x00]);
+        frame.extend_from_slice(&[0xaa, 0xaa, 0x03, 0x00, 0x00, 0x00, 0x08, 0x00]);
+        frame.extend_from_slice(&IPV4_UDP);
+        frame
+    }
+
+    #[test]
+    fn test_read_sll2() {
+        let mut bytes = vec![0x08, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x01];
+        bytes.extend_from_slice(&[0x00, 0x06, 0x02, 0x42, 0xac, 0x11, 0x00, 0x02, 0x00, 0x00]);
+        bytes.extend_from_slice(&IPV4_UDP);
+        let sll2 = read_sll2(bytes.into(), &mut Context::default()).unwrap();
+        assert_eq!(sll2.line(), "10.0.0.1:53 → 10.0.0.2:54321 UDP");
+        let fields = sll2.fields();
+        assert_eq!(value(&fields, "sll.ifindex").unwrap(), ("3".to_string(), 4));
+        assert_eq!(value(&fields, "sll.etype").unwrap().0, "2048");
+        assert_eq!(
+            value(&fields, "ip.src").unwrap(),
+            ("10.0.0.1".to_string(), 32)
+        );
+    }
+
+    #[test]
+    fn test_read_radiotap() {
+        // flags with an fcs, rate and channel, then the frame and its fcs
+        let mut bytes = vec![0x00, 0x00, 0x0e, 0x00, 0x0e, 0x00, 0x00, 0x00, 0x10, 0x02];
+        bytes.extend_from_slice(&[0x6c, 0x09, 0xa0, 0x00]);
+        bytes.extend(ieee80211_data());
+        bytes.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        let radiotap = read_radiotap(bytes.clone().into(), &mut Context::default()).unwrap();
+        assert_eq!(radiotap.line(), "10.0.0.1:53 → 10.0.0.2:54321 UDP");
+        let fields = radiotap.fields();
+        assert_eq!(
+            value(&fields, "radiotap.datarate").unwrap(),
+            ("2".to_string(), 9)
+        );
+        assert_eq!(
+            value(&fields, "radiotap.channel.freq").unwrap(),
+            ("2412".to_string(), 10)
+        );
+        assert_eq!(
+            value(&fields, "wlan.fc.type").unwrap(),
+            ("2".to_string(), 14)
+        );
+        assert_eq!(value(&fields, "ip.src").unwrap().1, 14 + 32 + 12);
+
+        let mut bad = vec![0x00, 0x00, 0x40, 0x00, 0x00, 0x00, 0x00, 0x00];
+        bad.extend(ieee80211_data());
+        assert!(read_radiotap(bad.into(), &mut Context::default()).is_err());
+        // only a header cut short by the snaplen keeps the frame raw
+        let mut ctx = Context::default();
+        ctx.truncated = true;
+        let link_type = LINKTYPE_IEEE802_11_RADIOTAP;
+        let cut = read_l2data(bytes[..10].to_vec().into(), link_type, &mut ctx).unwrap();
+        assert_eq!(cut.line(), "linktype(127) [truncated]");
+        let invalid = vec![0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00];
+        assert!(read_l2data(invalid.into(), link_type, &mut ctx).is_err());
+    }
+
+    #[test]
+    fn test_read_ieee80211() {
+        let wlan = read_ieee80211(ieee80211_data().into(), false, &mut Context::default()).unwrap();
+        assert_eq!(wlan.line(), "10.0.0.1:53 → 10.0.0.2:54321 UDP");
+        let fields = wlan.fields();
+        let addrs: Vec<_> = fields[0]
+            .children()
+            .iter()
+            .filter(|x| x.name() == "wlan.addr")
+            .map(|x| x.value().to_string())
+            .collect();
+        assert_eq!(
+            addrs,
+            [
+                "02:00:00:00:00:01",
+                "02:00:00:00:00:02",
+                "02:00:00:00:00:03"
+            ]
+        );
+        assert_eq!(fields[0].len(), 24);
+        assert_eq!(value(&fields, "llc.pid").unwrap(), ("2048".to_string(), 30));
+        assert_eq!(value(&fields, "ip.src").unwrap().1, 32 + 12);
+
+        // spanning tree over plain LLC keeps the LLC header in the tree
+        let mut stp = ieee80211_data();
+        stp.truncate(24);
+        stp.extend_from_slice(&[0x42, 0x42, 0x03, 0x00, 0x00]);
+        let stp = read_ieee80211(stp.into(), false, &mut Context::default()).unwrap();
+        assert_eq!(stp.line(), "LLC dsap=0x42 ssap=0x42");
+        let fields = stp.fields();
+        assert_eq!(value(&fields, "llc.dsap").unwrap(), ("66".to_string(), 24));
+        assert_eq!(
+            value(&fields, "data.data").unwrap(),
+            ("00:00".to_string(), 27)
+        );
+
+        let ack = [0xd4, 0x00, 0x00, 0x00, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01];
+        let ack = read_ieee80211(ack.to_vec().into(), false, &mut Context::default()).unwrap();
+        assert_eq!(ack.line(), "802.11 ACK");
+        let beacon = [0x80, 0x00, 0x00, 0x00, 0xff, 0xff];
+        assert!(read_ieee80211(beacon.to_vec().into(), false, &mut Context::default()).is_err());
+    }
+}
@@ -0,0 +1,215 @@
+use crate::context::Context;
+use crate::field::{shifted, Field, FieldInfo, FieldType};
+use crate::l2data::llc::{read_llc, Llc};
+use crate::l2data::{L2data, Macaddr};
+use crate::l3data::{read_l3data, L3data};
+use anyhow::{Context as _, Result};
+use byteorder::{NetworkEndian, ReadBytesExt};
+use std::io::Read;
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("eth", "Ethernet II", FieldType::Protocol),
+    FieldInfo::new("eth.dst", "Destination", FieldType::Mac),
+    FieldInfo::new("eth.src", "Source", FieldType::Mac),
+    FieldInfo::new("eth.addr", "Address", FieldType::Mac),
+    FieldInfo::new("eth.type", "Type", FieldType::Int).hex(),
+    FieldInfo::new("vlan", "802.1Q Virtual LAN", FieldType::Protocol),
+    FieldInfo::new("vlan.id", "ID", FieldType::Int),
+    FieldInfo::new("vlan.priority", "Priority", FieldType::Int),
+    FieldInfo::new("vlan.dei", "DEI", FieldType::Int),
+];
+
+const TPID_8021Q: u16 = 0x8100;
+const TPID_8021AD: u16 = 0x88a8;
+const TPID_QINQ: u16 = 0x9100;
+// type_len values up to this are an 802.3 length
+const MAX_8023_LENGTH: u16 = 1500;
+
+#[derive(Debug, Clone, Copy)]
+struct VlanTag {
+    _tpid: u16,
+    _pcp: u8,
+    _dei: bool,
+    vid: u16,
+}
+
+#[derive(Debug)]
+struct FrameHeader {
+    _dst: Macaddr,
+    _src: Macaddr,
+    vlan_tags: Vec<VlanTag>,
+    _type_len: u16,
+    llc: Option<Llc>,
+}
+
+#[derive(Debug)]
+pub struct Ethernet {
+    header: FrameHeader,
+    data: Option<Box<dyn L3data>>,
+    _payload: Vec<u8>,
+}
+
+impl L2data for Ethernet {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("{:?}", self.header)];
+        match &self.data {
+            Some(data) => ans.append(data.text().as_mut()),
+            None => ans.push(format!("LLC payload {:x?}", self._payload)),
+        }
+        ans
+    }
+    fn line(&self) -> String {
+        let vlan = if self.header.vlan_tags.is_empty() {
+            "".to_string()
+        } else {
+            let ids: Vec<String> = self
+                .header
+                .vlan_tags
+                .iter()
+                .map(|x| x.vid.to_string())
+                .collect();
+            format!("VLAN {} ", ids.join("/"))
+        };
+        let data = match (&self.data, &self.header.llc) {
+            (Some(data), _) => data.line(),
+            (None, Some(llc)) => llc.line(),
+            (None, None) => "".to_string(),
+        };
+        format!("{}{}", vlan, data)
+    }
+    fn fields(&self) -> Vec<Field> {
+        let h = &self.header;
+        let type_offset = 12 + 4 * h.vlan_tags.len();
+        let mut children = vec![
+            Field::new("eth.dst", h._dst).at(0, 6),
+            Field::new("eth.src", h._src).at(6, 6),
+            Field::new("eth.addr", h._dst).at(0, 6),
+            Field::new("eth.addr", h._src).at(6, 6),
+        ];
+        if h.llc.is_none() {
+            children.push(Field::new("eth.type", h._type_len).at(type_offset, 2));
+        }
+        let mut ans = vec![Field::protocol("eth")
+            .at(0, type_offset + 2)
+            .with_children(children)];
+        for (i, tag) in h.vlan_tags.iter().enumerate() {
+            let offset = 12 + 4 * i;
+            ans.push(Field::protocol("vlan").at(offset, 4).with_children(vec![
+                Field::new("vlan.priority", tag._pcp).at(offset + 2, 1),
+                Field::new("vlan.dei", tag._dei).at(offset + 2, 1),
+                Field::new("vlan.id", tag.vid).at(offset + 2, 2),
+            ]));
+        }
+        let mut payload_offset = type_offset + 2;
+        if let Some(llc) = &h.llc {
+            ans.append(shifted(llc.fields(), payload_offset).as_mut());
+            payload_offset += llc.header_len();
+        }
+        match &self.data {
+            Some(data) => ans.append(shifted(data.fields(), payload_offset).as_mut()),
+            None => ans.push(
+                Field::new("data.data", self._payload.as_slice())
+                    .at(payload_offset, self._payload.len()),
+            ),
+        }
+        ans
+    }
+}
+
+fn read_vlan_tag(read: &mut impl Read, tpid: u16) -> Result<VlanTag> {
+    let tci = read.read_u16::<NetworkEndian>()?;
+    Ok(VlanTag {
+        _tpid: tpid,
+        _pcp: (tci >> 13) as u8,
+        _dei: tci & 0x1000 != 0,
+        vid: tci & 0x0fff,
+    })
+}
+
+pub fn read_ethernet(
+    mut read: std::collections::VecDeque<u8>,
+    ctx: &mut Context,
+) -> Result<Ethernet> {
+    let mut dst = [0u8; 6];
+    let mut src = [0u8; 6];
+    read.read_exact(&mut dst).context("read dst")?;
+    read.read_exact(&mut src).context("read src")?;
+    let mut type_len = read.read_u16::<NetworkEndian>().context("read type_len")?;
+    let mut vlan_tags = vec![];
+    while matches!(type_len, TPID_8021Q | TPID_8021AD | TPID_QINQ) {
+        vlan_tags.push(read_vlan_tag(&mut read, type_len).context("read vlan tag")?);
+        type_len = read.read_u16::<NetworkEndian>().context("read type_len")?;
+    }
+    let mut header = FrameHeader {
+        _dst: Macaddr(dst),
+        _src: Macaddr(src),
+        vlan_tags,
+        _type_len: type_len,
+        llc: None,
+    };
+    let ethertype = if type_len <= MAX_8023_LENGTH {
+        // drop the padding of short 802.3 frames
+        read.truncate(type_len as usize);
+        let llc = read_llc(&mut read).context("read llc")?;
+        let ethertype = llc.ethertype();
+        header.llc = Some(llc);
+        ethertype
+    } else {
+        Some(type_len)
+    };
+    let data = match ethertype {
+        Some(x) => Some(read_l3data(read, x, ctx).context("read data")?),
+        None => {
+            return Ok(Ethernet {
+                header,
+                data: None,
+                _payload: Vec::from(read),
+            })
+        }
+    };
+    Ok(Ethernet {
+        header,
+        data,
+        _payload: vec![],
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_qinq_ethernet() {
+        let mut bytes = vec![0xffu8; 6];
+        bytes.extend_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        bytes.extend_from_slice(&[0x88, 0xa8, 0xa0, 0x64]);
+        bytes.extend_from_slice(&[0x81, 0x00, 0x00, 0xc8]);
+        bytes.extend_from_slice(&[0x12, 0x34, 0xde, 0xad]);
+        let ethernet = read_ethernet(bytes.into(), &mut Context::default()).unwrap();
+        assert_eq!(ethernet.header.vlan_tags.len(), 2);
+        assert_eq!(ethernet.header.vlan_tags[0]._pcp, 5);
+        assert_eq!(ethernet.line(), "VLAN 100/200 type(4660)");
+    }
+
+    #[test]
+    fn test_read_8023_stp() {
+        let mut bytes = vec![0x01u8, 0x80, 0xc2, 0x00, 0x00, 0x00];
+        bytes.extend_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        bytes.extend_from_slice(&[0x00, 0x05, 0x42, 0x42, 0x03, 0x00, 0x00, 0x00, 0x00]);
+        let ethernet = read_ethernet(bytes.into(), &mut Context::default()).unwrap();
+        assert_eq!(ethernet.line(), "LLC dsap=0x42 ssap=0x42");
+        assert_eq!(ethernet._payload, vec![0x00, 0x00]);
+    }
+
+    #[test]
+    fn test_read_8023_cdp() {
+        let mut bytes = vec![0x01u8, 0x00, 0x0c, 0xcc, 0xcc, 0xcc];
+        bytes.extend_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x0a]);
+        bytes.extend_from_slice(&[0xaa, 0xaa, 0x03, 0x00, 0x00, 0x0c, 0x20, 0x00, 0x02, 0xb4]);
+        let ethernet = read_ethernet(bytes.into(), &mut Context::default()).unwrap();
+        assert!(ethernet.data.is_none());
+        assert_eq!(ethernet.line(), "LLC dsap=0xaa ssap=0xaa");
+        assert_eq!(ethernet._payload, vec![0x02, 0xb4]);
+    }
+}
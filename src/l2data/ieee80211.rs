@@ -0,0 +1,226 @@
+use crate::context::Context;
+use crate::field::{shifted, Field, FieldInfo, FieldType};
+use crate::l2data::llc::{read_llc, Llc};
+use crate::l2data::{L2data, Macaddr};
+use crate::l3data::{read_l3data, L3data};
+use anyhow::{Context as _, Result};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::Read;
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("wlan", "IEEE 802.11", FieldType::Protocol),
+    FieldInfo::new("wlan.fc.type", "Type", FieldType::Int),
+    FieldInfo::new("wlan.fc.subtype", "Subtype", FieldType::Int),
+    FieldInfo::new("wlan.addr", "Address", FieldType::Mac),
+];
+
+const TYPE_MANAGEMENT: u8 = 0;
+const TYPE_CONTROL: u8 = 1;
+const TYPE_DATA: u8 = 2;
+
+const FLAG_TO_DS: u8 = 0x01;
+const FLAG_FROM_DS: u8 = 0x02;
+const FLAG_PROTECTED: u8 = 0x40;
+const FLAG_ORDER: u8 = 0x80;
+
+#[derive(Debug)]
+struct Ieee80211Header {
+    frame_type: u8,
+    subtype: u8,
+    _flags: u8,
+    _duration: u16,
+    _addr1: Macaddr,
+    _addr2: Option<Macaddr>,
+    _addr3: Option<Macaddr>,
+    _sequence_control: Option<u16>,
+    _addr4: Option<Macaddr>,
+    _qos_control: Option<u16>,
+    llc: Option<Llc>,
+}
+
+impl Ieee80211Header {
+    fn subtype_name(&self) -> String {
+        let name = match (self.frame_type, self.subtype) {
+            (TYPE_MANAGEMENT, 0) => "Association Request",
+            (TYPE_MANAGEMENT, 1) => "Association Response",
+            (TYPE_MANAGEMENT, 2) => "Reassociation Request",
+            (TYPE_MANAGEMENT, 3) => "Reassociation Response",
+            (TYPE_MANAGEMENT, 4) => "Probe Request",
+            (TYPE_MANAGEMENT, 5) => "Probe Response",
+            (TYPE_MANAGEMENT, 8) => "Beacon",
+            (TYPE_MANAGEMENT, 9) => "ATIM",
+            (TYPE_MANAGEMENT, 10) => "Disassociation",
+            (TYPE_MANAGEMENT, 11) => "Authentication",
+            (TYPE_MANAGEMENT, 12) => "Deauthentication",
+            (TYPE_MANAGEMENT, 13) => "Action",
+            (TYPE_CONTROL, 8) => "Block Ack Request",
+            (TYPE_CONTROL, 9) => "Block Ack",
+            (TYPE_CONTROL, 10) => "PS-Poll",
+            (TYPE_CONTROL, 11) => "RTS",
+            (TYPE_CONTROL, 12) => "CTS",
+            (TYPE_CONTROL, 13) => "ACK",
+            (TYPE_CONTROL, 14) => "CF-End",
+            (TYPE_DATA, 0) => "Data",
+            (TYPE_DATA, 4) => "Null function",
+            (TYPE_DATA, 8) => "QoS Data",
+            (TYPE_DATA, 12) => "QoS Null function",
+            _ => {
+                return format!("type({}) subtype({})", self.frame_type, self.subtype);
+            }
+        };
+        name.to_string()
+    }
+}
+
+#[derive(Debug)]
+pub struct Ieee80211 {
+    header: Ieee80211Header,
+    data: Option<Box<dyn L3data>>,
+    _payload: Vec<u8>,
+    // where data or the payload starts, past the header and any LLC
+    payload_offset: usize,
+}
+
+impl L2data for Ieee80211 {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("{:?}", self.header)];
+        match &self.data {
+            Some(data) => ans.append(data.text().as_mut()),
+            None => ans.push(format!("802.11 payload {:x?}", self._payload)),
+        }
+        ans
+    }
+    fn line(&self) -> String {
+        match (&self.data, &self.header.llc) {
+            (Some(data), _) => data.line(),
+            (None, Some(llc)) => llc.line(),
+            (None, None) => format!("802.11 {}", self.header.subtype_name()),
+        }
+    }
+    fn fields(&self) -> Vec<Field> {
+        let h = &self.header;
+        let mut children = vec![
+            Field::new("wlan.fc.type", h.frame_type).at(0, 1),
+            Field::new("wlan.fc.subtype", h.subtype).at(0, 1),
+        ];
+        for (addr, offset) in [
+            (Some(h._addr1), 4),
+            (h._addr2, 10),
+            (h._addr3, 16),
+            (h._addr4, 24),
+        ] {
+            if let Some(addr) = addr {
+                children.push(Field::new("wlan.addr", addr).at(offset, 6));
+            }
+        }
+        let llc_len = h.llc.as_ref().map_or(0, |x| x.header_len());
+        let header_len = self.payload_offset - llc_len;
+        let mut ans = vec![Field::protocol("wlan")
+            .at(0, header_len)
+            .with_children(children)];
+        if let Some(llc) = &h.llc {
+            ans.append(shifted(llc.fields(), header_len).as_mut());
+        }
+        match &self.data {
+            Some(data) => ans.append(shifted(data.fields(), self.payload_offset).as_mut()),
+            None => ans.push(
+                Field::new("data.data", self._payload.as_slice())
+                    .at(self.payload_offset, self._payload.len()),
+            ),
+        }
+        ans
+    }
+}
+
+fn read_macaddr(read: &mut impl Read) -> Result<Macaddr> {
+    let mut addr = [0u8; 6];
+    read.read_exact(&mut addr)?;
+    Ok(Macaddr(addr))
+}
+
+pub fn read_ieee80211(
+    mut read: std::collections::VecDeque<u8>,
+    has_fcs: bool,
+    ctx: &mut Context,
+) -> Result<Ieee80211> {
+    if has_fcs {
+        read.truncate(read.len().saturating_sub(4));
+    }
+    let start = read.len();
+    let frame_control = read
+        .read_u16::<LittleEndian>()
+        .context("read frame_control")?;
+    let frame_type = ((frame_control >> 2) & 0x3) as u8;
+    let subtype = ((frame_control >> 4) & 0xf) as u8;
+    let flags = (frame_control >> 8) as u8;
+    let duration = read.read_u16::<LittleEndian>().context("read duration")?;
+    let addr1 = read_macaddr(&mut read).context("read addr1")?;
+    let mut header = Ieee80211Header {
+        frame_type,
+        subtype,
+        _flags: flags,
+        _duration: duration,
+        _addr1: addr1,
+        _addr2: None,
+        _addr3: None,
+        _sequence_control: None,
+        _addr4: None,
+        _qos_control: None,
+        llc: None,
+    };
+    if frame_type == TYPE_CONTROL {
+        // CTS and ACK only carry the receiver address
+        if subtype != 12 && subtype != 13 {
+            header._addr2 = Some(read_macaddr(&mut read).context("read addr2")?);
+        }
+        let payload_offset = start - read.len();
+        return Ok(Ieee80211 {
+            header,
+            data: None,
+            _payload: Vec::from(read),
+            payload_offset,
+        });
+    }
+    header._addr2 = Some(read_macaddr(&mut read).context("read addr2")?);
+    header._addr3 = Some(read_macaddr(&mut read).context("read addr3")?);
+    header._sequence_control = Some(
+        read.read_u16::<LittleEndian>()
+            .context("read sequence_control")?,
+    );
+    if frame_type == TYPE_DATA {
+        if flags & FLAG_TO_DS != 0 && flags & FLAG_FROM_DS != 0 {
+            header._addr4 = Some(read_macaddr(&mut read).context("read addr4")?);
+        }
+        let qos = subtype & 0x8 != 0;
+        if qos {
+            header._qos_control = Some(read.read_u16::<LittleEndian>().context("read qos")?);
+            if flags & FLAG_ORDER != 0 {
+                let _ht_control = read.read_u32::<LittleEndian>().context("read ht_control")?;
+            }
+        }
+        let null_function = subtype & 0x4 != 0;
+        if flags & FLAG_PROTECTED == 0 && !null_function && !read.is_empty() {
+            let llc = read_llc(&mut read).context("read llc")?;
+            let ethertype = llc.ethertype();
+            header.llc = Some(llc);
+            if let Some(ethertype) = ethertype {
+                let payload_offset = start - read.len();
+                let data = read_l3data(read, ethertype, ctx).context("read data")?;
+                return Ok(Ieee80211 {
+                    header,
+                    data: Some(data),
+                    _payload: vec![],
+                    payload_offset,
+                });
+            }
+        }
+    }
+    let payload_offset = start - read.len();
+    Ok(Ieee80211 {
+        header,
+        data: None,
+        _payload: Vec::from(read),
+        payload_offset,
+    })
+}
@@ -0,0 +1,88 @@
+use crate::field::{Field, FieldInfo, FieldType};
+use anyhow::{Context, Result};
+use byteorder::{NetworkEndian, ReadBytesExt};
+use std::io::Read;
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("llc", "Logical-Link Control", FieldType::Protocol),
+    FieldInfo::new("llc.dsap", "DSAP", FieldType::Int).hex(),
+    FieldInfo::new("llc.ssap", "SSAP", FieldType::Int).hex(),
+    FieldInfo::new("llc.pid", "PID", FieldType::Int).hex(),
+];
+
+// RFC 1042 and 802.1H
+const OUI_ENCAPSULATED_ETHERNET: [u8; 3] = [0x00, 0x00, 0x00];
+const OUI_BRIDGE_TUNNEL: [u8; 3] = [0x00, 0x00, 0xf8];
+
+#[derive(Debug)]
+pub struct Snap {
+    oui: [u8; 3],
+    protocol: u16,
+}
+
+#[derive(Debug)]
+pub struct Llc {
+    dsap: u8,
+    ssap: u8,
+    _control: u16,
+    snap: Option<Snap>,
+    header_len: usize,
+}
+
+impl Llc {
+    /// EtherType carried in a SNAP header, if any. Other organizations
+    /// number their own protocols, e.g. 00:00:0c for Cisco's CDP.
+    pub fn ethertype(&self) -> Option<u16> {
+        self.snap
+            .as_ref()
+            .filter(|x| matches!(x.oui, OUI_ENCAPSULATED_ETHERNET | OUI_BRIDGE_TUNNEL))
+            .map(|x| x.protocol)
+    }
+    pub fn line(&self) -> String {
+        format!("LLC dsap=0x{:02x} ssap=0x{:02x}", self.dsap, self.ssap)
+    }
+    pub fn header_len(&self) -> usize {
+        self.header_len
+    }
+    pub fn fields(&self) -> Vec<Field> {
+        let mut children = vec![
+            Field::new("llc.dsap", self.dsap).at(0, 1),
+            Field::new("llc.ssap", self.ssap).at(1, 1),
+        ];
+        if let Some(snap) = &self.snap {
+            children.push(Field::new("llc.pid", snap.protocol).at(self.header_len - 2, 2));
+        }
+        vec![Field::protocol("llc")
+            .at(0, self.header_len)
+            .with_children(children)]
+    }
+}
+
+pub fn read_llc(read: &mut std::collections::VecDeque<u8>) -> Result<Llc> {
+    let start = read.len();
+    let dsap = read.read_u8().context("read dsap")?;
+    let ssap = read.read_u8().context("read ssap")?;
+    let control = read.read_u8().context("read control")?;
+    // information and supervisory frames have a two byte control field
+    let control = if control & 0x3 != 0x3 {
+        (control as u16) << 8 | read.read_u8().context("read control")? as u16
+    } else {
+        control as u16
+    };
+    let snap = if dsap == 0xaa && ssap == 0xaa {
+        let mut oui = [0u8; 3];
+        read.read_exact(&mut oui).context("read oui")?;
+        let protocol = read.read_u16::<NetworkEndian>().context("read protocol")?;
+        Some(Snap { oui, protocol })
+    } else {
+        None
+    };
+    Ok(Llc {
+        dsap,
+        ssap,
+        _control: control,
+        snap,
+        header_len: start - read.len(),
+    })
+}
@@ -0,0 +1,74 @@
+use crate::context::Context;
+use crate::field::{shifted, Field, FieldInfo, FieldType};
+use crate::l2data::L2data;
+use crate::l3data::{read_l3data, L3data, ETHERTYPE_IPV4, ETHERTYPE_IPV6};
+use anyhow::{Context as _, Result};
+use byteorder::{LittleEndian, NetworkEndian, ReadBytesExt};
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("null", "Null/Loopback", FieldType::Protocol),
+    FieldInfo::new("null.family", "Family", FieldType::Int),
+];
+
+#[derive(Debug)]
+struct NullHeader {
+    _family: u32,
+}
+
+#[derive(Debug)]
+pub struct Null {
+    header: NullHeader,
+    data: Box<dyn L3data>,
+}
+
+impl L2data for Null {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("{:?}", self.header)];
+        ans.append(self.data.text().as_mut());
+        ans
+    }
+    fn line(&self) -> String {
+        self.data.line()
+    }
+    fn fields(&self) -> Vec<Field> {
+        let mut ans = vec![Field::protocol("null")
+            .at(0, 4)
+            .with_children(vec![Field::new("null.family", self.header._family).at(0, 4)])];
+        ans.append(shifted(self.data.fields(), 4).as_mut());
+        ans
+    }
+}
+
+/// LINKTYPE_NULL stores the address family in the capturing host's byte
+/// order, LINKTYPE_LOOP always in network byte order.
+pub fn read_null(
+    mut read: std::collections::VecDeque<u8>,
+    network_order: bool,
+    ctx: &mut Context,
+) -> Result<Null> {
+    let family = if network_order {
+        read.read_u32::<NetworkEndian>()
+    } else {
+        read.read_u32::<LittleEndian>()
+    }
+    .context("read family")?;
+    // a host order family written on a big endian host has its value in the
+    // high bytes
+    let family = if family > 0xffff {
+        family.swap_bytes()
+    } else {
+        family
+    };
+    // AF_INET6 differs between BSDs (24, 28, 30)
+    let ethertype = match family {
+        2 => ETHERTYPE_IPV4,
+        24 | 28 | 30 => ETHERTYPE_IPV6,
+        _ => 0,
+    };
+    let data = read_l3data(read, ethertype, ctx).context("read data")?;
+    Ok(Null {
+        header: NullHeader { _family: family },
+        data,
+    })
+}
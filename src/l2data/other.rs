@@ -0,0 +1,54 @@
+use crate::field::Field;
+use crate::l2data::L2data;
+use anyhow::Result;
+
+#[derive(Debug)]
+pub struct OtherL2data {
+    link_type: u32,
+    payload: Vec<u8>,
+    truncated: bool,
+}
+
+impl L2data for OtherL2data {
+    fn text(&self) -> Vec<String> {
+        vec![format!("L2Data {:x?}", self.payload)]
+    }
+    fn line(&self) -> String {
+        if self.truncated {
+            return format!("linktype({}) [truncated]", self.link_type);
+        }
+        format!("linktype({}) {} bytes", self.link_type, self.payload.len())
+    }
+    fn fields(&self) -> Vec<Field> {
+        let name = if self.truncated { "truncated" } else { "data" };
+        vec![Field::protocol(name)
+            .at(0, self.payload.len())
+            .with_children(vec![
+                Field::new("data.data", self.payload.as_slice()).at(0, self.payload.len())
+            ])]
+    }
+}
+
+pub fn read_otherl2data(
+    read: std::collections::VecDeque<u8>,
+    link_type: u32,
+) -> Result<OtherL2data> {
+    let payload = Vec::from(read);
+    Ok(OtherL2data {
+        link_type,
+        payload,
+        truncated: false,
+    })
+}
+
+/// Keeps the bytes of a packet cut short before its link layer header ended.
+pub(crate) fn read_truncated_l2data(
+    read: std::collections::VecDeque<u8>,
+    link_type: u32,
+) -> OtherL2data {
+    OtherL2data {
+        link_type,
+        payload: Vec::from(read),
+        truncated: true,
+    }
+}
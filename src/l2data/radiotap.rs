@@ -0,0 +1,166 @@
+use crate::context::Context;
+use crate::field::{shifted, Field, FieldInfo, FieldType};
+use crate::l2data::ieee80211::{read_ieee80211, Ieee80211};
+use crate::l2data::L2data;
+use anyhow::{bail, Context as _, Result};
+use byteorder::{LittleEndian, ReadBytesExt};
+use std::io::Read;
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("radiotap", "Radiotap Header", FieldType::Protocol),
+    FieldInfo::new("radiotap.channel.freq", "Channel frequency", FieldType::Int),
+    FieldInfo::new("radiotap.datarate", "Data rate (500 kbps)", FieldType::Int),
+];
+
+const FLAG_FCS: u8 = 0x10;
+
+// (size, alignment) of the radiotap fields in the default namespace
+const FIELD_LAYOUT: [(usize, usize); 23] = [
+    (8, 8),  // TSFT
+    (1, 1),  // Flags
+    (1, 1),  // Rate
+    (4, 2),  // Channel
+    (2, 1),  // FHSS
+    (1, 1),  // Antenna signal (dBm)
+    (1, 1),  // Antenna noise (dBm)
+    (2, 2),  // Lock quality
+    (2, 2),  // TX attenuation
+    (2, 2),  // TX attenuation (dB)
+    (1, 1),  // TX power (dBm)
+    (1, 1),  // Antenna
+    (1, 1),  // Antenna signal (dB)
+    (1, 1),  // Antenna noise (dB)
+    (2, 2),  // RX flags
+    (2, 2),  // TX flags
+    (1, 1),  // RTS retries
+    (1, 1),  // Data retries
+    (8, 4),  // XChannel
+    (3, 1),  // MCS
+    (8, 4),  // A-MPDU status
+    (12, 2), // VHT
+    (12, 8), // Timestamp
+];
+
+#[derive(Debug, Default)]
+struct RadiotapHeader {
+    _version: u8,
+    _len: u16,
+    _present: u32,
+    _tsft: Option<u64>,
+    flags: Option<u8>,
+    // in 500 kbps units
+    _rate: Option<u8>,
+    _channel_freq: Option<u16>,
+    _channel_flags: Option<u16>,
+    _antenna_signal: Option<i8>,
+    _antenna_noise: Option<i8>,
+    _antenna: Option<u8>,
+    // (bit, offset) of every field that was read
+    field_offsets: Vec<(usize, usize)>,
+}
+
+#[derive(Debug)]
+pub struct Radiotap {
+    header: RadiotapHeader,
+    data: Ieee80211,
+}
+
+impl L2data for Radiotap {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("{:?}", self.header)];
+        ans.append(self.data.text().as_mut());
+        ans
+    }
+    fn line(&self) -> String {
+        self.data.line()
+    }
+    fn fields(&self) -> Vec<Field> {
+        let h = &self.header;
+        let offset = |bit| {
+            h.field_offsets
+                .iter()
+                .find(|(x, _)| *x == bit)
+                .map_or(0, |(_, offset)| *offset)
+        };
+        let mut children = vec![];
+        if let Some(x) = h._rate {
+            children.push(Field::new("radiotap.datarate", x).at(offset(2), 1));
+        }
+        if let Some(x) = h._channel_freq {
+            children.push(Field::new("radiotap.channel.freq", x).at(offset(3), 2));
+        }
+        let mut ans = vec![Field::protocol("radiotap")
+            .at(0, h._len as usize)
+            .with_children(children)];
+        ans.append(shifted(self.data.fields(), h._len as usize).as_mut());
+        ans
+    }
+}
+
+fn read_radiotap_fields(header: &mut RadiotapHeader, buf: &[u8], mut offset: usize) -> Result<()> {
+    for (bit, (size, align)) in FIELD_LAYOUT.iter().enumerate() {
+        if header._present & (1 << bit) == 0 {
+            continue;
+        }
+        offset = offset.div_ceil(*align) * align;
+        if offset + size > buf.len() {
+            bail!("radiotap field {} overruns header", bit);
+        }
+        let mut field = &buf[offset..offset + size];
+        match bit {
+            0 => header._tsft = Some(field.read_u64::<LittleEndian>()?),
+            1 => header.flags = Some(field.read_u8()?),
+            2 => header._rate = Some(field.read_u8()?),
+            3 => {
+                header._channel_freq = Some(field.read_u16::<LittleEndian>()?);
+                header._channel_flags = Some(field.read_u16::<LittleEndian>()?);
+            }
+            5 => header._antenna_signal = Some(field.read_i8()?),
+            6 => header._antenna_noise = Some(field.read_i8()?),
+            11 => header._antenna = Some(field.read_u8()?),
+            _ => {}
+        }
+        header.field_offsets.push((bit, offset));
+        offset += size;
+    }
+    Ok(())
+}
+
+pub fn read_radiotap(
+    mut read: std::collections::VecDeque<u8>,
+    ctx: &mut Context,
+) -> Result<Radiotap> {
+    let version = read.read_u8().context("read version")?;
+    let _pad = read.read_u8().context("read pad")?;
+    let len = read.read_u16::<LittleEndian>().context("read len")?;
+    if len < 8 {
+        bail!("invalid radiotap length {}", len);
+    }
+    let mut buf = vec![0u8; len as usize];
+    read.read_exact(&mut buf[4..]).context("read header")?;
+    let mut header = RadiotapHeader {
+        _version: version,
+        _len: len,
+        ..Default::default()
+    };
+    let mut present_slice = &buf[4..];
+    header._present = present_slice.read_u32::<LittleEndian>()?;
+    // skip extended presence bitmaps, the fields of the first namespace come
+    // right after the last one
+    let mut offset = 8;
+    let mut present = header._present;
+    while present & (1 << 31) != 0 {
+        present = present_slice
+            .read_u32::<LittleEndian>()
+            .context("read extended present")?;
+        offset += 4;
+    }
+    // fields beyond the ones we know have unknown size, stop parsing there
+    if header._present & 0x1f80_0000 == 0 {
+        read_radiotap_fields(&mut header, &buf, offset).context("read radiotap fields")?;
+    }
+    let has_fcs = header.flags.unwrap_or(0) & FLAG_FCS != 0;
+    let data = read_ieee80211(read, has_fcs, ctx).context("read ieee802.11")?;
+    Ok(Radiotap { header, data })
+}
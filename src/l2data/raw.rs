@@ -0,0 +1,47 @@
+use crate::context::Context;
+use crate::field::{Field, FieldInfo, FieldType};
+use crate::l2data::other::read_otherl2data;
+use crate::l2data::L2data;
+use crate::l3data::{read_l3data, L3data, ETHERTYPE_IPV4, ETHERTYPE_IPV6};
+use anyhow::{Context as _, Result};
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[FieldInfo::new(
+    "raw",
+    "Raw packet data",
+    FieldType::Protocol,
+)];
+
+#[derive(Debug)]
+pub struct Raw {
+    data: Box<dyn L3data>,
+}
+
+impl L2data for Raw {
+    fn text(&self) -> Vec<String> {
+        self.data.text()
+    }
+    fn line(&self) -> String {
+        self.data.line()
+    }
+    fn fields(&self) -> Vec<Field> {
+        let mut ans = vec![Field::protocol("raw")];
+        ans.append(self.data.fields().as_mut());
+        ans
+    }
+}
+
+pub fn read_raw(
+    read: std::collections::VecDeque<u8>,
+    link_type: u32,
+    ctx: &mut Context,
+) -> Result<Box<dyn L2data>> {
+    let version = read.front().context("read version")? >> 4;
+    let ethertype = match version {
+        4 => ETHERTYPE_IPV4,
+        6 => ETHERTYPE_IPV6,
+        _ => return Ok(Box::new(read_otherl2data(read, link_type)?)),
+    };
+    let data = read_l3data(read, ethertype, ctx).context("read data")?;
+    Ok(Box::new(Raw { data }))
+}
@@ -0,0 +1,72 @@
+use crate::context::Context;
+use crate::field::{shifted, Field, FieldInfo, FieldType};
+use crate::l2data::L2data;
+use crate::l3data::{read_l3data, L3data};
+use anyhow::{Context as _, Result};
+use byteorder::{NetworkEndian, ReadBytesExt};
+use std::io::Read;
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("sll", "Linux cooked capture", FieldType::Protocol),
+    FieldInfo::new("sll.pkttype", "Packet type", FieldType::Int),
+    FieldInfo::new("sll.hatype", "Link-layer address type", FieldType::Int),
+    FieldInfo::new("sll.ifindex", "Interface index", FieldType::Int),
+    FieldInfo::new("sll.etype", "Protocol", FieldType::Int).hex(),
+];
+
+#[derive(Debug)]
+struct SllHeader {
+    _packet_type: u16,
+    _arphrd_type: u16,
+    _addr: Vec<u8>,
+    _protocol: u16,
+}
+
+#[derive(Debug)]
+pub struct Sll {
+    header: SllHeader,
+    data: Box<dyn L3data>,
+}
+
+impl L2data for Sll {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("{:?}", self.header)];
+        ans.append(self.data.text().as_mut());
+        ans
+    }
+    fn line(&self) -> String {
+        self.data.line()
+    }
+    fn fields(&self) -> Vec<Field> {
+        let h = &self.header;
+        let mut ans = vec![Field::protocol("sll").at(0, 16).with_children(vec![
+            Field::new("sll.pkttype", h._packet_type).at(0, 2),
+            Field::new("sll.hatype", h._arphrd_type).at(2, 2),
+            Field::new("sll.etype", h._protocol).at(14, 2),
+        ])];
+        ans.append(shifted(self.data.fields(), 16).as_mut());
+        ans
+    }
+}
+
+pub fn read_sll(mut read: std::collections::VecDeque<u8>, ctx: &mut Context) -> Result<Sll> {
+    let packet_type = read
+        .read_u16::<NetworkEndian>()
+        .context("read packet_type")?;
+    let arphrd_type = read
+        .read_u16::<NetworkEndian>()
+        .context("read arphrd_type")?;
+    let addr_len = read.read_u16::<NetworkEndian>().context("read addr_len")?;
+    let mut addr = [0u8; 8];
+    read.read_exact(&mut addr).context("read addr")?;
+    let protocol = read.read_u16::<NetworkEndian>().context("read protocol")?;
+    let header = SllHeader {
+        _packet_type: packet_type,
+        _arphrd_type: arphrd_type,
+        _addr: addr[..std::cmp::min(addr_len as usize, 8)].to_vec(),
+        _protocol: protocol,
+    };
+    let data = read_l3data(read, protocol, ctx).context("read data")?;
+    Ok(Sll { header, data })
+}
@@ -0,0 +1,68 @@
+use crate::context::Context;
+use crate::field::{shifted, Field};
+use crate::l2data::L2data;
+use crate::l3data::{read_l3data, L3data};
+use anyhow::{Context as _, Result};
+use byteorder::{NetworkEndian, ReadBytesExt};
+use std::io::Read;
+
+#[derive(Debug)]
+struct Sll2Header {
+    _protocol: u16,
+    _interface_index: u32,
+    _arphrd_type: u16,
+    _packet_type: u8,
+    _addr: Vec<u8>,
+}
+
+#[derive(Debug)]
+pub struct Sll2 {
+    header: Sll2Header,
+    data: Box<dyn L3data>,
+}
+
+impl L2data for Sll2 {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("{:?}", self.header)];
+        ans.append(self.data.text().as_mut());
+        ans
+    }
+    fn line(&self) -> String {
+        self.data.line()
+    }
+    fn fields(&self) -> Vec<Field> {
+        let h = &self.header;
+        let mut ans = vec![Field::protocol("sll").at(0, 20).with_children(vec![
+            Field::new("sll.etype", h._protocol).at(0, 2),
+            Field::new("sll.ifindex", h._interface_index).at(4, 4),
+            Field::new("sll.hatype", h._arphrd_type).at(8, 2),
+            Field::new("sll.pkttype", h._packet_type).at(10, 1),
+        ])];
+        ans.append(shifted(self.data.fields(), 20).as_mut());
+        ans
+    }
+}
+
+pub fn read_sll2(mut read: std::collections::VecDeque<u8>, ctx: &mut Context) -> Result<Sll2> {
+    let protocol = read.read_u16::<NetworkEndian>().context("read protocol")?;
+    let _reserved = read.read_u16::<NetworkEndian>().context("read reserved")?;
+    let interface_index = read
+        .read_u32::<NetworkEndian>()
+        .context("read interface_index")?;
+    let arphrd_type = read
+        .read_u16::<NetworkEndian>()
+        .context("read arphrd_type")?;
+    let packet_type = read.read_u8().context("read packet_type")?;
+    let addr_len = read.read_u8().context("read addr_len")?;
+    let mut addr = [0u8; 8];
+    read.read_exact(&mut addr).context("read addr")?;
+    let header = Sll2Header {
+        _protocol: protocol,
+        _interface_index: interface_index,
+        _arphrd_type: arphrd_type,
+        _packet_type: packet_type,
+        _addr: addr[..std::cmp::min(addr_len as usize, 8)].to_vec(),
+    };
+    let data = read_l3data(read, protocol, ctx).context("read data")?;
+    Ok(Sll2 { header, data })
+}
@@ -1,20 +1,75 @@
+pub mod arp;
 pub mod ipv4;
 pub mod ipv4data;
+pub mod ipv6;
 pub mod other;
+pub mod reassembly;
 
-use anyhow::{Context, Result};
+use crate::context::Context;
+use crate::field::Field;
+use crate::l7data::L7data;
+use anyhow::{Context as _, Result};
+use arp::read_arp;
 use ipv4::read_ipv4;
-use other::read_otherl3data;
+use ipv6::read_ipv6;
+use other::{read_otherl3data, read_truncated_l3data};
 use std::fmt::Debug;
 
+pub const ETHERTYPE_IPV4: u16 = 0x0800;
+pub const ETHERTYPE_ARP: u16 = 0x0806;
+pub const ETHERTYPE_RARP: u16 = 0x8035;
+pub const ETHERTYPE_IPV6: u16 = 0x86dd;
+
 pub trait L3data: Debug + Send + Sync {
     fn text(&self) -> Vec<String>;
     fn line(&self) -> String;
+    fn fields(&self) -> Vec<Field>;
+}
+
+/// Network layer decoded by a dissector registered for the EtherType.
+#[derive(Debug)]
+struct Registered(Box<dyn L7data>);
+
+impl L3data for Registered {
+    fn text(&self) -> Vec<String> {
+        self.0.text()
+    }
+    fn line(&self) -> String {
+        self.0.line()
+    }
+    fn fields(&self) -> Vec<Field> {
+        self.0.fields()
+    }
 }
 
-pub fn read_l3data(read: std::collections::VecDeque<u8>, _type: u16) -> Result<Box<dyn L3data>> {
+pub fn read_l3data(
+    read: std::collections::VecDeque<u8>,
+    _type: u16,
+    ctx: &mut Context,
+) -> Result<Box<dyn L3data>> {
+    ctx.or_truncated(
+        read,
+        |read, ctx| dissect_l3data(read, _type, ctx),
+        |read| Box::new(read_truncated_l3data(read, _type)),
+    )
+}
+
+fn dissect_l3data(
+    read: std::collections::VecDeque<u8>,
+    _type: u16,
+    ctx: &mut Context,
+) -> Result<Box<dyn L3data>> {
+    let registered = ctx.registry().by_ethertype(_type);
+    if let Some(dissector) = registered {
+        let data = dissector
+            .dissect(&Vec::from(read), ctx)
+            .with_context(|| format!("read {}", dissector.name()))?;
+        return Ok(Box::new(Registered(data)));
+    }
     match _type {
-        8 => Ok(Box::new(read_ipv4(read).context("read ipv4")?)),
+        ETHERTYPE_IPV4 => Ok(Box::new(read_ipv4(read, ctx).context("read ipv4")?)),
+        ETHERTYPE_IPV6 => Ok(Box::new(read_ipv6(read, ctx).context("read ipv6")?)),
+        ETHERTYPE_ARP | ETHERTYPE_RARP => Ok(Box::new(read_arp(read, ctx).context("read arp")?)),
         _ => Ok(Box::new(
             read_otherl3data(read, _type).context("read otherl3data")?,
         )),
@@ -0,0 +1,270 @@
+use crate::context::Context;
+use crate::field::{Field, FieldInfo, FieldType};
+use crate::l2data::Macaddr;
+use crate::l3data::L3data;
+use anyhow::{bail, Context as _, Result};
+use byteorder::{NetworkEndian, ReadBytesExt};
+use std::collections::HashMap;
+use std::io::Read;
+use std::net::Ipv4Addr;
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("arp", "Address Resolution Protocol", FieldType::Protocol),
+    FieldInfo::new("arp.opcode", "Opcode", FieldType::Int),
+    FieldInfo::new("arp.src.hw_mac", "Sender MAC address", FieldType::Mac),
+    FieldInfo::new("arp.src.proto_ipv4", "Sender IP address", FieldType::Addr),
+    FieldInfo::new("arp.dst.hw_mac", "Target MAC address", FieldType::Mac),
+    FieldInfo::new("arp.dst.proto_ipv4", "Target IP address", FieldType::Addr),
+];
+
+const HTYPE_ETHERNET: u16 = 1;
+const PTYPE_IPV4: u16 = 0x0800;
+
+#[derive(Debug, Clone)]
+pub enum ArpEvent {
+    Gratuitous,
+    DuplicateIp {
+        ip: Ipv4Addr,
+        other_mac: Macaddr,
+        other_frame: usize,
+    },
+    MacChanged {
+        ip: Ipv4Addr,
+        old_mac: Macaddr,
+        old_frame: usize,
+    },
+}
+
+impl std::fmt::Display for ArpEvent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            ArpEvent::Gratuitous => write!(f, "Gratuitous ARP"),
+            ArpEvent::DuplicateIp {
+                ip,
+                other_mac,
+                other_frame,
+            } => write!(
+                f,
+                "Duplicate use of {} detected (also in use by {} in frame {})",
+                ip, other_mac, other_frame
+            ),
+            ArpEvent::MacChanged {
+                ip,
+                old_mac,
+                old_frame,
+            } => write!(
+                f,
+                "MAC address of {} changed (was {} in frame {})",
+                ip, old_mac, old_frame
+            ),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct ArpBinding {
+    mac: Macaddr,
+    frame: usize,
+    // every MAC address that has claimed the IP, with the last frame it did
+    history: Vec<(Macaddr, usize)>,
+}
+
+/// IP to MAC bindings learned from ARP senders across the capture.
+#[derive(Debug, Default)]
+pub struct ArpTable {
+    bindings: HashMap<Ipv4Addr, ArpBinding>,
+}
+
+impl ArpTable {
+    fn learn(&mut self, ip: Ipv4Addr, mac: Macaddr, frame: usize) -> Option<ArpEvent> {
+        if ip.is_unspecified() {
+            return None;
+        }
+        let binding = self.bindings.entry(ip).or_insert_with(|| ArpBinding {
+            mac,
+            frame,
+            history: vec![(mac, frame)],
+        });
+        let event = if binding.mac == mac {
+            None
+        } else if binding.history.iter().any(|(x, _)| *x == mac) {
+            // two stations keep claiming the same address
+            Some(ArpEvent::DuplicateIp {
+                ip,
+                other_mac: binding.mac,
+                other_frame: binding.frame,
+            })
+        } else {
+            Some(ArpEvent::MacChanged {
+                ip,
+                old_mac: binding.mac,
+                old_frame: binding.frame,
+            })
+        };
+        binding.mac = mac;
+        binding.frame = frame;
+        match binding.history.iter_mut().find(|(x, _)| *x == mac) {
+            Some(x) => x.1 = frame,
+            None => binding.history.push((mac, frame)),
+        }
+        event
+    }
+}
+
+#[derive(Debug)]
+struct ArpHeader {
+    _htype: u16,
+    _ptype: u16,
+    opcode: u16,
+    sender_mac: Macaddr,
+    sender_ip: Ipv4Addr,
+    target_mac: Macaddr,
+    target_ip: Ipv4Addr,
+}
+
+#[derive(Debug)]
+pub struct Arp {
+    header: ArpHeader,
+    events: Vec<ArpEvent>,
+}
+
+impl L3data for Arp {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("{:?}", self.header)];
+        for event in &self.events {
+            ans.push(format!("Warning: {}", event));
+        }
+        ans
+    }
+    fn line(&self) -> String {
+        let h = &self.header;
+        let gratuitous = if self.is_gratuitous() {
+            "Gratuitous ARP "
+        } else {
+            ""
+        };
+        match h.opcode {
+            1 if h.sender_ip.is_unspecified() => format!("Who has {}? (ARP Probe)", h.target_ip),
+            1 if self.is_gratuitous() => format!("Gratuitous ARP for {} (Request)", h.target_ip),
+            1 => format!("Who has {}? Tell {}", h.target_ip, h.sender_ip),
+            2 => format!("{}{} is at {}", gratuitous, h.sender_ip, h.sender_mac),
+            3 => format!("Who is {}? Tell {}", h.target_mac, h.sender_mac),
+            4 => format!("{} is at {}", h.target_mac, h.target_ip),
+            8 => format!("Who is {}? Tell {}", h.target_mac, h.sender_ip),
+            9 => format!("{} is at {}", h.sender_mac, h.sender_ip),
+            x => format!("ARP opcode({})", x),
+        }
+    }
+    fn fields(&self) -> Vec<Field> {
+        let h = &self.header;
+        vec![Field::protocol("arp").at(0, 28).with_children(vec![
+            Field::new("arp.opcode", h.opcode).at(6, 2),
+            Field::new("arp.src.hw_mac", h.sender_mac).at(8, 6),
+            Field::new("arp.src.proto_ipv4", h.sender_ip).at(14, 4),
+            Field::new("arp.dst.hw_mac", h.target_mac).at(18, 6),
+            Field::new("arp.dst.proto_ipv4", h.target_ip).at(24, 4),
+        ])]
+    }
+}
+
+impl Arp {
+    fn is_gratuitous(&self) -> bool {
+        matches!(self.header.opcode, 1 | 2)
+            && !self.header.sender_ip.is_unspecified()
+            && self.header.sender_ip == self.header.target_ip
+    }
+}
+
+fn read_macaddr(read: &mut impl Read) -> Result<Macaddr> {
+    let mut addr = [0u8; 6];
+    read.read_exact(&mut addr)?;
+    Ok(Macaddr(addr))
+}
+
+fn read_ipv4addr(read: &mut impl Read) -> Result<Ipv4Addr> {
+    let mut addr = [0u8; 4];
+    read.read_exact(&mut addr)?;
+    Ok(addr.into())
+}
+
+pub fn read_arp(mut read: std::collections::VecDeque<u8>, ctx: &mut Context) -> Result<Arp> {
+    let htype = read.read_u16::<NetworkEndian>().context("read htype")?;
+    let ptype = read.read_u16::<NetworkEndian>().context("read ptype")?;
+    let hlen = read.read_u8().context("read hlen")?;
+    let plen = read.read_u8().context("read plen")?;
+    if htype != HTYPE_ETHERNET || ptype != PTYPE_IPV4 || hlen != 6 || plen != 4 {
+        bail!(
+            "unsupported arp htype {} ptype {:#06x} hlen {} plen {}",
+            htype,
+            ptype,
+            hlen,
+            plen
+        );
+    }
+    let opcode = read.read_u16::<NetworkEndian>().context("read opcode")?;
+    let header = ArpHeader {
+        _htype: htype,
+        _ptype: ptype,
+        opcode,
+        sender_mac: read_macaddr(&mut read).context("read sender mac")?,
+        sender_ip: read_ipv4addr(&mut read).context("read sender ip")?,
+        target_mac: read_macaddr(&mut read).context("read target mac")?,
+        target_ip: read_ipv4addr(&mut read).context("read target ip")?,
+    };
+    let mut arp = Arp {
+        header,
+        events: vec![],
+    };
+    if matches!(opcode, 1 | 2) {
+        if arp.is_gratuitous() {
+            arp.events.push(ArpEvent::Gratuitous);
+        }
+        let event = ctx
+            .arp
+            .learn(arp.header.sender_ip, arp.header.sender_mac, ctx.number());
+        arp.events.extend(event);
+    }
+    Ok(arp)
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    fn arp_bytes(opcode: u8, sender_mac: u8, sender_ip: [u8; 4], target_ip: [u8; 4]) -> Vec<u8> {
+        let mut bytes = vec![0x00, 0x01, 0x08, 0x00, 0x06, 0x04, 0x00, opcode];
+        bytes.extend_from_slice(&[0x02, 0x00, 0x00, 0x00, 0x00, sender_mac]);
+        bytes.extend_from_slice(&sender_ip);
+        bytes.extend_from_slice(&[0x00; 6]);
+        bytes.extend_from_slice(&target_ip);
+        bytes
+    }
+
+    #[test]
+    fn test_arp_spoofing_events() {
+        let mut ctx = Context::default();
+        let mut read = |bytes: Vec<u8>| {
+            ctx.begin(Default::default());
+            read_arp(bytes.into(), &mut ctx).unwrap()
+        };
+        let request = read(arp_bytes(1, 0x02, [10, 0, 0, 2], [10, 0, 0, 1]));
+        assert_eq!(request.line(), "Who has 10.0.0.1? Tell 10.0.0.2");
+        assert!(request.events.is_empty());
+        let reply = read(arp_bytes(2, 0x01, [10, 0, 0, 1], [10, 0, 0, 2]));
+        assert_eq!(reply.line(), "10.0.0.1 is at 02:00:00:00:00:01");
+        let spoofed = read(arp_bytes(2, 0x66, [10, 0, 0, 1], [10, 0, 0, 2]));
+        assert!(matches!(
+            spoofed.events[..],
+            [ArpEvent::MacChanged { old_frame: 2, .. }]
+        ));
+        let genuine = read(arp_bytes(2, 0x01, [10, 0, 0, 1], [10, 0, 0, 1]));
+        assert!(matches!(
+            genuine.events[..],
+            [
+                ArpEvent::Gratuitous,
+                ArpEvent::DuplicateIp { other_frame: 3, .. }
+            ]
+        ));
+    }
+}
@@ -1,18 +1,90 @@
+use crate::context::Context;
 use crate::l3data::ipv4data::Ipv4data;
 use crate::l3data::{L3data, Result};
+use anyhow::{bail, Context as _};
 use byteorder::{NetworkEndian, ReadBytesExt};
 use std::io::Read;
 
+use super::ipv4data::other::read_other;
 use super::ipv4data::read_ipv4data;
+use super::reassembly::{FragmentFields, Reassembly};
+use crate::checksum::{checksum_field, Checksum};
+use crate::field::{detached, shifted, Field, FieldInfo, FieldType};
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("ip", "Internet Protocol Version 4", FieldType::Protocol),
+    FieldInfo::new("ip.version", "Version", FieldType::Int),
+    FieldInfo::new("ip.hdr_len", "Header Length", FieldType::Int),
+    FieldInfo::new(
+        "ip.dsfield",
+        "Differentiated Services Field",
+        FieldType::Int,
+    )
+    .hex(),
+    FieldInfo::new("ip.len", "Total Length", FieldType::Int),
+    FieldInfo::new("ip.id", "Identification", FieldType::Int).hex(),
+    FieldInfo::new("ip.flags.df", "Don't fragment", FieldType::Int),
+    FieldInfo::new("ip.flags.mf", "More fragments", FieldType::Int),
+    FieldInfo::new("ip.frag_offset", "Fragment Offset", FieldType::Int),
+    FieldInfo::new("ip.ttl", "Time to Live", FieldType::Int),
+    FieldInfo::new("ip.proto", "Protocol", FieldType::Int),
+    FieldInfo::new("ip.checksum", "Header Checksum", FieldType::Int).hex(),
+    FieldInfo::new(
+        "ip.checksum.status",
+        "Header checksum status",
+        FieldType::Str,
+    ),
+    FieldInfo::new("ip.src", "Source Address", FieldType::Addr),
+    FieldInfo::new("ip.dst", "Destination Address", FieldType::Addr),
+    FieldInfo::new("ip.addr", "Address", FieldType::Addr),
+    FieldInfo::new("ip.src_host", "Source Host", FieldType::Str),
+    FieldInfo::new("ip.dst_host", "Destination Host", FieldType::Str),
+    FieldInfo::new("ip.host", "Host", FieldType::Str),
+    FieldInfo::new(
+        "ip.reassembled_in",
+        "Reassembled IPv4 in frame",
+        FieldType::Int,
+    ),
+    FieldInfo::new("ip.fragments", "IPv4 Fragments", FieldType::Protocol),
+    FieldInfo::new("ip.fragment", "Frame", FieldType::Int),
+    FieldInfo::new("ip.fragment.count", "Fragment count", FieldType::Int),
+    FieldInfo::new("ip.fragment.overlap", "Fragment overlap", FieldType::Int),
+    FieldInfo::new(
+        "ip.fragment.overlap.conflict",
+        "Conflicting data in fragment overlap",
+        FieldType::Int,
+    ),
+    FieldInfo::new(
+        "ip.reassembled.length",
+        "Reassembled IPv4 length",
+        FieldType::Int,
+    ),
+];
+
+const FRAGMENT_FIELDS: FragmentFields = FragmentFields {
+    fragments: "ip.fragments",
+    fragment: "ip.fragment",
+    count: "ip.fragment.count",
+    overlap: "ip.fragment.overlap",
+    conflict: "ip.fragment.overlap.conflict",
+    length: "ip.reassembled.length",
+};
 
 #[derive(Debug)]
 pub struct Ipv4 {
     _header: Ipv4Header,
     payload: Box<dyn Ipv4data>,
+    // set when checksums are verified
+    expected_checksum: Option<u16>,
+    // set for fragments, the payload of the last one is the whole datagram
+    reassembly: Option<Reassembly>,
+    // names the capture file resolved the source and destination to
+    hosts: [Option<String>; 2],
 }
 
 #[derive(Debug)]
-struct Ipv4Header {
+pub(crate) struct Ipv4Header {
     _header_len: u8,
     _service_type: u8,
     _packet_len: u16,
@@ -26,28 +98,131 @@ struct Ipv4Header {
     _src: std::net::Ipv4Addr,
     _dst: std::net::Ipv4Addr,
     _option: Vec<u8>,
+    // what the checksum should be, computed over the header as read
+    expected_checksum: u16,
+}
+
+impl Ipv4Header {
+    pub(crate) fn src(&self) -> std::net::Ipv4Addr {
+        self._src
+    }
+    pub(crate) fn dst(&self) -> std::net::Ipv4Addr {
+        self._dst
+    }
+    pub(crate) fn protocol(&self) -> u8 {
+        self._protocol
+    }
+    /// Length of the header with its options.
+    pub(crate) fn len(&self) -> usize {
+        self._header_len as usize * 4
+    }
+    /// The header fields, with a checksum status when `expected_checksum`
+    /// is given.
+    pub(crate) fn fields(&self, expected_checksum: Option<u16>) -> Vec<Field> {
+        vec![
+            Field::new("ip.version", 4u8).at(0, 1),
+            Field::new("ip.hdr_len", self.len()).at(0, 1),
+            Field::new("ip.dsfield", self._service_type).at(1, 1),
+            Field::new("ip.len", self._packet_len).at(2, 2),
+            Field::new("ip.id", self._id).at(4, 2),
+            Field::new("ip.flags.df", self._df).at(6, 1),
+            Field::new("ip.flags.mf", self._mf).at(6, 1),
+            Field::new("ip.frag_offset", self._fragment_offset).at(6, 2),
+            Field::new("ip.ttl", self._ttl).at(8, 1),
+            Field::new("ip.proto", self._protocol).at(9, 1),
+            checksum_field(
+                "ip.checksum",
+                "ip.checksum.status",
+                self._header_checksum,
+                expected_checksum,
+                10,
+            ),
+            Field::new("ip.src", self._src).at(12, 4),
+            Field::new("ip.dst", self._dst).at(16, 4),
+            Field::new("ip.addr", self._src).at(12, 4),
+            Field::new("ip.addr", self._dst).at(16, 4),
+        ]
+    }
 }
 
 impl L3data for Ipv4 {
     fn text(&self) -> Vec<String> {
         let mut ans = vec![format!("{:?}", self._header)];
+        if let Some(reassembly) = &self.reassembly {
+            ans.push(format!("{:?}", reassembly));
+        }
         ans.append(self.payload.text().as_mut());
         ans
     }
     fn line(&self) -> String {
-        self.payload.line(&self._header._src, &self._header._dst)
+        let h = &self._header;
+        let Some(Reassembly::Pending(reassembled_in)) = &self.reassembly else {
+            return self.payload.line(&h._src.into(), &h._dst.into());
+        };
+        let line = format!(
+            "{} → {} Fragmented IP protocol (proto={}, off={}, ID={:#06x})",
+            h._src,
+            h._dst,
+            h._protocol,
+            h._fragment_offset as usize * 8,
+            h._id
+        );
+        match reassembled_in.get() {
+            Some(frame) => format!("{} [Reassembled in #{}]", line, frame),
+            None => line,
+        }
+    }
+    fn fields(&self) -> Vec<Field> {
+        let header_len = self._header.len();
+        let mut children = self._header.fields(self.expected_checksum);
+        for (host, offset, name) in [(0, 12, "ip.src_host"), (1, 16, "ip.dst_host")] {
+            if let Some(x) = &self.hosts[host] {
+                children.push(Field::new(name, x.as_str()).at(offset, 4));
+                children.push(Field::new("ip.host", x.as_str()).at(offset, 4));
+            }
+        }
+        let reassembled_in = match &self.reassembly {
+            Some(Reassembly::Pending(x)) => x.get(),
+            _ => None,
+        };
+        if let Some(frame) = reassembled_in {
+            children.push(Field::new("ip.reassembled_in", *frame));
+        }
+        let mut ans = vec![Field::protocol("ip")
+            .at(0, header_len)
+            .with_children(children)];
+        match &self.reassembly {
+            // the datagram isn't in the frame as it is
+            Some(Reassembly::Complete(x)) => {
+                ans.push(x.field(&FRAGMENT_FIELDS));
+                ans.append(detached(self.payload.fields()).as_mut());
+            }
+            _ => ans.append(shifted(self.payload.fields(), header_len).as_mut()),
+        }
+        ans
     }
 }
 
-fn read_ipv4_header<T: Read>(read: &mut T) -> Result<Ipv4Header> {
-    let _header_len = read.read_u8()? - (4 * 16) as u8;
+pub(crate) fn read_ipv4_header<T: Read>(read: &mut T) -> Result<Ipv4Header> {
+    let tmp = read.read_u8()?;
+    let version = tmp >> 4;
+    if version != 4 {
+        bail!("ip version {} is not 4", version);
+    }
+    let _header_len = tmp & 0x0f;
+    if _header_len < 5 {
+        bail!("ip header length {} is smaller than 5", _header_len);
+    }
     let service_type = read.read_u8()?;
     let packet_len = read.read_u16::<NetworkEndian>()?;
+    if packet_len != 0 && (packet_len as usize) < _header_len as usize * 4 {
+        bail!("ip total length {} is shorter than its header", packet_len);
+    }
     let id = read.read_u16::<NetworkEndian>()?;
-    let tmp = read.read_u16::<NetworkEndian>()?;
-    let df = tmp & 1u16 << 14 == 1u16 << 14;
-    let mf = tmp & 1u16 << 13 == 1u16 << 13;
-    let fragment_offset = tmp << 3 >> 3;
+    let flags = read.read_u16::<NetworkEndian>()?;
+    let df = flags & 1u16 << 14 == 1u16 << 14;
+    let mf = flags & 1u16 << 13 == 1u16 << 13;
+    let fragment_offset = flags << 3 >> 3;
     let ttl = read.read_u8()?;
     let protocol = read.read_u8()?;
     let header_checksum = read.read_u16::<NetworkEndian>()?;
@@ -63,8 +238,18 @@ fn read_ipv4_header<T: Read>(read: &mut T) -> Result<Ipv4Header> {
         read.read_u8()?,
         read.read_u8()?,
     );
-    let mut option = vec![0; _header_len as usize * 4 - 20];
-    read.read_exact(option.as_mut_slice())?;
+    let mut option = vec![0; (_header_len as usize - 5) * 4];
+    read.read_exact(option.as_mut_slice())
+        .context("read options")?;
+    let mut checksum = Checksum::default();
+    checksum.add(&[tmp, service_type]);
+    checksum.add(&packet_len.to_be_bytes());
+    checksum.add(&id.to_be_bytes());
+    checksum.add(&flags.to_be_bytes());
+    checksum.add(&[ttl, protocol]);
+    checksum.add(&src.octets());
+    checksum.add(&dst.octets());
+    checksum.add(&option);
     Ok(Ipv4Header {
         _header_len,
         _service_type: service_type,
@@ -79,14 +264,142 @@ fn read_ipv4_header<T: Read>(read: &mut T) -> Result<Ipv4Header> {
         _src: src,
         _dst: dst,
         _option: option,
+        expected_checksum: checksum.finish(),
     })
 }
 
-pub fn read_ipv4(mut read: std::collections::VecDeque<u8>) -> Result<Ipv4> {
+pub fn read_ipv4(mut read: std::collections::VecDeque<u8>, ctx: &mut Context) -> Result<Ipv4> {
     let header = read_ipv4_header(&mut read)?;
-    let payload = read_ipv4data(read, header._protocol)?;
+    // a zero total length is left by segmentation offload, keep everything
+    // then
+    if header._packet_len != 0 {
+        let payload_len = header._packet_len as usize - header._header_len as usize * 4;
+        if payload_len > read.len() && !ctx.truncated {
+            bail!("ip total length {} overruns the packet", header._packet_len);
+        }
+        // drops the ethernet padding
+        read.truncate(payload_len);
+    }
+    ctx.src = Some(header._src.into());
+    ctx.dst = Some(header._dst.into());
+    let hosts = [ctx.host(header._src.into()), ctx.host(header._dst.into())];
+    ctx.fragmented = header._mf || header._fragment_offset != 0;
+    let expected_checksum = ctx
+        .registry()
+        .verify_checksums()
+        .then_some(header.expected_checksum);
+    // fragments cut short can't be put together, and only the first one
+    // starts with the transport layer header
+    if !ctx.fragmented || ctx.truncated {
+        let payload = match header._fragment_offset {
+            0 => read_ipv4data(read, header._protocol, ctx)?,
+            _ => Box::new(read_other(read, header._protocol)?),
+        };
+        return Ok(Ipv4 {
+            _header: header,
+            payload,
+            expected_checksum,
+            reassembly: None,
+            hosts,
+        });
+    }
+    let key = (
+        header._src.into(),
+        header._dst.into(),
+        header._id.into(),
+        header._protocol,
+    );
+    let (reassembly, datagram) = ctx.fragments.add(
+        key,
+        header._fragment_offset as usize * 8,
+        read.make_contiguous(),
+        header._mf,
+        ctx.number(),
+        ctx.timestamp(),
+    )?;
+    ctx.pending = matches!(reassembly, Reassembly::Pending(_));
+    let payload = match datagram {
+        Some(datagram) => {
+            ctx.fragmented = false;
+            read_ipv4data(datagram.into(), header._protocol, ctx)
+                .context("read reassembled datagram")?
+        }
+        None => Box::new(read_other(read, header._protocol)?),
+    };
     Ok(Ipv4 {
         _header: header,
         payload,
+        expected_checksum,
+        reassembly: Some(reassembly),
+        hosts,
     })
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_invalid_header() {
+        let header = [
+            0x45u8, 0x00, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00, 0x40, 0x11, 0x00, 0x00, 0x0a, 0x00,
+            0x00, 0x01, 0x0a, 0x00, 0x00, 0x02,
+        ];
+        assert!(read_ipv4_header(&mut &header[..]).is_ok());
+        for (i, x) in [(0, 0x65), (0, 0x44), (0, 0x46), (3, 0x10)] {
+            let mut bytes = header;
+            bytes[i] = x;
+            assert!(read_ipv4_header(&mut &bytes[..]).is_err(), "{:x?}", bytes);
+        }
+        let mut ctx = Context::default();
+        let mut packet = [&header[..], &[0; 8]].concat();
+        packet[3] = 0x30;
+        assert!(read_ipv4(packet.into(), &mut ctx).is_err());
+    }
+
+    #[test]
+    fn test_reassemble() {
+        // the dns query of the fuzzing corpus, split after 16 bytes of udp
+        let file = include_bytes!("../../fuzz/corpus/read_packet/ethernet.pcap");
+        let datagram = &file[54..115];
+        let fragment = |flags: u16, payload: &[u8]| {
+            let mut bytes = datagram[..20].to_vec();
+            bytes[2..4].copy_from_slice(&(20 + payload.len() as u16).to_be_bytes());
+            bytes[6..8].copy_from_slice(&flags.to_be_bytes());
+            bytes.extend(payload);
+            bytes
+        };
+        let mut ctx = Context::default();
+        let whole = read_ipv4(datagram.to_vec().into(), &mut ctx).unwrap();
+        ctx.begin(chrono::Local::now());
+        let first = read_ipv4(fragment(0x2000, &datagram[20..36]).into(), &mut ctx).unwrap();
+        assert_eq!(
+            first.line(),
+            "10.0.0.1 → 10.0.0.2 Fragmented IP protocol (proto=17, off=0, ID=0x0001)"
+        );
+        ctx.begin(chrono::Local::now());
+        let last = read_ipv4(fragment(2, &datagram[36..]).into(), &mut ctx).unwrap();
+        assert_eq!(last.line(), whole.line());
+        assert!(first.line().ends_with(" [Reassembled in #2]"));
+        let value = |x: &Ipv4, name| {
+            let fields = x.fields();
+            let field = fields
+                .iter()
+                .flat_map(|x| x.iter())
+                .find(|x| x.name() == name)?;
+            Some(field.value().to_string())
+        };
+        assert_eq!(value(&first, "ip.reassembled_in").unwrap(), "2");
+        assert_eq!(value(&last, "ip.reassembled.length").unwrap(), "41");
+        assert_eq!(value(&last, "udp.checksum.status").unwrap(), "good");
+        assert!(value(&last, "dns").is_some());
+        // a fragment cut short is left out of the reassembly
+        ctx.begin(chrono::Local::now());
+        ctx.truncated = true;
+        let mut bytes = fragment(2, &datagram[36..]);
+        bytes.truncate(24);
+        let cut = read_ipv4(bytes.into(), &mut ctx).unwrap();
+        assert_eq!(cut.line(), "10.0.0.1 -> 10.0.0.2 protocol(17)");
+        assert!(cut.reassembly.is_none());
+    }
+}
@@ -1,22 +1,71 @@
+pub mod icmp;
+pub mod icmpv6;
 pub mod other;
+pub mod tcp;
 pub mod udp;
 
-use anyhow::{Context, Result};
-use other::read_other;
+use crate::context::Context;
+use crate::field::Field;
+use crate::l7data::L7data;
+use anyhow::{Context as _, Result};
+use icmp::read_icmp;
+use icmpv6::read_icmpv6;
+use other::{read_other, read_truncated};
 use std::fmt::Debug;
+use tcp::read_tcp;
 use udp::read_udp;
 
 pub trait Ipv4data: Debug + Send + Sync {
     fn text(&self) -> Vec<String>;
-    fn line(&self, src: &std::net::Ipv4Addr, dst: &std::net::Ipv4Addr) -> String;
+    fn line(&self, src: &std::net::IpAddr, dst: &std::net::IpAddr) -> String;
+    fn fields(&self) -> Vec<Field>;
+}
+
+/// Transport layer decoded by a dissector registered for the IP protocol.
+#[derive(Debug)]
+struct Registered(Box<dyn L7data>);
+
+impl Ipv4data for Registered {
+    fn text(&self) -> Vec<String> {
+        self.0.text()
+    }
+    fn line(&self, src: &std::net::IpAddr, dst: &std::net::IpAddr) -> String {
+        format!("{} → {} {}", src, dst, self.0.line())
+    }
+    fn fields(&self) -> Vec<Field> {
+        self.0.fields()
+    }
 }
 
 pub fn read_ipv4data(
     read: std::collections::VecDeque<u8>,
     protocol: u8,
+    ctx: &mut Context,
 ) -> Result<Box<dyn Ipv4data>> {
+    ctx.or_truncated(
+        read,
+        |read, ctx| dissect_ipv4data(read, protocol, ctx),
+        |read| Box::new(read_truncated(read, protocol)),
+    )
+}
+
+fn dissect_ipv4data(
+    read: std::collections::VecDeque<u8>,
+    protocol: u8,
+    ctx: &mut Context,
+) -> Result<Box<dyn Ipv4data>> {
+    let registered = ctx.registry().by_ip_protocol(protocol);
+    if let Some(dissector) = registered {
+        let data = dissector
+            .dissect(&Vec::from(read), ctx)
+            .with_context(|| format!("read {}", dissector.name()))?;
+        return Ok(Box::new(Registered(data)));
+    }
     match protocol {
-        17 => Ok(Box::new(read_udp(read).context("read udp")?)),
+        1 => Ok(Box::new(read_icmp(read, ctx).context("read icmp")?)),
+        6 => Ok(Box::new(read_tcp(read, ctx).context("read tcp")?)),
+        17 => Ok(Box::new(read_udp(read, ctx).context("read udp")?)),
+        58 => Ok(Box::new(read_icmpv6(read, ctx).context("read icmpv6")?)),
         _type => Ok(Box::new(read_other(read, protocol)?)),
     }
 }
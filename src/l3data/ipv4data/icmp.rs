@@ -0,0 +1,374 @@
+use crate::context::{fmt_latency, Context, RequestTable};
+use crate::field::{shifted, Field, FieldInfo, FieldType};
+use crate::l3data::ipv4::{read_ipv4_header, Ipv4Header};
+use crate::l3data::ipv4data::Ipv4data;
+use anyhow::{Context as _, Result};
+use byteorder::{NetworkEndian, ReadBytesExt};
+use chrono::{DateTime, Local};
+use std::net::IpAddr;
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new(
+        "icmp",
+        "Internet Control Message Protocol",
+        FieldType::Protocol,
+    ),
+    FieldInfo::new("icmp.type", "Type", FieldType::Int),
+    FieldInfo::new("icmp.code", "Code", FieldType::Int),
+    FieldInfo::new("icmp.checksum", "Checksum", FieldType::Int).hex(),
+    FieldInfo::new("icmp.ident", "Identifier", FieldType::Int),
+    FieldInfo::new("icmp.seq", "Sequence Number", FieldType::Int),
+];
+
+/// Echo requests seen so far keyed by (src, dst, id, seq), shared by ICMP
+/// and ICMPv6.
+type EchoKey = (IpAddr, IpAddr, u16, u16);
+
+#[derive(Debug, Default)]
+pub struct EchoTable {
+    requests: RequestTable<EchoKey>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct EchoResponse {
+    request_frame: usize,
+    rtt: chrono::Duration,
+}
+
+impl std::fmt::Display for EchoResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "request in {}, rtt={}",
+            self.request_frame,
+            fmt_latency(self.rtt)
+        )
+    }
+}
+
+impl EchoTable {
+    pub(crate) fn request(
+        &mut self,
+        addrs: (IpAddr, IpAddr),
+        id: u16,
+        seq: u16,
+        frame: usize,
+        timestamp: DateTime<Local>,
+    ) {
+        self.requests
+            .insert((addrs.0, addrs.1, id, seq), frame, timestamp);
+    }
+    pub(crate) fn reply(
+        &mut self,
+        addrs: (IpAddr, IpAddr),
+        id: u16,
+        seq: u16,
+        timestamp: DateTime<Local>,
+    ) -> Option<EchoResponse> {
+        // the reply travels the other way round
+        let (request_frame, request_time) = self.requests.take(&(addrs.1, addrs.0, id, seq))?;
+        Some(EchoResponse {
+            request_frame,
+            rtt: timestamp - request_time,
+        })
+    }
+}
+
+/// Records an echo request or matches an echo reply against the context.
+pub(crate) fn track_echo(
+    ctx: &mut Context,
+    is_request: bool,
+    id: u16,
+    seq: u16,
+) -> Option<EchoResponse> {
+    let addrs = (ctx.src?, ctx.dst?);
+    if is_request {
+        let (number, timestamp) = (ctx.number(), ctx.timestamp());
+        ctx.echo.request(addrs, id, seq, number, timestamp);
+        None
+    } else {
+        let timestamp = ctx.timestamp();
+        ctx.echo.reply(addrs, id, seq, timestamp)
+    }
+}
+
+#[derive(Debug)]
+pub struct Echo {
+    id: u16,
+    seq: u16,
+    _data: Vec<u8>,
+    response: Option<EchoResponse>,
+}
+
+impl Echo {
+    pub(crate) fn id(&self) -> u16 {
+        self.id
+    }
+    pub(crate) fn seq(&self) -> u16 {
+        self.seq
+    }
+    pub(crate) fn line(&self) -> String {
+        let response = match &self.response {
+            Some(x) => format!(" ({})", x),
+            None => "".to_string(),
+        };
+        format!("id=0x{:04x} seq={}{}", self.id, self.seq, response)
+    }
+}
+
+#[derive(Debug)]
+struct OriginalDatagram {
+    header: Ipv4Header,
+    // source and destination port for UDP and TCP
+    ports: Option<(u16, u16)>,
+}
+
+impl OriginalDatagram {
+    /// Fields of the quoted header, which follows the 8 octets of the ICMP
+    /// header.
+    fn fields(&self) -> Vec<Field> {
+        let header_len = self.header.len();
+        let mut ans = vec![Field::protocol("ip")
+            .at(0, header_len)
+            .with_children(self.header.fields(None))];
+        if let Some((src_port, dst_port)) = self.ports {
+            let (name, src_name, dst_name) = match self.header.protocol() {
+                6 => ("tcp", "tcp.srcport", "tcp.dstport"),
+                _ => ("udp", "udp.srcport", "udp.dstport"),
+            };
+            ans.push(Field::protocol(name).at(header_len, 4).with_children(vec![
+                Field::new(src_name, src_port).at(header_len, 2),
+                Field::new(dst_name, dst_port).at(header_len + 2, 2),
+            ]));
+        }
+        shifted(ans, 8)
+    }
+}
+
+#[derive(Debug)]
+enum IcmpBody {
+    Echo(Echo),
+    Error(Option<OriginalDatagram>),
+    Other { _payload: Vec<u8> },
+}
+
+#[derive(Debug)]
+struct IcmpHeader {
+    icmp_type: u8,
+    code: u8,
+    _checksum: u16,
+}
+
+#[derive(Debug)]
+pub struct Icmp {
+    header: IcmpHeader,
+    body: IcmpBody,
+    // of the whole message
+    len: usize,
+}
+
+fn type_name(icmp_type: u8, code: u8) -> String {
+    let name = match icmp_type {
+        0 => "Echo (ping) reply",
+        3 => {
+            let reason = match code {
+                0 => "Network unreachable",
+                1 => "Host unreachable",
+                2 => "Protocol unreachable",
+                3 => "Port unreachable",
+                4 => "Fragmentation needed",
+                5 => "Source route failed",
+                6 => "Destination network unknown",
+                7 => "Destination host unknown",
+                9 => "Network administratively prohibited",
+                10 => "Host administratively prohibited",
+                13 => "Communication administratively prohibited",
+                _ => "",
+            };
+            return format!("Destination unreachable ({})", reason);
+        }
+        4 => "Source quench",
+        5 => "Redirect",
+        8 => "Echo (ping) request",
+        9 => "Router advertisement",
+        10 => "Router solicitation",
+        11 => {
+            let reason = match code {
+                0 => "TTL exceeded in transit",
+                1 => "Fragment reassembly time exceeded",
+                _ => "",
+            };
+            return format!("Time-to-live exceeded ({})", reason);
+        }
+        12 => "Parameter problem",
+        13 => "Timestamp request",
+        14 => "Timestamp reply",
+        _ => return format!("type({}) code({})", icmp_type, code),
+    };
+    name.to_string()
+}
+
+impl Ipv4data for Icmp {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("{:?}", self.header), format!("{:?}", self.body)];
+        if let IcmpBody::Echo(Echo {
+            response: Some(x), ..
+        }) = &self.body
+        {
+            ans.push(format!("Response to {}", x));
+        }
+        ans
+    }
+    fn line(&self, src: &IpAddr, dst: &IpAddr) -> String {
+        let detail = match &self.body {
+            IcmpBody::Echo(echo) => format!(" {}", echo.line()),
+            IcmpBody::Error(Some(original)) => {
+                let (src, dst) = (original.header.src(), original.header.dst());
+                match original.ports {
+                    Some((src_port, dst_port)) => {
+                        format!(" for {}:{} → {}:{}", src, src_port, dst, dst_port)
+                    }
+                    None => format!(" for {} → {}", src, dst),
+                }
+            }
+            _ => "".to_string(),
+        };
+        format!(
+            "{} → {} ICMP {}{}",
+            src,
+            dst,
+            type_name(self.header.icmp_type, self.header.code),
+            detail
+        )
+    }
+    fn fields(&self) -> Vec<Field> {
+        let mut children = vec![
+            Field::new("icmp.type", self.header.icmp_type).at(0, 1),
+            Field::new("icmp.code", self.header.code).at(1, 1),
+            Field::new("icmp.checksum", self.header._checksum).at(2, 2),
+        ];
+        match &self.body {
+            IcmpBody::Echo(echo) => {
+                children.push(Field::new("icmp.ident", echo.id).at(4, 2));
+                children.push(Field::new("icmp.seq", echo.seq).at(6, 2));
+            }
+            IcmpBody::Error(Some(original)) => children.append(original.fields().as_mut()),
+            _ => {}
+        }
+        vec![Field::protocol("icmp")
+            .at(0, self.len)
+            .with_children(children)]
+    }
+}
+
+pub(crate) fn read_echo(
+    mut read: std::collections::VecDeque<u8>,
+    is_request: bool,
+    ctx: &mut Context,
+) -> Result<Echo> {
+    let id = read.read_u16::<NetworkEndian>().context("read id")?;
+    let seq = read.read_u16::<NetworkEndian>().context("read seq")?;
+    let response = track_echo(ctx, is_request, id, seq);
+    Ok(Echo {
+        id,
+        seq,
+        _data: Vec::from(read),
+        response,
+    })
+}
+
+fn read_original_datagram(mut read: std::collections::VecDeque<u8>) -> Option<OriginalDatagram> {
+    let header = read_ipv4_header(&mut read).ok()?;
+    let ports = match header.protocol() {
+        6 | 17 => Some((
+            read.read_u16::<NetworkEndian>().ok()?,
+            read.read_u16::<NetworkEndian>().ok()?,
+        )),
+        _ => None,
+    };
+    Some(OriginalDatagram { header, ports })
+}
+
+pub fn read_icmp(mut read: std::collections::VecDeque<u8>, ctx: &mut Context) -> Result<Icmp> {
+    let len = read.len();
+    let icmp_type = read.read_u8().context("read type")?;
+    let code = read.read_u8().context("read code")?;
+    let checksum = read.read_u16::<NetworkEndian>().context("read checksum")?;
+    let header = IcmpHeader {
+        icmp_type,
+        code,
+        _checksum: checksum,
+    };
+    let body = match icmp_type {
+        0 | 8 => IcmpBody::Echo(read_echo(read, icmp_type == 8, ctx).context("read echo")?),
+        3 | 4 | 5 | 11 | 12 => {
+            // unused (or gateway / pointer) field before the original datagram
+            read.drain(..std::cmp::min(4, read.len()));
+            IcmpBody::Error(read_original_datagram(read))
+        }
+        _ => IcmpBody::Other {
+            _payload: Vec::from(read),
+        },
+    };
+    Ok(Icmp { header, body, len })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_echo_reply_links_to_request() {
+        let mut ctx = Context::default();
+        let a: IpAddr = "10.0.0.1".parse().unwrap();
+        let b: IpAddr = "10.0.0.2".parse().unwrap();
+        let request = [0x08u8, 0x00, 0x00, 0x00, 0x12, 0x34, 0x00, 0x01];
+        let reply = [0x00u8, 0x00, 0x00, 0x00, 0x12, 0x34, 0x00, 0x01];
+
+        ctx.begin(Local.timestamp_opt(100, 0).unwrap());
+        (ctx.src, ctx.dst) = (Some(a), Some(b));
+        let request = read_icmp(request.to_vec().into(), &mut ctx).unwrap();
+        assert_eq!(
+            request.line(&a, &b),
+            "10.0.0.1 → 10.0.0.2 ICMP Echo (ping) request id=0x1234 seq=1"
+        );
+
+        ctx.begin(Local.timestamp_opt(100, 1_500_000).unwrap());
+        (ctx.src, ctx.dst) = (Some(b), Some(a));
+        let first = read_icmp(reply.to_vec().into(), &mut ctx).unwrap();
+        assert_eq!(
+            first.line(&b, &a),
+            "10.0.0.2 → 10.0.0.1 ICMP Echo (ping) reply id=0x1234 seq=1 (request in 1, rtt=1.500ms)"
+        );
+
+        // only the first reply is linked
+        ctx.begin(Local.timestamp_opt(100, 2_000_000).unwrap());
+        (ctx.src, ctx.dst) = (Some(b), Some(a));
+        let duplicate = read_icmp(reply.to_vec().into(), &mut ctx).unwrap();
+        assert_eq!(
+            duplicate.line(&b, &a),
+            "10.0.0.2 → 10.0.0.1 ICMP Echo (ping) reply id=0x1234 seq=1"
+        );
+    }
+
+    #[test]
+    fn test_original_datagram_fields() {
+        // port unreachable for a udp datagram from 10.0.0.1:5353 to 10.0.0.2:53
+        let mut bytes = vec![0x03u8, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00];
+        bytes.extend_from_slice(&[0x45, 0x00, 0x00, 0x1c, 0x00, 0x01, 0x00, 0x00]);
+        bytes.extend_from_slice(&[0x40, 0x11, 0x00, 0x00, 0x0a, 0x00, 0x00, 0x01]);
+        bytes.extend_from_slice(&[0x0a, 0x00, 0x00, 0x02, 0x14, 0xe9, 0x00, 0x35]);
+        bytes.extend_from_slice(&[0x00, 0x08, 0x00, 0x00]);
+        let icmp = read_icmp(bytes.clone().into(), &mut Context::default()).unwrap();
+        let fields = icmp.fields();
+        assert_eq!(fields[0].len(), bytes.len());
+        let field = |name| fields[0].iter().find(|x| x.name() == name).unwrap();
+        assert_eq!(field("ip").offset(), 8);
+        assert_eq!(field("ip.dst").value().to_string(), "10.0.0.2");
+        assert_eq!(field("ip.dst").offset(), 24);
+        assert_eq!(field("udp.dstport").value().to_string(), "53");
+        assert_eq!(field("udp.dstport").offset(), 30);
+    }
+}
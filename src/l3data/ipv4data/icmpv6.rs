@@ -0,0 +1,350 @@
+use crate::context::Context;
+use crate::field::{Field, FieldInfo, FieldType};
+use crate::l2data::Macaddr;
+use crate::l3data::ipv4data::icmp::{read_echo, Echo};
+use crate::l3data::ipv4data::Ipv4data;
+use crate::l3data::ipv6::{read_ipv6_addr, read_ipv6_header, Ipv6Header};
+use anyhow::{bail, Context as _, Result};
+use byteorder::{NetworkEndian, ReadBytesExt};
+use std::io::Read;
+use std::net::{IpAddr, Ipv6Addr};
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new(
+        "icmpv6",
+        "Internet Control Message Protocol v6",
+        FieldType::Protocol,
+    ),
+    FieldInfo::new("icmpv6.type", "Type", FieldType::Int),
+    FieldInfo::new("icmpv6.code", "Code", FieldType::Int),
+    FieldInfo::new("icmpv6.checksum", "Checksum", FieldType::Int).hex(),
+    FieldInfo::new("icmpv6.echo.identifier", "Identifier", FieldType::Int),
+    FieldInfo::new(
+        "icmpv6.echo.sequence_number",
+        "Sequence Number",
+        FieldType::Int,
+    ),
+    FieldInfo::new("icmpv6.nd.target", "Target Address", FieldType::Addr),
+];
+
+#[derive(Debug)]
+enum NdpOption {
+    SourceLinkLayerAddress(Macaddr),
+    TargetLinkLayerAddress(Macaddr),
+    PrefixInformation {
+        _prefix_len: u8,
+        _flags: u8,
+        _valid_lifetime: u32,
+        _preferred_lifetime: u32,
+        _prefix: Ipv6Addr,
+    },
+    Mtu {
+        _mtu: u32,
+    },
+    Other {
+        _option_type: u8,
+        _data: Vec<u8>,
+    },
+}
+
+#[derive(Debug)]
+enum Icmpv6Body {
+    Echo(Echo),
+    Error(Option<Ipv6Header>),
+    RouterSolicitation {
+        options: Vec<NdpOption>,
+    },
+    RouterAdvertisement {
+        _cur_hop_limit: u8,
+        _flags: u8,
+        _router_lifetime: u16,
+        _reachable_time: u32,
+        _retrans_timer: u32,
+        options: Vec<NdpOption>,
+    },
+    NeighborSolicitation {
+        target: Ipv6Addr,
+        options: Vec<NdpOption>,
+    },
+    NeighborAdvertisement {
+        flags: u32,
+        target: Ipv6Addr,
+        options: Vec<NdpOption>,
+    },
+    Redirect {
+        target: Ipv6Addr,
+        _destination: Ipv6Addr,
+        _options: Vec<NdpOption>,
+    },
+    Other {
+        _payload: Vec<u8>,
+    },
+}
+
+#[derive(Debug)]
+struct Icmpv6Header {
+    icmp_type: u8,
+    code: u8,
+    _checksum: u16,
+}
+
+#[derive(Debug)]
+pub struct Icmpv6 {
+    header: Icmpv6Header,
+    body: Icmpv6Body,
+}
+
+fn type_name(icmp_type: u8, code: u8) -> String {
+    let name = match icmp_type {
+        1 => {
+            let reason = match code {
+                0 => "No route to destination",
+                1 => "Administratively prohibited",
+                2 => "Beyond scope of source address",
+                3 => "Address unreachable",
+                4 => "Port unreachable",
+                5 => "Source address failed ingress/egress policy",
+                6 => "Reject route to destination",
+                _ => "",
+            };
+            return format!("Destination unreachable ({})", reason);
+        }
+        2 => "Packet too big",
+        3 => {
+            let reason = match code {
+                0 => "Hop limit exceeded in transit",
+                1 => "Fragment reassembly time exceeded",
+                _ => "",
+            };
+            return format!("Time exceeded ({})", reason);
+        }
+        4 => "Parameter problem",
+        128 => "Echo (ping) request",
+        129 => "Echo (ping) reply",
+        133 => "Router Solicitation",
+        134 => "Router Advertisement",
+        135 => "Neighbor Solicitation",
+        136 => "Neighbor Advertisement",
+        137 => "Redirect",
+        _ => return format!("type({}) code({})", icmp_type, code),
+    };
+    name.to_string()
+}
+
+fn link_layer_address(options: &[NdpOption]) -> Option<Macaddr> {
+    options.iter().find_map(|x| match x {
+        NdpOption::SourceLinkLayerAddress(mac) | NdpOption::TargetLinkLayerAddress(mac) => {
+            Some(*mac)
+        }
+        _ => None,
+    })
+}
+
+impl Ipv4data for Icmpv6 {
+    fn text(&self) -> Vec<String> {
+        vec![format!("{:?}", self.header), format!("{:?}", self.body)]
+    }
+    fn line(&self, src: &IpAddr, dst: &IpAddr) -> String {
+        let detail = match &self.body {
+            Icmpv6Body::Echo(echo) => format!(" {}", echo.line()),
+            Icmpv6Body::Error(Some(original)) => {
+                format!(" for {} → {}", original.src(), original.dst())
+            }
+            Icmpv6Body::RouterSolicitation { options }
+            | Icmpv6Body::RouterAdvertisement { options, .. } => {
+                match link_layer_address(options) {
+                    Some(mac) => format!(" from {}", mac),
+                    None => "".to_string(),
+                }
+            }
+            Icmpv6Body::NeighborSolicitation { target, options } => {
+                match link_layer_address(options) {
+                    Some(mac) => format!(" for {} from {}", target, mac),
+                    None => format!(" for {}", target),
+                }
+            }
+            Icmpv6Body::NeighborAdvertisement {
+                flags,
+                target,
+                options,
+            } => {
+                let names: Vec<&str> = [(31, "rtr"), (30, "sol"), (29, "ovr")]
+                    .iter()
+                    .filter(|(bit, _)| flags & (1 << bit) != 0)
+                    .map(|(_, name)| *name)
+                    .collect();
+                let at = match link_layer_address(options) {
+                    Some(mac) => format!(" is at {}", mac),
+                    None => "".to_string(),
+                };
+                format!(" {} ({}){}", target, names.join(", "), at)
+            }
+            Icmpv6Body::Redirect { target, .. } => format!(" to {}", target),
+            _ => "".to_string(),
+        };
+        format!(
+            "{} → {} ICMPv6 {}{}",
+            src,
+            dst,
+            type_name(self.header.icmp_type, self.header.code),
+            detail
+        )
+    }
+    fn fields(&self) -> Vec<Field> {
+        let mut children = vec![
+            Field::new("icmpv6.type", self.header.icmp_type).at(0, 1),
+            Field::new("icmpv6.code", self.header.code).at(1, 1),
+            Field::new("icmpv6.checksum", self.header._checksum).at(2, 2),
+        ];
+        let mut len = 4;
+        match &self.body {
+            Icmpv6Body::Echo(echo) => {
+                children.push(Field::new("icmpv6.echo.identifier", echo.id()).at(4, 2));
+                children.push(Field::new("icmpv6.echo.sequence_number", echo.seq()).at(6, 2));
+                len = 8;
+            }
+            Icmpv6Body::NeighborSolicitation { target, .. }
+            | Icmpv6Body::NeighborAdvertisement { target, .. } => {
+                children.push(Field::new("icmpv6.nd.target", *target).at(8, 16));
+                len = 24;
+            }
+            _ => {}
+        }
+        vec![Field::protocol("icmpv6").at(0, len).with_children(children)]
+    }
+}
+
+fn read_ndp_options(mut read: std::collections::VecDeque<u8>) -> Result<Vec<NdpOption>> {
+    let mut options = vec![];
+    while read.len() >= 2 {
+        let option_type = read.read_u8()?;
+        let len = read.read_u8()? as usize * 8;
+        if len < 2 || len - 2 > read.len() {
+            bail!("invalid ndp option length {}", len);
+        }
+        let mut data: std::collections::VecDeque<u8> = read.drain(..len - 2).collect();
+        let option = match option_type {
+            1 | 2 if data.len() >= 6 => {
+                let mut mac = [0u8; 6];
+                data.read_exact(&mut mac)?;
+                if option_type == 1 {
+                    NdpOption::SourceLinkLayerAddress(Macaddr(mac))
+                } else {
+                    NdpOption::TargetLinkLayerAddress(Macaddr(mac))
+                }
+            }
+            3 if data.len() == 30 => {
+                let prefix_len = data.read_u8()?;
+                let flags = data.read_u8()?;
+                let valid_lifetime = data.read_u32::<NetworkEndian>()?;
+                let preferred_lifetime = data.read_u32::<NetworkEndian>()?;
+                let _reserved = data.read_u32::<NetworkEndian>()?;
+                NdpOption::PrefixInformation {
+                    _prefix_len: prefix_len,
+                    _flags: flags,
+                    _valid_lifetime: valid_lifetime,
+                    _preferred_lifetime: preferred_lifetime,
+                    _prefix: read_ipv6_addr(&mut data)?,
+                }
+            }
+            5 if data.len() == 6 => {
+                let _reserved = data.read_u16::<NetworkEndian>()?;
+                NdpOption::Mtu {
+                    _mtu: data.read_u32::<NetworkEndian>()?,
+                }
+            }
+            _ => NdpOption::Other {
+                _option_type: option_type,
+                _data: Vec::from(data),
+            },
+        };
+        options.push(option);
+    }
+    Ok(options)
+}
+
+pub fn read_icmpv6(mut read: std::collections::VecDeque<u8>, ctx: &mut Context) -> Result<Icmpv6> {
+    let icmp_type = read.read_u8().context("read type")?;
+    let code = read.read_u8().context("read code")?;
+    let checksum = read.read_u16::<NetworkEndian>().context("read checksum")?;
+    let header = Icmpv6Header {
+        icmp_type,
+        code,
+        _checksum: checksum,
+    };
+    let body = match icmp_type {
+        128 | 129 => Icmpv6Body::Echo(read_echo(read, icmp_type == 128, ctx).context("read echo")?),
+        1..=4 => {
+            // unused, mtu or pointer field before the original datagram
+            read.drain(..std::cmp::min(4, read.len()));
+            Icmpv6Body::Error(read_ipv6_header(&mut read).ok())
+        }
+        133 => {
+            let _reserved = read.read_u32::<NetworkEndian>()?;
+            Icmpv6Body::RouterSolicitation {
+                options: read_ndp_options(read).context("read options")?,
+            }
+        }
+        134 => Icmpv6Body::RouterAdvertisement {
+            _cur_hop_limit: read.read_u8()?,
+            _flags: read.read_u8()?,
+            _router_lifetime: read.read_u16::<NetworkEndian>()?,
+            _reachable_time: read.read_u32::<NetworkEndian>()?,
+            _retrans_timer: read.read_u32::<NetworkEndian>()?,
+            options: read_ndp_options(read).context("read options")?,
+        },
+        135 => {
+            let _reserved = read.read_u32::<NetworkEndian>()?;
+            let target = read_ipv6_addr(&mut read).context("read target")?;
+            Icmpv6Body::NeighborSolicitation {
+                target,
+                options: read_ndp_options(read).context("read options")?,
+            }
+        }
+        136 => {
+            let flags = read.read_u32::<NetworkEndian>()?;
+            let target = read_ipv6_addr(&mut read).context("read target")?;
+            Icmpv6Body::NeighborAdvertisement {
+                flags,
+                target,
+                options: read_ndp_options(read).context("read options")?,
+            }
+        }
+        137 => {
+            let _reserved = read.read_u32::<NetworkEndian>()?;
+            let target = read_ipv6_addr(&mut read).context("read target")?;
+            let destination = read_ipv6_addr(&mut read).context("read destination")?;
+            Icmpv6Body::Redirect {
+                target,
+                _destination: destination,
+                _options: read_ndp_options(read).context("read options")?,
+            }
+        }
+        _ => Icmpv6Body::Other {
+            _payload: Vec::from(read),
+        },
+    };
+    Ok(Icmpv6 { header, body })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_neighbor_advertisement() {
+        let mut bytes = vec![0x88u8, 0x00, 0x00, 0x00, 0x60, 0x00, 0x00, 0x00];
+        bytes.extend_from_slice(&[0xfe, 0x80]);
+        bytes.extend_from_slice(&[0u8; 13]);
+        bytes.push(0x01);
+        bytes.extend_from_slice(&[0x02, 0x01, 0x02, 0x00, 0x00, 0x00, 0x00, 0x01]);
+        let na = read_icmpv6(bytes.into(), &mut Context::default()).unwrap();
+        let src: IpAddr = "fe80::1".parse().unwrap();
+        let dst: IpAddr = "fe80::2".parse().unwrap();
+        assert_eq!(
+            na.line(&src, &dst),
+            "fe80::1 → fe80::2 ICMPv6 Neighbor Advertisement fe80::1 (sol, ovr) is at 02:00:00:00:00:01"
+        );
+    }
+}
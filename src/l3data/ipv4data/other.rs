@@ -1,3 +1,4 @@
+use crate::field::Field;
 use crate::l3data::ipv4data::Ipv4data;
 use anyhow::Result;
 
@@ -5,18 +6,43 @@ use anyhow::Result;
 pub struct Other {
     _payload: Vec<u8>,
     _type: u8,
+    truncated: bool,
 }
 
 pub fn read_other(read: std::collections::VecDeque<u8>, _type: u8) -> Result<Other> {
     let _payload = Vec::from(read);
-    Ok(Other { _payload, _type })
+    Ok(Other {
+        _payload,
+        _type,
+        truncated: false,
+    })
+}
+
+/// Keeps the bytes of a packet cut short before its transport layer header ended.
+pub(crate) fn read_truncated(read: std::collections::VecDeque<u8>, _type: u8) -> Other {
+    Other {
+        _payload: Vec::from(read),
+        _type,
+        truncated: true,
+    }
 }
 
 impl Ipv4data for Other {
     fn text(&self) -> Vec<String> {
         vec![format!("Ipv4Data {:x?}", self._payload)]
     }
-    fn line(&self, src: &std::net::Ipv4Addr, dst: &std::net::Ipv4Addr) -> String {
+    fn line(&self, src: &std::net::IpAddr, dst: &std::net::IpAddr) -> String {
+        if self.truncated {
+            return format!("{} -> {} protocol({}) [truncated]", src, dst, self._type);
+        }
         format!("{} -> {} protocol({})", src, dst, self._type)
     }
+    fn fields(&self) -> Vec<Field> {
+        let name = if self.truncated { "truncated" } else { "data" };
+        vec![Field::protocol(name)
+            .at(0, self._payload.len())
+            .with_children(vec![
+                Field::new("data.data", self._payload.as_slice()).at(0, self._payload.len())
+            ])]
+    }
 }
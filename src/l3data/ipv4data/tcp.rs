@@ -1,14 +1,478 @@
-use anyhow::{Context, Result};
+use crate::checksum::{checksum_field, pseudo_header};
+use crate::context::Context;
+use crate::field::{shifted, Field, FieldInfo, FieldType};
+use crate::l3data::ipv4data::Ipv4data;
+use crate::l7data::{read_l7data, L7data};
+use crate::registry::Transport;
+use anyhow::{bail, Context as _, Result};
+use byteorder::NetworkEndian;
+use byteorder::ReadBytesExt;
 use std::io::Read;
+use std::net::SocketAddr;
 
-pub struct Tcp{
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("tcp", "Transmission Control Protocol", FieldType::Protocol),
+    FieldInfo::new("tcp.srcport", "Source Port", FieldType::Int),
+    FieldInfo::new("tcp.dstport", "Destination Port", FieldType::Int),
+    FieldInfo::new("tcp.port", "Port", FieldType::Int),
+    FieldInfo::new("tcp.seq", "Sequence Number", FieldType::Int),
+    FieldInfo::new("tcp.ack", "Acknowledgment Number", FieldType::Int),
+    FieldInfo::new("tcp.hdr_len", "Header Length", FieldType::Int),
+    FieldInfo::new("tcp.flags", "Flags", FieldType::Int).hex(),
+    FieldInfo::new("tcp.flags.fin", "Fin", FieldType::Int),
+    FieldInfo::new("tcp.flags.syn", "Syn", FieldType::Int),
+    FieldInfo::new("tcp.flags.reset", "Reset", FieldType::Int),
+    FieldInfo::new("tcp.flags.push", "Push", FieldType::Int),
+    FieldInfo::new("tcp.flags.ack", "Acknowledgment", FieldType::Int),
+    FieldInfo::new("tcp.flags.urg", "Urgent", FieldType::Int),
+    FieldInfo::new("tcp.window_size_value", "Window", FieldType::Int),
+    FieldInfo::new("tcp.checksum", "Checksum", FieldType::Int).hex(),
+    FieldInfo::new("tcp.checksum.status", "Checksum Status", FieldType::Str),
+    FieldInfo::new("tcp.urgent_pointer", "Urgent Pointer", FieldType::Int),
+    FieldInfo::new("tcp.options", "Options", FieldType::Protocol),
+    FieldInfo::new(
+        "tcp.options.eol",
+        "End of Option List (EOL)",
+        FieldType::Protocol,
+    ),
+    FieldInfo::new("tcp.options.nop", "No-Operation (NOP)", FieldType::Protocol),
+    FieldInfo::new(
+        "tcp.options.mss",
+        "Maximum segment size",
+        FieldType::Protocol,
+    ),
+    FieldInfo::new("tcp.options.mss_val", "MSS Value", FieldType::Int),
+    FieldInfo::new("tcp.options.wscale", "Window scale", FieldType::Protocol),
+    FieldInfo::new("tcp.options.wscale.shift", "Shift count", FieldType::Int),
+    FieldInfo::new(
+        "tcp.options.sack_perm",
+        "SACK permitted",
+        FieldType::Protocol,
+    ),
+    FieldInfo::new("tcp.options.sack", "SACK", FieldType::Protocol),
+    FieldInfo::new("tcp.options.sack_le", "left edge", FieldType::Int),
+    FieldInfo::new("tcp.options.sack_re", "right edge", FieldType::Int),
+    FieldInfo::new("tcp.options.timestamp", "Timestamps", FieldType::Protocol),
+    FieldInfo::new(
+        "tcp.options.timestamp.tsval",
+        "Timestamp value",
+        FieldType::Int,
+    ),
+    FieldInfo::new(
+        "tcp.options.timestamp.tsecr",
+        "Timestamp echo reply",
+        FieldType::Int,
+    ),
+    FieldInfo::new("tcp.option_kind", "Kind", FieldType::Int),
+    FieldInfo::new("tcp.len", "TCP Segment Len", FieldType::Int),
+    FieldInfo::new("tcp.payload", "TCP payload", FieldType::Bytes),
+];
+
+#[derive(Clone, Copy)]
+pub struct TcpFlags(u16);
+
+impl TcpFlags {
+    const NAMES: [(u16, &'static str); 9] = [
+        (1 << 0, "FIN"),
+        (1 << 1, "SYN"),
+        (1 << 2, "RST"),
+        (1 << 3, "PSH"),
+        (1 << 4, "ACK"),
+        (1 << 5, "URG"),
+        (1 << 6, "ECE"),
+        (1 << 7, "CWR"),
+        (1 << 8, "NS"),
+    ];
+    const ACK: u16 = 1 << 4;
+
+    fn names(&self) -> Vec<&'static str> {
+        Self::NAMES
+            .iter()
+            .filter(|(bit, _)| self.0 & bit != 0)
+            .map(|(_, name)| *name)
+            .collect()
+    }
+}
+
+impl std::fmt::Debug for TcpFlags {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "[{}]", self.names().join(","))
+    }
+}
+
+pub enum TcpOption {
+    EndOfList,
+    Nop,
+    Mss(u16),
+    WindowScale(u8),
+    SackPermitted,
+    Sack(Vec<(u32, u32)>),
+    Timestamps {
+        value: u32,
+        echo_reply: u32,
+    },
+    Unknown {
+        kind: u8,
+        data: Vec<u8>,
+    },
+    /// The bytes from an option that couldn't be read to the end of the options.
+    Malformed(Vec<u8>),
+}
+
+impl std::fmt::Debug for TcpOption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TcpOption::EndOfList => write!(f, "EOL"),
+            TcpOption::Nop => write!(f, "NOP"),
+            TcpOption::Mss(mss) => write!(f, "MSS={}", mss),
+            TcpOption::WindowScale(shift) => write!(f, "WS={}", shift),
+            TcpOption::SackPermitted => write!(f, "SACK_PERM"),
+            TcpOption::Sack(blocks) => {
+                let blocks: Vec<String> = blocks
+                    .iter()
+                    .map(|(left, right)| format!("{}-{}", left, right))
+                    .collect();
+                write!(f, "SACK={}", blocks.join(","))
+            }
+            TcpOption::Timestamps { value, echo_reply } => {
+                write!(f, "TSval={} TSecr={}", value, echo_reply)
+            }
+            TcpOption::Unknown { kind, data } => write!(f, "Option({}) {:x?}", kind, data),
+            TcpOption::Malformed(data) => write!(f, "Malformed {:x?}", data),
+        }
+    }
+}
+
+impl TcpOption {
+    /// Length of the option on the wire.
+    fn len(&self) -> usize {
+        match self {
+            TcpOption::EndOfList | TcpOption::Nop => 1,
+            TcpOption::Mss(_) => 4,
+            TcpOption::WindowScale(_) => 3,
+            TcpOption::SackPermitted => 2,
+            TcpOption::Sack(blocks) => 2 + blocks.len() * 8,
+            TcpOption::Timestamps { .. } => 10,
+            TcpOption::Unknown { data, .. } => 2 + data.len(),
+            TcpOption::Malformed(data) => data.len(),
+        }
+    }
+    /// The option as a field placed at the start of it, or `None` for bytes
+    /// that aren't an option.
+    fn field(&self) -> Option<Field> {
+        let len = self.len();
+        let field = match self {
+            TcpOption::EndOfList => Field::protocol("tcp.options.eol"),
+            TcpOption::Nop => Field::protocol("tcp.options.nop"),
+            TcpOption::Mss(mss) => Field::protocol("tcp.options.mss")
+                .with_children(vec![Field::new("tcp.options.mss_val", *mss).at(2, 2)]),
+            TcpOption::WindowScale(shift) => Field::protocol("tcp.options.wscale")
+                .with_children(vec![Field::new("tcp.options.wscale.shift", *shift).at(2, 1)]),
+            TcpOption::SackPermitted => Field::protocol("tcp.options.sack_perm"),
+            TcpOption::Sack(blocks) => {
+                let mut children = vec![];
+                for (i, (left, right)) in blocks.iter().enumerate() {
+                    let offset = 2 + i * 8;
+                    children.push(Field::new("tcp.options.sack_le", *left).at(offset, 4));
+                    children.push(Field::new("tcp.options.sack_re", *right).at(offset + 4, 4));
+                }
+                Field::protocol("tcp.options.sack").with_children(children)
+            }
+            TcpOption::Timestamps { value, echo_reply } => Field::protocol("tcp.options.timestamp")
+                .with_children(vec![
+                    Field::new("tcp.options.timestamp.tsval", *value).at(2, 4),
+                    Field::new("tcp.options.timestamp.tsecr", *echo_reply).at(6, 4),
+                ]),
+            TcpOption::Unknown { kind, .. } => Field::new("tcp.option_kind", *kind),
+            TcpOption::Malformed(_) => return None,
+        };
+        Some(field.at(0, len))
+    }
+}
+
+#[derive(Debug)]
+pub struct TcpHeader {
+    src_port: u16,
+    dst_port: u16,
+    seq: u32,
+    ack: u32,
+    _data_offset: u8,
+    flags: TcpFlags,
+    _window: u16,
+    _checksum: u16,
+    _urgent_pointer: u16,
+    _options: Vec<TcpOption>,
+}
+
+#[derive(Debug)]
+pub struct Tcp {
+    header: TcpHeader,
+    data: Vec<u8>,
+    app: Option<Box<dyn L7data>>,
+    // set when the checksum is verified
+    expected_checksum: Option<u16>,
+}
+
+fn read_tcp_option(read: &mut &[u8]) -> Result<TcpOption> {
+    let kind = read.read_u8()?;
+    match kind {
+        0 => return Ok(TcpOption::EndOfList),
+        1 => return Ok(TcpOption::Nop),
+        _ => {}
+    }
+    let len = read.read_u8().context("read option len")?;
+    if len < 2 {
+        bail!("tcp option {} has invalid length {}", kind, len);
+    }
+    let mut data = vec![0u8; len as usize - 2];
+    read.read_exact(&mut data).context("read option data")?;
+    let mut data_read = data.as_slice();
+    let option = match (kind, len) {
+        (2, 4) => TcpOption::Mss(data_read.read_u16::<NetworkEndian>()?),
+        (3, 3) => TcpOption::WindowScale(data_read.read_u8()?),
+        (4, 2) => TcpOption::SackPermitted,
+        (5, _) if (len - 2) % 8 == 0 => {
+            let mut blocks = vec![];
+            while !data_read.is_empty() {
+                let left = data_read.read_u32::<NetworkEndian>()?;
+                let right = data_read.read_u32::<NetworkEndian>()?;
+                blocks.push((left, right));
+            }
+            TcpOption::Sack(blocks)
+        }
+        (8, 10) => TcpOption::Timestamps {
+            value: data_read.read_u32::<NetworkEndian>()?,
+            echo_reply: data_read.read_u32::<NetworkEndian>()?,
+        },
+        _ => TcpOption::Unknown { kind, data },
+    };
+    Ok(option)
 }
 
-fn read_tcp(&mut read: impl Read) -> Result<Tcp>{
-    match protocol {
-        6 => Ok(Box::new(read_ipv4(read, len, _type).context("read ipv4")?)),
-        _ => Ok(Box::new(
-            read_otherl3data(read, len, _type).context("read otherl3data")?,
-        )),
+fn read_tcp_options(mut read: &[u8]) -> Vec<TcpOption> {
+    let mut options = vec![];
+    while !read.is_empty() {
+        let rest = read;
+        let Ok(option) = read_tcp_option(&mut read) else {
+            // the options before a bad one are still good
+            options.push(TcpOption::Malformed(rest.to_vec()));
+            break;
+        };
+        let end = matches!(option, TcpOption::EndOfList);
+        options.push(option);
+        if end {
+            break;
+        }
+    }
+    options
+}
+
+pub fn read_tcp(mut read: std::collections::VecDeque<u8>, ctx: &mut Context) -> Result<Tcp> {
+    let expected_checksum = pseudo_header(ctx, 6, read.len())
+        .filter(|_| read.len() >= 20)
+        .map(|mut sum| {
+            // everything but the checksum itself
+            let segment = read.make_contiguous();
+            sum.add(&segment[..16]);
+            sum.add(&segment[18..]);
+            sum.finish()
+        });
+    let src_port = read
+        .read_u16::<NetworkEndian>()
+        .context("read src_port failed")?;
+    let dst_port = read
+        .read_u16::<NetworkEndian>()
+        .context("read dst_port failed")?;
+    let seq = read
+        .read_u32::<NetworkEndian>()
+        .context("read seq failed")?;
+    let ack = read
+        .read_u32::<NetworkEndian>()
+        .context("read ack failed")?;
+    let tmp = read.read_u16::<NetworkEndian>()?;
+    let data_offset = (tmp >> 12) as u8;
+    let flags = TcpFlags(tmp & 0x01ff);
+    let window = read.read_u16::<NetworkEndian>()?;
+    let checksum = read.read_u16::<NetworkEndian>()?;
+    let urgent_pointer = read.read_u16::<NetworkEndian>()?;
+    if data_offset < 5 {
+        bail!("tcp data offset {} is smaller than 5", data_offset);
+    }
+    let mut option = vec![0u8; (data_offset as usize - 5) * 4];
+    read.read_exact(&mut option)
+        .context("read options failed")?;
+    let options = read_tcp_options(&option);
+    let header = TcpHeader {
+        src_port,
+        dst_port,
+        seq,
+        ack,
+        _data_offset: data_offset,
+        flags,
+        _window: window,
+        _checksum: checksum,
+        _urgent_pointer: urgent_pointer,
+        _options: options,
+    };
+    ctx.transport = Some(Transport::Tcp);
+    ctx.src_port = Some(src_port);
+    ctx.dst_port = Some(dst_port);
+    let data = Vec::from(read);
+    let app = read_l7data(&data, ctx);
+    Ok(Tcp {
+        header,
+        data,
+        app,
+        expected_checksum,
+    })
+}
+
+impl Ipv4data for Tcp {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("{:?}", self.header)];
+        match &self.app {
+            Some(app) => ans.append(app.text().as_mut()),
+            None => ans.push(format!("TcpData {:?}", self.data)),
+        }
+        ans
+    }
+    fn line(&self, src: &std::net::IpAddr, dst: &std::net::IpAddr) -> String {
+        let ack = if self.header.flags.0 & TcpFlags::ACK != 0 {
+            format!(" ack={}", self.header.ack)
+        } else {
+            "".to_string()
+        };
+        let line = format!(
+            "{} → {} TCP {:?} seq={}{} len={}",
+            SocketAddr::new(*src, self.header.src_port),
+            SocketAddr::new(*dst, self.header.dst_port),
+            self.header.flags,
+            self.header.seq,
+            ack,
+            self.data.len()
+        );
+        match &self.app {
+            Some(app) => format!("{} {}", line, app.line()),
+            None => line,
+        }
+    }
+    fn fields(&self) -> Vec<Field> {
+        let h = &self.header;
+        let header_len = h._data_offset as usize * 4;
+        let mut children = vec![
+            Field::new("tcp.srcport", h.src_port).at(0, 2),
+            Field::new("tcp.dstport", h.dst_port).at(2, 2),
+            Field::new("tcp.port", h.src_port).at(0, 2),
+            Field::new("tcp.port", h.dst_port).at(2, 2),
+            Field::new("tcp.seq", h.seq).at(4, 4),
+            Field::new("tcp.ack", h.ack).at(8, 4),
+            Field::new("tcp.hdr_len", header_len).at(12, 1),
+            Field::new("tcp.flags", h.flags.0).at(12, 2),
+        ];
+        for (name, bit) in [
+            ("tcp.flags.fin", 0),
+            ("tcp.flags.syn", 1),
+            ("tcp.flags.reset", 2),
+            ("tcp.flags.push", 3),
+            ("tcp.flags.ack", 4),
+            ("tcp.flags.urg", 5),
+        ] {
+            children.push(Field::new(name, h.flags.0 & 1 << bit != 0).at(13, 1));
+        }
+        children.push(Field::new("tcp.window_size_value", h._window).at(14, 2));
+        children.push(checksum_field(
+            "tcp.checksum",
+            "tcp.checksum.status",
+            h._checksum,
+            self.expected_checksum,
+            16,
+        ));
+        children.push(Field::new("tcp.urgent_pointer", h._urgent_pointer).at(18, 2));
+        if !h._options.is_empty() {
+            let mut options = vec![];
+            let mut offset = 20;
+            for option in &h._options {
+                if let Some(field) = option.field() {
+                    options.append(shifted(vec![field], offset).as_mut());
+                }
+                offset += option.len();
+            }
+            children.push(
+                Field::protocol("tcp.options")
+                    .at(20, header_len - 20)
+                    .with_children(options),
+            );
+        }
+        children.push(Field::new("tcp.len", self.data.len()));
+        if self.app.is_none() && !self.data.is_empty() {
+            children.push(
+                Field::new("tcp.payload", self.data.as_slice()).at(header_len, self.data.len()),
+            );
+        }
+        let mut ans = vec![Field::protocol("tcp")
+            .at(0, header_len)
+            .with_children(children)];
+        if let Some(app) = &self.app {
+            ans.append(shifted(app.fields(), header_len).as_mut());
+        }
+        ans
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_tcp_syn_ack() {
+        let bytes = [
+            0x01u8, 0xbb, 0xd4, 0x31, 0x00, 0x00, 0x10, 0x00, 0x00, 0x00, 0x20, 0x01, 0xa0, 0x12,
+            0xff, 0xff, 0x12, 0x34, 0x00, 0x00, 0x02, 0x04, 0x05, 0xb4, 0x04, 0x02, 0x08, 0x0a,
+            0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x02, 0x01, 0x03, 0x03, 0x07,
+        ];
+        let tcp = read_tcp(bytes.to_vec().into(), &mut Context::default()).unwrap();
+        assert_eq!(tcp.header.src_port, 443);
+        assert_eq!(tcp.header.dst_port, 54321);
+        assert!(matches!(tcp.header._options[0], TcpOption::Mss(1460)));
+        assert!(matches!(tcp.header._options[1], TcpOption::SackPermitted));
+        assert!(matches!(
+            tcp.header._options[2],
+            TcpOption::Timestamps {
+                value: 1,
+                echo_reply: 2
+            }
+        ));
+        assert!(matches!(tcp.header._options[4], TcpOption::WindowScale(7)));
+        let src = "10.0.0.1".parse().unwrap();
+        let dst = "10.0.0.2".parse().unwrap();
+        assert_eq!(
+            tcp.line(&src, &dst),
+            "10.0.0.1:443 → 10.0.0.2:54321 TCP [SYN,ACK] seq=4096 ack=8193 len=0"
+        );
+        let fields = tcp.fields();
+        let field = |name| fields[0].iter().find(|x| x.name() == name).unwrap();
+        assert_eq!(field("tcp.options").offset(), 20);
+        assert_eq!(field("tcp.options").len(), 20);
+        assert_eq!(field("tcp.options.mss_val").value().to_string(), "1460");
+        assert_eq!(field("tcp.options.mss_val").offset(), 22);
+        assert_eq!(field("tcp.options.sack_perm").offset(), 24);
+        assert_eq!(
+            field("tcp.options.timestamp.tsecr").value().to_string(),
+            "2"
+        );
+        assert_eq!(field("tcp.options.timestamp.tsecr").offset(), 32);
+        assert_eq!(field("tcp.options.nop").offset(), 36);
+        assert_eq!(field("tcp.options.wscale.shift").value().to_string(), "7");
+        assert_eq!(field("tcp.options.wscale.shift").offset(), 39);
+    }
+
+    #[test]
+    fn test_read_malformed_option() {
+        // an mss followed by an option shorter than its own header
+        let options = read_tcp_options(&[0x02, 0x04, 0x05, 0xb4, 0x08, 0x01, 0x00, 0x00]);
+        assert_eq!(options.len(), 2);
+        assert!(matches!(options[0], TcpOption::Mss(1460)));
+        assert!(matches!(&options[1], TcpOption::Malformed(x) if x == &[0x08, 0x01, 0x00, 0x00]));
     }
 }
@@ -1,7 +1,25 @@
+use crate::checksum::{checksum_field, pseudo_header};
+use crate::context::Context;
+use crate::field::{shifted, Field, FieldInfo, FieldType};
 use crate::l3data::ipv4data::Ipv4data;
-use anyhow::{Context, Result};
+use crate::l7data::{read_l7data, L7data};
+use crate::registry::Transport;
+use anyhow::{bail, Context as _, Result};
 use byteorder::NetworkEndian;
 use byteorder::ReadBytesExt;
+use std::net::SocketAddr;
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("udp", "User Datagram Protocol", FieldType::Protocol),
+    FieldInfo::new("udp.srcport", "Source Port", FieldType::Int),
+    FieldInfo::new("udp.dstport", "Destination Port", FieldType::Int),
+    FieldInfo::new("udp.port", "Port", FieldType::Int),
+    FieldInfo::new("udp.length", "Length", FieldType::Int),
+    FieldInfo::new("udp.checksum", "Checksum", FieldType::Int).hex(),
+    FieldInfo::new("udp.checksum.status", "Checksum Status", FieldType::Str),
+    FieldInfo::new("udp.payload", "UDP payload", FieldType::Bytes),
+];
 
 #[derive(Debug)]
 pub struct UdpHeader {
@@ -15,9 +33,12 @@ pub struct UdpHeader {
 pub struct Udp {
     header: UdpHeader,
     data: Vec<u8>,
+    app: Option<Box<dyn L7data>>,
+    // set when the checksum is verified
+    expected_checksum: Option<u16>,
 }
 
-pub fn read_udp(mut read: std::collections::VecDeque<u8>) -> Result<Udp> {
+pub fn read_udp(mut read: std::collections::VecDeque<u8>, ctx: &mut Context) -> Result<Udp> {
     let src_port = read
         .read_u16::<NetworkEndian>()
         .context("read src_port failed")?;
@@ -26,27 +47,96 @@ pub fn read_udp(mut read: std::collections::VecDeque<u8>) -> Result<Udp> {
         .context("read dst_port failed")?;
     let _len = read.read_u16::<NetworkEndian>()?;
     let _checksum = read.read_u16::<NetworkEndian>()?;
+    // a zero length is left by jumbograms and segmentation offload
+    if _len != 0 {
+        if _len < 8 {
+            bail!("udp length {} is shorter than its header", _len);
+        }
+        // a fragment carries only part of what the length covers
+        if _len as usize - 8 > read.len() && !ctx.truncated && !ctx.fragmented {
+            bail!("udp length {} overruns the packet", _len);
+        }
+        read.truncate(_len as usize - 8);
+    }
+    let expected_checksum = match pseudo_header(ctx, 17, 8 + read.len()) {
+        // a zero checksum over ipv4 means the sender computed none
+        Some(_) if _checksum == 0 && ctx.src.is_some_and(|x| x.is_ipv4()) => None,
+        Some(mut sum) => {
+            sum.add(&src_port.to_be_bytes());
+            sum.add(&dst_port.to_be_bytes());
+            sum.add(&_len.to_be_bytes());
+            sum.add(read.make_contiguous());
+            // a computed zero is sent as all ones
+            Some(match sum.finish() {
+                0 => 0xffff,
+                x => x,
+            })
+        }
+        None => None,
+    };
     let header = UdpHeader {
         src_port,
         dst_port,
         _len,
         _checksum,
     };
+    ctx.transport = Some(Transport::Udp);
+    ctx.src_port = Some(src_port);
+    ctx.dst_port = Some(dst_port);
     let data = Vec::from(read);
-    Ok(Udp { header, data })
+    let app = read_l7data(&data, ctx);
+    Ok(Udp {
+        header,
+        data,
+        app,
+        expected_checksum,
+    })
 }
 
 impl Ipv4data for Udp {
     fn text(&self) -> Vec<String> {
-        vec![
-            format!("{:?}", self.header),
-            format!("UdpData {:?}", self.data),
-        ]
+        let mut ans = vec![format!("{:?}", self.header)];
+        match &self.app {
+            Some(app) => ans.append(app.text().as_mut()),
+            None => ans.push(format!("UdpData {:?}", self.data)),
+        }
+        ans
     }
-    fn line(&self, src: &std::net::Ipv4Addr, dst: &std::net::Ipv4Addr) -> String {
+    fn line(&self, src: &std::net::IpAddr, dst: &std::net::IpAddr) -> String {
+        let protocol = match &self.app {
+            Some(app) => app.line(),
+            None => "UDP".to_string(),
+        };
         format!(
-            "{}:{} → {}:{} UDP",
-            src, self.header.src_port, dst, self.header.dst_port
+            "{} → {} {}",
+            SocketAddr::new(*src, self.header.src_port),
+            SocketAddr::new(*dst, self.header.dst_port),
+            protocol
         )
     }
+    fn fields(&self) -> Vec<Field> {
+        let h = &self.header;
+        let mut children = vec![
+            Field::new("udp.srcport", h.src_port).at(0, 2),
+            Field::new("udp.dstport", h.dst_port).at(2, 2),
+            Field::new("udp.port", h.src_port).at(0, 2),
+            Field::new("udp.port", h.dst_port).at(2, 2),
+            Field::new("udp.length", h._len).at(4, 2),
+            checksum_field(
+                "udp.checksum",
+                "udp.checksum.status",
+                h._checksum,
+                self.expected_checksum,
+                6,
+            ),
+        ];
+        if self.app.is_none() {
+            children.push(Field::new("udp.payload", self.data.as_slice()).at(8, self.data.len()));
+        }
+        let mut ans = vec![Field::protocol("udp").at(0, 8).with_children(children)];
+        if let Some(app) = &self.app {
+            ans.append(shifted(app.fields(), 8).as_mut());
+        }
+        ans
+    }
 }
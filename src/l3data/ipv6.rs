@@ -0,0 +1,618 @@
+use super::reassembly::{FragmentFields, Reassembly};
+use crate::context::Context;
+use crate::field::{detached, shifted, Field, FieldInfo, FieldType};
+use crate::l3data::ipv4data::other::read_other;
+use crate::l3data::ipv4data::{read_ipv4data, Ipv4data};
+use crate::l3data::{L3data, Result};
+use anyhow::{bail, Context as _};
+use byteorder::{NetworkEndian, ReadBytesExt};
+use std::io::Read;
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("ipv6", "Internet Protocol Version 6", FieldType::Protocol),
+    FieldInfo::new("ipv6.tclass", "Traffic Class", FieldType::Int).hex(),
+    FieldInfo::new("ipv6.flow", "Flow Label", FieldType::Int).hex(),
+    FieldInfo::new("ipv6.plen", "Payload Length", FieldType::Int),
+    FieldInfo::new("ipv6.nxt", "Next Header", FieldType::Int),
+    FieldInfo::new("ipv6.hlim", "Hop Limit", FieldType::Int),
+    FieldInfo::new("ipv6.src", "Source Address", FieldType::Addr),
+    FieldInfo::new("ipv6.dst", "Destination Address", FieldType::Addr),
+    FieldInfo::new("ipv6.addr", "Address", FieldType::Addr),
+    FieldInfo::new("ipv6.src_host", "Source Host", FieldType::Str),
+    FieldInfo::new("ipv6.dst_host", "Destination Host", FieldType::Str),
+    FieldInfo::new("ipv6.host", "Host", FieldType::Str),
+    FieldInfo::new("ipv6.hopopts", "Hop-by-Hop Option", FieldType::Protocol),
+    FieldInfo::new("ipv6.hopopts.nxt", "Next Header", FieldType::Int),
+    FieldInfo::new("ipv6.hopopts.len", "Length", FieldType::Int),
+    FieldInfo::new("ipv6.routing", "Routing Header", FieldType::Protocol),
+    FieldInfo::new("ipv6.routing.nxt", "Next Header", FieldType::Int),
+    FieldInfo::new("ipv6.routing.len", "Length", FieldType::Int),
+    FieldInfo::new("ipv6.routing.type", "Type", FieldType::Int),
+    FieldInfo::new("ipv6.routing.segleft", "Segments Left", FieldType::Int),
+    FieldInfo::new("ipv6.fraghdr", "Fragment Header", FieldType::Protocol),
+    FieldInfo::new("ipv6.fraghdr.nxt", "Next header", FieldType::Int),
+    FieldInfo::new("ipv6.fraghdr.offset", "Offset", FieldType::Int),
+    FieldInfo::new("ipv6.fraghdr.more", "More Fragments", FieldType::Int),
+    FieldInfo::new("ipv6.fraghdr.ident", "Identification", FieldType::Int).hex(),
+    FieldInfo::new("ipv6.dstopts", "Destination Options", FieldType::Protocol),
+    FieldInfo::new("ipv6.dstopts.nxt", "Next Header", FieldType::Int),
+    FieldInfo::new("ipv6.dstopts.len", "Length", FieldType::Int),
+    FieldInfo::new("ah", "Authentication Header", FieldType::Protocol),
+    FieldInfo::new("ah.next_header", "Next header", FieldType::Int),
+    FieldInfo::new("ah.length", "Length", FieldType::Int),
+    FieldInfo::new("ah.spi", "AH SPI", FieldType::Int).hex(),
+    FieldInfo::new("ah.sequence", "AH Sequence", FieldType::Int),
+    FieldInfo::new("ah.icv", "AH ICV", FieldType::Bytes),
+    FieldInfo::new("esp", "Encapsulating Security Payload", FieldType::Protocol),
+    FieldInfo::new("esp.spi", "ESP SPI", FieldType::Int).hex(),
+    FieldInfo::new("esp.sequence", "ESP Sequence", FieldType::Int),
+    FieldInfo::new(
+        "ipv6.reassembled_in",
+        "Reassembled IPv6 in frame",
+        FieldType::Int,
+    ),
+    FieldInfo::new("ipv6.fragments", "IPv6 Fragments", FieldType::Protocol),
+    FieldInfo::new("ipv6.fragment", "Frame", FieldType::Int),
+    FieldInfo::new("ipv6.fragment.count", "Fragment count", FieldType::Int),
+    FieldInfo::new("ipv6.fragment.overlap", "Fragment overlap", FieldType::Int),
+    FieldInfo::new(
+        "ipv6.fragment.overlap.conflict",
+        "Conflicting data in fragment overlap",
+        FieldType::Int,
+    ),
+    FieldInfo::new(
+        "ipv6.reassembled.length",
+        "Reassembled IPv6 length",
+        FieldType::Int,
+    ),
+];
+
+const FRAGMENT_FIELDS: FragmentFields = FragmentFields {
+    fragments: "ipv6.fragments",
+    fragment: "ipv6.fragment",
+    count: "ipv6.fragment.count",
+    overlap: "ipv6.fragment.overlap",
+    conflict: "ipv6.fragment.overlap.conflict",
+    length: "ipv6.reassembled.length",
+};
+
+const HOP_BY_HOP: u8 = 0;
+const ROUTING: u8 = 43;
+const FRAGMENT: u8 = 44;
+const ESP: u8 = 50;
+const AH: u8 = 51;
+const NO_NEXT_HEADER: u8 = 59;
+const DESTINATION_OPTIONS: u8 = 60;
+
+#[derive(Debug)]
+enum ExtensionHeader {
+    HopByHop {
+        _options: Vec<u8>,
+    },
+    Routing {
+        _routing_type: u8,
+        _segments_left: u8,
+        _data: Vec<u8>,
+    },
+    Fragment {
+        offset: u16,
+        more: bool,
+        id: u32,
+    },
+    DestinationOptions {
+        _options: Vec<u8>,
+    },
+    Ah {
+        _spi: u32,
+        _sequence: u32,
+        _icv: Vec<u8>,
+    },
+    Esp {
+        _spi: u32,
+        _sequence: u32,
+    },
+}
+
+impl ExtensionHeader {
+    /// Length of the header on the wire.
+    fn len(&self) -> usize {
+        match self {
+            ExtensionHeader::HopByHop { _options } => 2 + _options.len(),
+            ExtensionHeader::DestinationOptions { _options } => 2 + _options.len(),
+            ExtensionHeader::Routing { _data, .. } => 4 + _data.len(),
+            ExtensionHeader::Fragment { .. } => 8,
+            ExtensionHeader::Ah { _icv, .. } => 12 + _icv.len(),
+            ExtensionHeader::Esp { .. } => 8,
+        }
+    }
+    /// The header as it is on the wire, `next` being the next header it
+    /// names. Lengths are left in the units the header counts them in.
+    fn field(&self, next: u8) -> Field {
+        let (name, children) = match self {
+            ExtensionHeader::HopByHop { .. } => (
+                "ipv6.hopopts",
+                vec![
+                    Field::new("ipv6.hopopts.nxt", next).at(0, 1),
+                    Field::new("ipv6.hopopts.len", self.len() / 8 - 1).at(1, 1),
+                ],
+            ),
+            ExtensionHeader::DestinationOptions { .. } => (
+                "ipv6.dstopts",
+                vec![
+                    Field::new("ipv6.dstopts.nxt", next).at(0, 1),
+                    Field::new("ipv6.dstopts.len", self.len() / 8 - 1).at(1, 1),
+                ],
+            ),
+            ExtensionHeader::Routing {
+                _routing_type,
+                _segments_left,
+                ..
+            } => (
+                "ipv6.routing",
+                vec![
+                    Field::new("ipv6.routing.nxt", next).at(0, 1),
+                    Field::new("ipv6.routing.len", self.len() / 8 - 1).at(1, 1),
+                    Field::new("ipv6.routing.type", *_routing_type).at(2, 1),
+                    Field::new("ipv6.routing.segleft", *_segments_left).at(3, 1),
+                ],
+            ),
+            ExtensionHeader::Fragment { offset, more, id } => (
+                "ipv6.fraghdr",
+                vec![
+                    Field::new("ipv6.fraghdr.nxt", next).at(0, 1),
+                    Field::new("ipv6.fraghdr.offset", *offset).at(2, 2),
+                    Field::new("ipv6.fraghdr.more", *more).at(3, 1),
+                    Field::new("ipv6.fraghdr.ident", *id).at(4, 4),
+                ],
+            ),
+            ExtensionHeader::Ah {
+                _spi,
+                _sequence,
+                _icv,
+            } => (
+                "ah",
+                vec![
+                    Field::new("ah.next_header", next).at(0, 1),
+                    Field::new("ah.length", self.len() / 4 - 2).at(1, 1),
+                    Field::new("ah.spi", *_spi).at(4, 4),
+                    Field::new("ah.sequence", *_sequence).at(8, 4),
+                    Field::new("ah.icv", _icv.as_slice()).at(12, _icv.len()),
+                ],
+            ),
+            // the next header is encrypted
+            ExtensionHeader::Esp { _spi, _sequence } => (
+                "esp",
+                vec![
+                    Field::new("esp.spi", *_spi).at(0, 4),
+                    Field::new("esp.sequence", *_sequence).at(4, 4),
+                ],
+            ),
+        };
+        Field::protocol(name)
+            .at(0, self.len())
+            .with_children(children)
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Ipv6Header {
+    _traffic_class: u8,
+    _flow_label: u32,
+    _payload_len: u16,
+    _next_header: u8,
+    _hop_limit: u8,
+    _src: std::net::Ipv6Addr,
+    _dst: std::net::Ipv6Addr,
+    // with the next header each of them names
+    _extension_headers: Vec<(ExtensionHeader, u8)>,
+}
+
+#[derive(Debug)]
+pub struct Ipv6 {
+    _header: Ipv6Header,
+    payload: Box<dyn Ipv4data>,
+    // set for fragments, the payload of the last one is the whole datagram
+    reassembly: Option<Reassembly>,
+    // names the capture file resolved the source and destination to
+    hosts: [Option<String>; 2],
+}
+
+impl Ipv6Header {
+    pub(crate) fn src(&self) -> std::net::Ipv6Addr {
+        self._src
+    }
+    pub(crate) fn dst(&self) -> std::net::Ipv6Addr {
+        self._dst
+    }
+}
+
+impl L3data for Ipv6 {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("{:?}", self._header)];
+        if let Some(reassembly) = &self.reassembly {
+            ans.push(format!("{:?}", reassembly));
+        }
+        ans.append(self.payload.text().as_mut());
+        ans
+    }
+    fn line(&self) -> String {
+        let h = &self._header;
+        let pending = match &self.reassembly {
+            Some(Reassembly::Pending(x)) => h._extension_headers.last().map(|y| (x, y)),
+            _ => None,
+        };
+        let Some((reassembled_in, (ExtensionHeader::Fragment { offset, more, id }, next))) =
+            pending
+        else {
+            return self.payload.line(&h._src.into(), &h._dst.into());
+        };
+        let line = format!(
+            "{} → {} IPv6 fragment (off={} more={} ident={:#010x} nxt={})",
+            h._src,
+            h._dst,
+            *offset as usize * 8,
+            if *more { "y" } else { "n" },
+            id,
+            next
+        );
+        match reassembled_in.get() {
+            Some(frame) => format!("{} [Reassembled in #{}]", line, frame),
+            None => line,
+        }
+    }
+    fn fields(&self) -> Vec<Field> {
+        let h = &self._header;
+        let header_len = 40
+            + h._extension_headers
+                .iter()
+                .map(|(x, _)| x.len())
+                .sum::<usize>();
+        let mut children = vec![
+            Field::new("ipv6.tclass", h._traffic_class).at(0, 2),
+            Field::new("ipv6.flow", h._flow_label).at(1, 3),
+            Field::new("ipv6.plen", h._payload_len).at(4, 2),
+            Field::new("ipv6.nxt", h._next_header).at(6, 1),
+            Field::new("ipv6.hlim", h._hop_limit).at(7, 1),
+            Field::new("ipv6.src", h._src).at(8, 16),
+            Field::new("ipv6.dst", h._dst).at(24, 16),
+            Field::new("ipv6.addr", h._src).at(8, 16),
+            Field::new("ipv6.addr", h._dst).at(24, 16),
+        ];
+        for (host, offset, name) in [(0, 8, "ipv6.src_host"), (1, 24, "ipv6.dst_host")] {
+            if let Some(x) = &self.hosts[host] {
+                children.push(Field::new(name, x.as_str()).at(offset, 16));
+                children.push(Field::new("ipv6.host", x.as_str()).at(offset, 16));
+            }
+        }
+        let mut offset = 40;
+        for (header, next) in &h._extension_headers {
+            children.append(shifted(vec![header.field(*next)], offset).as_mut());
+            offset += header.len();
+        }
+        let reassembled_in = match &self.reassembly {
+            Some(Reassembly::Pending(x)) => x.get(),
+            _ => None,
+        };
+        if let Some(frame) = reassembled_in {
+            children.push(Field::new("ipv6.reassembled_in", *frame));
+        }
+        let mut ans = vec![Field::protocol("ipv6")
+            .at(0, header_len)
+            .with_children(children)];
+        match &self.reassembly {
+            // the datagram isn't in the frame as it is
+            Some(Reassembly::Complete(x)) => {
+                ans.push(x.field(&FRAGMENT_FIELDS));
+                ans.append(detached(self.payload.fields()).as_mut());
+            }
+            _ => ans.append(shifted(self.payload.fields(), header_len).as_mut()),
+        }
+        ans
+    }
+}
+
+pub(crate) fn read_ipv6_addr<T: Read>(read: &mut T) -> Result<std::net::Ipv6Addr> {
+    let mut octets = [0u8; 16];
+    read.read_exact(&mut octets)?;
+    Ok(octets.into())
+}
+
+/// Reads the generic next header / length prefixed option headers and
+/// returns (next_header, option bytes).
+fn read_options_header<T: Read>(read: &mut T) -> Result<(u8, Vec<u8>)> {
+    let next_header = read.read_u8()?;
+    let len = read.read_u8()?;
+    let mut options = vec![0u8; len as usize * 8 + 6];
+    read.read_exact(&mut options)?;
+    Ok((next_header, options))
+}
+
+fn read_extension_header<T: Read>(read: &mut T, kind: u8) -> Result<(u8, ExtensionHeader)> {
+    match kind {
+        HOP_BY_HOP => {
+            let (next, options) = read_options_header(read)?;
+            Ok((next, ExtensionHeader::HopByHop { _options: options }))
+        }
+        DESTINATION_OPTIONS => {
+            let (next, options) = read_options_header(read)?;
+            Ok((
+                next,
+                ExtensionHeader::DestinationOptions { _options: options },
+            ))
+        }
+        ROUTING => {
+            let (next, data) = read_options_header(read)?;
+            Ok((
+                next,
+                ExtensionHeader::Routing {
+                    _routing_type: data[0],
+                    _segments_left: data[1],
+                    _data: data[2..].to_vec(),
+                },
+            ))
+        }
+        FRAGMENT => {
+            let next = read.read_u8()?;
+            let _reserved = read.read_u8()?;
+            let tmp = read.read_u16::<NetworkEndian>()?;
+            let id = read.read_u32::<NetworkEndian>()?;
+            Ok((
+                next,
+                ExtensionHeader::Fragment {
+                    offset: tmp >> 3,
+                    more: tmp & 1 == 1,
+                    id,
+                },
+            ))
+        }
+        AH => {
+            let next = read.read_u8()?;
+            let len = read.read_u8()?;
+            let _reserved = read.read_u16::<NetworkEndian>()?;
+            let spi = read.read_u32::<NetworkEndian>()?;
+            let sequence = read.read_u32::<NetworkEndian>()?;
+            // payload len is in 4 octet units minus 2, the fixed part is 12 octets
+            let icv_len = (len as usize + 2) * 4;
+            if icv_len < 12 {
+                bail!("invalid ah length {}", len);
+            }
+            let mut icv = vec![0u8; icv_len - 12];
+            read.read_exact(&mut icv)?;
+            Ok((
+                next,
+                ExtensionHeader::Ah {
+                    _spi: spi,
+                    _sequence: sequence,
+                    _icv: icv,
+                },
+            ))
+        }
+        ESP => {
+            let spi = read.read_u32::<NetworkEndian>()?;
+            let sequence = read.read_u32::<NetworkEndian>()?;
+            // the rest is encrypted, including the next header
+            Ok((
+                NO_NEXT_HEADER,
+                ExtensionHeader::Esp {
+                    _spi: spi,
+                    _sequence: sequence,
+                },
+            ))
+        }
+        _ => bail!("not an extension header {}", kind),
+    }
+}
+
+fn is_extension_header(kind: u8) -> bool {
+    matches!(
+        kind,
+        HOP_BY_HOP | ROUTING | FRAGMENT | DESTINATION_OPTIONS | AH | ESP
+    )
+}
+
+/// Reads the fixed 40 octet header, extension headers are left in `read`.
+pub(crate) fn read_ipv6_header<T: Read>(read: &mut T) -> Result<Ipv6Header> {
+    let tmp = read.read_u32::<NetworkEndian>()?;
+    let version = tmp >> 28;
+    if version != 6 {
+        bail!("ip version {} is not 6", version);
+    }
+    let traffic_class = (tmp >> 20) as u8;
+    let flow_label = tmp & 0x000f_ffff;
+    let payload_len = read.read_u16::<NetworkEndian>()?;
+    let next_header = read.read_u8()?;
+    let hop_limit = read.read_u8()?;
+    let src = read_ipv6_addr(read).context("read src")?;
+    let dst = read_ipv6_addr(read).context("read dst")?;
+    Ok(Ipv6Header {
+        _traffic_class: traffic_class,
+        _flow_label: flow_label,
+        _payload_len: payload_len,
+        _next_header: next_header,
+        _hop_limit: hop_limit,
+        _src: src,
+        _dst: dst,
+        _extension_headers: vec![],
+    })
+}
+
+pub fn read_ipv6(mut read: std::collections::VecDeque<u8>, ctx: &mut Context) -> Result<Ipv6> {
+    let mut header = read_ipv6_header(&mut read)?;
+    ctx.src = Some(header._src.into());
+    ctx.dst = Some(header._dst.into());
+    let hosts = [ctx.host(header._src.into()), ctx.host(header._dst.into())];
+    // a zero payload length means a jumbogram, keep everything then
+    if header._payload_len != 0 {
+        if header._payload_len as usize > read.len() && !ctx.truncated {
+            bail!(
+                "ipv6 payload length {} overruns the packet",
+                header._payload_len
+            );
+        }
+        read.truncate(header._payload_len as usize);
+    }
+    let mut extension_headers = vec![];
+    let mut protocol = header._next_header;
+    let mut fragment = None;
+    // what follows a fragment header is only whole in the reassembled
+    // datagram
+    while is_extension_header(protocol) && fragment.is_none() {
+        let (next, header) = read_extension_header(&mut read, protocol)
+            .with_context(|| format!("read extension header {}", protocol))?;
+        // an atomic fragment is a whole datagram
+        if let ExtensionHeader::Fragment { offset, more, id } = header {
+            if offset != 0 || more {
+                fragment = Some((offset, more, id));
+            }
+        }
+        extension_headers.push((header, next));
+        protocol = next;
+    }
+    header._extension_headers = extension_headers;
+    ctx.fragmented = fragment.is_some();
+    // fragments cut short can't be put together, and only the first one
+    // starts with the upper layer header
+    let Some((offset, more, id)) = fragment.filter(|_| !ctx.truncated) else {
+        let payload: Box<dyn Ipv4data> = match fragment {
+            Some((offset, _, _)) if offset != 0 => Box::new(read_other(read, protocol)?),
+            _ if protocol == NO_NEXT_HEADER => Box::new(read_other(read, protocol)?),
+            _ => read_ipv4data(read, protocol, ctx)?,
+        };
+        return Ok(Ipv6 {
+            _header: header,
+            payload,
+            reassembly: None,
+            hosts,
+        });
+    };
+    let key = (header._src.into(), header._dst.into(), id, protocol);
+    let (reassembly, datagram) = ctx.fragments.add(
+        key,
+        offset as usize * 8,
+        read.make_contiguous(),
+        more,
+        ctx.number(),
+        ctx.timestamp(),
+    )?;
+    ctx.pending = matches!(reassembly, Reassembly::Pending(_));
+    let payload = match datagram {
+        Some(datagram) => {
+            ctx.fragmented = false;
+            read_ipv4data(datagram.into(), protocol, ctx).context("read reassembled datagram")?
+        }
+        None => Box::new(read_other(read, protocol)?),
+    };
+    Ok(Ipv6 {
+        _header: header,
+        payload,
+        reassembly: Some(reassembly),
+        hosts,
+    })
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_read_ipv6_with_extension_headers() {
+        let mut bytes = vec![0x60u8, 0x00, 0x00, 0x00, 0x00, 0x20, 0x00, 0x40];
+        bytes.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+        bytes.extend_from_slice(&[0u8; 11]);
+        bytes.push(0x01);
+        bytes.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+        bytes.extend_from_slice(&[0u8; 11]);
+        bytes.push(0x02);
+        // hop-by-hop with a PadN option
+        bytes.extend_from_slice(&[0x2c, 0x00, 0x01, 0x04, 0x00, 0x00, 0x00, 0x00]);
+        // an atomic fragment, the datagram is whole
+        bytes.extend_from_slice(&[0x11, 0x00, 0x00, 0x00, 0x12, 0x34, 0x56, 0x78]);
+        bytes.extend_from_slice(&[0x00, 0x35, 0xd4, 0x31, 0x00, 0x10, 0x00, 0x00]);
+        bytes.extend_from_slice(&[0xaa; 8]);
+        let ipv6 = read_ipv6(bytes.into(), &mut Context::default()).unwrap();
+        assert_eq!(ipv6._header._extension_headers.len(), 2);
+        assert!(matches!(
+            ipv6._header._extension_headers[1],
+            (
+                ExtensionHeader::Fragment {
+                    offset: 0,
+                    more: false,
+                    ..
+                },
+                0x11
+            )
+        ));
+        assert_eq!(ipv6.line(), "[2001:db8::1]:53 → [2001:db8::2]:54321 UDP");
+        let fields = ipv6.fields();
+        let field = |name| {
+            fields
+                .iter()
+                .flat_map(|x| x.iter())
+                .find(|x| x.name() == name)
+                .unwrap()
+        };
+        assert_eq!(field("ipv6.hopopts.nxt").value().to_string(), "44");
+        assert_eq!(field("ipv6.hopopts.nxt").offset(), 40);
+        assert_eq!(field("ipv6.hopopts.len").value().to_string(), "0");
+        assert_eq!(field("ipv6.fraghdr").offset(), 48);
+        assert_eq!(field("ipv6.fraghdr.nxt").value().to_string(), "17");
+        assert_eq!(field("ipv6.fraghdr.ident").value().to_string(), "305419896");
+        assert_eq!(field("ipv6.fraghdr.more").value().to_string(), "0");
+        assert_eq!(field("udp").offset(), 56);
+    }
+
+    fn fragment(offset: u16, more: bool, payload: &[u8]) -> Vec<u8> {
+        let len = 8 + payload.len() as u16;
+        let mut bytes = vec![0x60u8, 0x00, 0x00, 0x00];
+        bytes.extend_from_slice(&len.to_be_bytes());
+        bytes.extend_from_slice(&[0x2c, 0x40]);
+        bytes.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+        bytes.extend_from_slice(&[0u8; 11]);
+        bytes.push(0x01);
+        bytes.extend_from_slice(&[0x20, 0x01, 0x0d, 0xb8]);
+        bytes.extend_from_slice(&[0u8; 11]);
+        bytes.push(0x02);
+        bytes.extend_from_slice(&[0x11, 0x00]);
+        bytes.extend_from_slice(&(offset << 3 | u16::from(more)).to_be_bytes());
+        bytes.extend_from_slice(&[0x12, 0x34, 0x56, 0x78]);
+        bytes.extend_from_slice(payload);
+        bytes
+    }
+
+    #[test]
+    fn test_reassemble() {
+        // the udp length covers both fragments
+        let mut datagram = vec![0x00, 0x35, 0xd4, 0x31, 0x00, 0x20, 0x00, 0x00];
+        datagram.extend_from_slice(&[0xaa; 24]);
+        let mut ctx = Context::default();
+        ctx.begin(chrono::Local::now());
+        let first = read_ipv6(fragment(0, true, &datagram[..24]).into(), &mut ctx).unwrap();
+        assert_eq!(
+            first.line(),
+            "2001:db8::1 → 2001:db8::2 IPv6 fragment (off=0 more=y ident=0x12345678 nxt=17)"
+        );
+        ctx.begin(chrono::Local::now());
+        let last = read_ipv6(fragment(3, false, &datagram[24..]).into(), &mut ctx).unwrap();
+        assert_eq!(last.line(), "[2001:db8::1]:53 → [2001:db8::2]:54321 UDP");
+        assert!(first.line().ends_with(" [Reassembled in #2]"));
+        let value = |x: &Ipv6, name| {
+            let fields = x.fields();
+            let field = fields
+                .iter()
+                .flat_map(|x| x.iter())
+                .find(|x| x.name() == name)?;
+            Some(field.value().to_string())
+        };
+        assert_eq!(value(&first, "ipv6.reassembled_in").unwrap(), "2");
+        assert_eq!(value(&last, "ipv6.fragment.count").unwrap(), "2");
+        assert_eq!(value(&last, "ipv6.reassembled.length").unwrap(), "32");
+        assert_eq!(value(&last, "udp.length").unwrap(), "32");
+        // a first fragment cut short still shows its udp header
+        ctx.begin(chrono::Local::now());
+        ctx.truncated = true;
+        let cut = read_ipv6(fragment(0, true, &datagram[..24]).into(), &mut ctx).unwrap();
+        assert_eq!(cut.line(), "[2001:db8::1]:53 → [2001:db8::2]:54321 UDP");
+        assert!(cut.reassembly.is_none());
+    }
+}
@@ -1,3 +1,4 @@
+use crate::field::Field;
 use crate::l3data::L3data;
 use anyhow::Result;
 
@@ -5,6 +6,7 @@ use anyhow::Result;
 pub struct OtherL3data {
     _type: u16,
     _payload: Vec<u8>,
+    truncated: bool,
 }
 
 impl L3data for OtherL3data {
@@ -12,11 +14,38 @@ impl L3data for OtherL3data {
         vec![format!("L3Data {:x?}", self._payload)]
     }
     fn line(&self) -> String {
+        if self.truncated {
+            return format!("type({}) [truncated]", self._type);
+        }
         format!("type({})", self._type)
     }
+    fn fields(&self) -> Vec<Field> {
+        let name = if self.truncated { "truncated" } else { "data" };
+        vec![Field::protocol(name)
+            .at(0, self._payload.len())
+            .with_children(vec![
+                Field::new("data.data", self._payload.as_slice()).at(0, self._payload.len())
+            ])]
+    }
 }
 
 pub fn read_otherl3data(read: std::collections::VecDeque<u8>, _type: u16) -> Result<OtherL3data> {
     let _payload = Vec::from(read);
-    Ok(OtherL3data { _type, _payload })
+    Ok(OtherL3data {
+        _type,
+        _payload,
+        truncated: false,
+    })
+}
+
+/// Keeps the bytes of a packet cut short before its network layer header ended.
+pub(crate) fn read_truncated_l3data(
+    read: std::collections::VecDeque<u8>,
+    _type: u16,
+) -> OtherL3data {
+    OtherL3data {
+        _type,
+        _payload: Vec::from(read),
+        truncated: true,
+    }
 }
@@ -0,0 +1,298 @@
+use crate::field::Field;
+use anyhow::{bail, Result};
+use chrono::{DateTime, Duration, Local};
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, OnceLock};
+
+/// How long fragments wait for the rest of their datagram, like Linux's
+/// ipfrag_time.
+const TIMEOUT_SECS: i64 = 30;
+const MAX_DATAGRAM_LEN: usize = 65535;
+/// How many datagrams and bytes may wait at once, so that fragments which
+/// never complete can't exhaust memory. The least recently seen go first.
+const MAX_DATAGRAMS: usize = 1024;
+const MAX_BUFFERED: usize = 4 * 1024 * 1024;
+
+/// (src, dst, id, protocol)
+pub(crate) type FragmentKey = (IpAddr, IpAddr, u32, u8);
+
+/// Where a fragment put its bytes into the datagram.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct FragmentInfo {
+    pub(crate) frame: usize,
+    pub(crate) offset: usize,
+    pub(crate) len: usize,
+}
+
+#[derive(Debug)]
+pub(crate) enum Reassembly {
+    /// More fragments are missing. The cell is set to the frame that
+    /// completes the datagram once it is seen.
+    Pending(Arc<OnceLock<usize>>),
+    /// The fragment completed the datagram.
+    Complete(Reassembled),
+}
+
+#[derive(Debug)]
+pub(crate) struct Reassembled {
+    /// Sorted by offset.
+    pub(crate) fragments: Vec<FragmentInfo>,
+    pub(crate) len: usize,
+    /// Some fragments carried bytes received before.
+    pub(crate) overlap: bool,
+    /// Overlapping bytes or the datagram lengths disagreed, the first
+    /// received win.
+    pub(crate) conflict: bool,
+}
+
+/// Names of the fields describing a reassembled datagram, which differ
+/// between IPv4 and IPv6.
+pub(crate) struct FragmentFields {
+    pub(crate) fragments: &'static str,
+    pub(crate) fragment: &'static str,
+    pub(crate) count: &'static str,
+    pub(crate) overlap: &'static str,
+    pub(crate) conflict: &'static str,
+    pub(crate) length: &'static str,
+}
+
+impl Reassembled {
+    /// The fragments the datagram was put together from.
+    pub(crate) fn field(&self, names: &FragmentFields) -> Field {
+        let mut children: Vec<Field> = self
+            .fragments
+            .iter()
+            .map(|x| {
+                let end = x.offset + x.len;
+                Field::new(names.fragment, x.frame)
+                    .with_note(format!("payload: {}-{} ({} bytes)", x.offset, end, x.len))
+            })
+            .collect();
+        children.push(Field::new(names.count, self.fragments.len()));
+        if self.overlap {
+            children.push(Field::new(names.overlap, true));
+        }
+        if self.conflict {
+            children.push(Field::new(names.conflict, true));
+        }
+        children.push(Field::new(names.length, self.len));
+        Field::protocol(names.fragments).with_children(children)
+    }
+}
+
+#[derive(Debug)]
+struct Datagram {
+    data: Vec<u8>,
+    received: Vec<bool>,
+    len: Option<usize>,
+    fragments: Vec<FragmentInfo>,
+    overlap: bool,
+    conflict: bool,
+    last_seen: DateTime<Local>,
+    reassembled_in: Arc<OnceLock<usize>>,
+}
+
+impl Datagram {
+    fn new(timestamp: DateTime<Local>) -> Self {
+        Self {
+            data: vec![],
+            received: vec![],
+            len: None,
+            fragments: vec![],
+            overlap: false,
+            conflict: false,
+            last_seen: timestamp,
+            reassembled_in: Arc::new(OnceLock::new()),
+        }
+    }
+    fn insert(&mut self, offset: usize, data: &[u8]) {
+        let end = offset + data.len();
+        if self.data.len() < end {
+            self.data.resize(end, 0);
+            self.received.resize(end, false);
+        }
+        for (i, x) in data.iter().enumerate() {
+            if self.received[offset + i] {
+                self.overlap = true;
+                self.conflict |= self.data[offset + i] != *x;
+            } else {
+                self.data[offset + i] = *x;
+                self.received[offset + i] = true;
+            }
+        }
+    }
+    fn is_complete(&self) -> bool {
+        self.len
+            .is_some_and(|x| self.received.len() >= x && self.received[..x].iter().all(|x| *x))
+    }
+}
+
+/// Fragments of IP datagrams waiting for the rest of them.
+#[derive(Debug, Default)]
+pub struct FragmentTable {
+    datagrams: HashMap<FragmentKey, Datagram>,
+}
+
+impl FragmentTable {
+    /// Adds the fragment at `offset` bytes of its datagram, and returns the
+    /// whole payload too once it completes the datagram.
+    pub(crate) fn add(
+        &mut self,
+        key: FragmentKey,
+        offset: usize,
+        data: &[u8],
+        more: bool,
+        frame: usize,
+        timestamp: DateTime<Local>,
+    ) -> Result<(Reassembly, Option<Vec<u8>>)> {
+        let end = offset + data.len();
+        if end > MAX_DATAGRAM_LEN {
+            bail!("fragment ends at {} beyond the largest datagram", end);
+        }
+        let timeout = Duration::seconds(TIMEOUT_SECS);
+        self.datagrams
+            .retain(|_, x| timestamp - x.last_seen <= timeout);
+        let buffered = self.datagrams.get(&key).map(|x| x.data.len());
+        if buffered.is_none() && self.datagrams.len() >= MAX_DATAGRAMS {
+            self.evict(&key);
+        }
+        let growth = end.saturating_sub(buffered.unwrap_or(0));
+        while self.buffered() + growth > MAX_BUFFERED && self.evict(&key) {}
+        let datagram = self
+            .datagrams
+            .entry(key)
+            .or_insert_with(|| Datagram::new(timestamp));
+        datagram.last_seen = timestamp;
+        datagram.insert(offset, data);
+        if !more {
+            match datagram.len {
+                Some(x) if x != end => datagram.conflict = true,
+                Some(_) => {}
+                None => datagram.len = Some(end),
+            }
+        }
+        if datagram.len.is_some_and(|x| end > x) {
+            datagram.conflict = true;
+        }
+        datagram.fragments.push(FragmentInfo {
+            frame,
+            offset,
+            len: data.len(),
+        });
+        if !datagram.is_complete() {
+            let cell = Arc::clone(&datagram.reassembled_in);
+            return Ok((Reassembly::Pending(cell), None));
+        }
+        let mut datagram = self.datagrams.remove(&key).unwrap();
+        let _ = datagram.reassembled_in.set(frame);
+        let len = datagram.len.unwrap();
+        datagram.fragments.sort_by_key(|x| x.offset);
+        datagram.data.truncate(len);
+        let reassembled = Reassembled {
+            fragments: datagram.fragments,
+            len,
+            overlap: datagram.overlap,
+            conflict: datagram.conflict,
+        };
+        Ok((Reassembly::Complete(reassembled), Some(datagram.data)))
+    }
+    fn buffered(&self) -> usize {
+        self.datagrams.values().map(|x| x.data.len()).sum()
+    }
+    /// Drops the least recently seen datagram other than `keep`, and returns
+    /// whether there was one.
+    fn evict(&mut self, keep: &FragmentKey) -> bool {
+        let oldest = self
+            .datagrams
+            .iter()
+            .filter(|(key, _)| *key != keep)
+            .min_by_key(|(_, x)| x.last_seen)
+            .map(|(key, _)| *key);
+        oldest.is_some_and(|x| self.datagrams.remove(&x).is_some())
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_reassemble() {
+        let mut table = FragmentTable::default();
+        let key = (
+            "10.0.0.1".parse().unwrap(),
+            "10.0.0.2".parse().unwrap(),
+            7,
+            17,
+        );
+        let time = |secs| Local.timestamp_opt(secs, 0).unwrap();
+        // the last fragment first, then one that overlaps it
+        let (last, _) = table.add(key, 8, &[3; 8], false, 1, time(0)).unwrap();
+        let (overlapping, _) = table.add(key, 4, &[2; 8], true, 2, time(1)).unwrap();
+        let (reassembly, data) = table.add(key, 0, &[1; 8], true, 3, time(2)).unwrap();
+        assert_eq!(data.unwrap(), [&[1; 4][..], &[2; 4], &[3; 8]].concat());
+        let reassembled = match reassembly {
+            Reassembly::Complete(x) => x,
+            x => panic!("{:?}", x),
+        };
+        assert_eq!((reassembled.len, reassembled.overlap), (16, true));
+        assert!(reassembled.conflict);
+        let frames: Vec<_> = reassembled.fragments.iter().map(|x| x.frame).collect();
+        assert_eq!(frames, [3, 2, 1]);
+        for x in [last, overlapping] {
+            match x {
+                Reassembly::Pending(x) => assert_eq!(x.get(), Some(&3)),
+                x => panic!("{:?}", x),
+            }
+        }
+        // fragments left waiting too long are dropped
+        let (first, _) = table.add(key, 0, &[1; 8], true, 4, time(10)).unwrap();
+        let (reassembly, data) = table.add(key, 8, &[3; 8], false, 5, time(50)).unwrap();
+        assert!(data.is_none());
+        assert!(matches!(reassembly, Reassembly::Pending(_)));
+        assert!(matches!(first, Reassembly::Pending(x) if x.get().is_none()));
+        assert!(table.add(key, 65528, &[0; 8], false, 6, time(50)).is_err());
+    }
+
+    #[test]
+    fn test_limits() {
+        let mut table = FragmentTable::default();
+        let key = |id| {
+            (
+                "10.0.0.1".parse().unwrap(),
+                "10.0.0.2".parse().unwrap(),
+                id,
+                17,
+            )
+        };
+        let time = |id: u32| Local.timestamp_opt(0, id * 1000).unwrap();
+        // one datagram too many drops the first one
+        for id in 0..=MAX_DATAGRAMS as u32 {
+            table.add(key(id), 8, &[0; 8], false, 0, time(id)).unwrap();
+        }
+        assert_eq!(table.datagrams.len(), MAX_DATAGRAMS);
+        let now = time(MAX_DATAGRAMS as u32 + 1);
+        let (_, data) = table.add(key(0), 0, &[0; 8], true, 0, now).unwrap();
+        assert!(data.is_none());
+        let (_, data) = table.add(key(2), 0, &[0; 8], true, 0, now).unwrap();
+        assert!(data.is_some());
+
+        // and so do too many bytes
+        let mut table = FragmentTable::default();
+        let count = MAX_BUFFERED / 60008 + 1;
+        for id in 0..count as u32 {
+            table
+                .add(key(id), 60000, &[0; 8], false, 0, time(id))
+                .unwrap();
+        }
+        assert!(table.buffered() <= MAX_BUFFERED);
+        let now = time(count as u32);
+        let (_, data) = table.add(key(0), 0, &[0; 60000], true, 0, now).unwrap();
+        assert!(data.is_none());
+        let last = key(count as u32 - 1);
+        let (_, data) = table.add(last, 0, &[0; 60000], true, 0, now).unwrap();
+        assert!(data.is_some());
+    }
+}
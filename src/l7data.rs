@@ -0,0 +1,25 @@
+pub mod dns;
+pub mod http;
+
+use crate::context::Context;
+use crate::field::Field;
+use std::fmt::Debug;
+
+pub trait L7data: Debug + Send + Sync {
+    fn text(&self) -> Vec<String>;
+    fn line(&self) -> String;
+    fn fields(&self) -> Vec<Field> {
+        vec![]
+    }
+}
+
+/// Dissects a transport payload with the registered dissectors. A payload
+/// none of them accepts stays raw data of the transport layer.
+pub(crate) fn read_l7data(data: &[u8], ctx: &mut Context) -> Option<Box<dyn L7data>> {
+    let (transport, src_port, dst_port) = (ctx.transport()?, ctx.src_port()?, ctx.dst_port()?);
+    if data.is_empty() {
+        return None;
+    }
+    let dissectors = ctx.registry().by_ports(transport, src_port, dst_port, data);
+    dissectors.iter().find_map(|x| x.dissect(data, ctx).ok())
+}
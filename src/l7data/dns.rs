@@ -0,0 +1,594 @@
+use crate::context::{fmt_latency, Context, RequestTable};
+use crate::field::{shifted, Field, FieldInfo, FieldType};
+use crate::l7data::L7data;
+use crate::registry::{Dissector, Transport};
+use anyhow::{bail, Context as _, Result};
+use byteorder::{NetworkEndian, ReadBytesExt};
+use std::io::Read;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr};
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("dns", "Domain Name System", FieldType::Protocol),
+    FieldInfo::new("dns.id", "Transaction ID", FieldType::Int).hex(),
+    FieldInfo::new("dns.flags.response", "Response", FieldType::Int),
+    FieldInfo::new("dns.flags.rcode", "Reply code", FieldType::Int),
+    FieldInfo::new("dns.qry.name", "Query Name", FieldType::Str),
+    FieldInfo::new("dns.qry.type", "Query Type", FieldType::Int),
+    FieldInfo::new("dns.resp.name", "Answer Name", FieldType::Str),
+    FieldInfo::new("dns.resp.type", "Answer Type", FieldType::Int),
+    FieldInfo::new("dns.a", "Address", FieldType::Addr),
+    FieldInfo::new("dns.aaaa", "AAAA Address", FieldType::Addr),
+    FieldInfo::new("dns.cname", "CNAME", FieldType::Str),
+];
+
+// pointers can only point backwards, but a hostile message can still loop
+const MAX_POINTERS: usize = 64;
+
+type QueryKey = (IpAddr, u16, IpAddr, u16);
+
+/// Outstanding queries keyed by (client, client port, server, id).
+#[derive(Debug, Default)]
+pub struct DnsTable {
+    queries: RequestTable<QueryKey>,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct DnsResponse {
+    query_frame: usize,
+    latency: chrono::Duration,
+}
+
+impl std::fmt::Display for DnsResponse {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "query in {}, latency={}",
+            self.query_frame,
+            fmt_latency(self.latency)
+        )
+    }
+}
+
+#[derive(Debug)]
+enum RData {
+    A(Ipv4Addr),
+    Aaaa(Ipv6Addr),
+    Cname(String),
+    Ns(String),
+    Ptr(String),
+    Mx {
+        preference: u16,
+        exchange: String,
+    },
+    Txt(Vec<String>),
+    Srv {
+        priority: u16,
+        weight: u16,
+        port: u16,
+        target: String,
+    },
+    Soa {
+        mname: String,
+        rname: String,
+        serial: u32,
+        _refresh: u32,
+        _retry: u32,
+        _expire: u32,
+        _minimum: u32,
+    },
+    Opt {
+        udp_payload_size: u16,
+        _extended_rcode: u8,
+        _version: u8,
+        _flags: u16,
+        _options: Vec<u8>,
+    },
+    Other(Vec<u8>),
+}
+
+impl std::fmt::Display for RData {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            RData::A(x) => write!(f, "{}", x),
+            RData::Aaaa(x) => write!(f, "{}", x),
+            RData::Cname(x) | RData::Ns(x) | RData::Ptr(x) => write!(f, "{}", x),
+            RData::Mx {
+                preference,
+                exchange,
+            } => write!(f, "{} {}", preference, exchange),
+            RData::Txt(x) => write!(f, "{:?}", x.join("")),
+            RData::Srv {
+                priority,
+                weight,
+                port,
+                target,
+            } => write!(f, "{} {} {} {}", priority, weight, port, target),
+            RData::Soa {
+                mname,
+                rname,
+                serial,
+                ..
+            } => write!(f, "{} {} {}", mname, rname, serial),
+            RData::Opt {
+                udp_payload_size, ..
+            } => write!(f, "udp_payload_size={}", udp_payload_size),
+            RData::Other(x) => write!(f, "{:x?}", x),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Question {
+    name: String,
+    qtype: u16,
+    _qclass: u16,
+    // where the question is in the message, and how long its name is there
+    offset: usize,
+    name_len: usize,
+}
+
+#[derive(Debug)]
+struct ResourceRecord {
+    _name: String,
+    rtype: u16,
+    _class: u16,
+    _ttl: u32,
+    rdata: RData,
+    offset: usize,
+    name_len: usize,
+    rdata_len: usize,
+}
+
+#[derive(Debug)]
+struct DnsHeader {
+    _id: u16,
+    flags: u16,
+    _qdcount: u16,
+    _ancount: u16,
+    _nscount: u16,
+    _arcount: u16,
+}
+
+impl DnsHeader {
+    fn is_response(&self) -> bool {
+        self.flags & 0x8000 != 0
+    }
+    fn rcode(&self) -> u16 {
+        self.flags & 0x000f
+    }
+}
+
+#[derive(Debug)]
+pub struct Dns {
+    header: DnsHeader,
+    questions: Vec<Question>,
+    answers: Vec<ResourceRecord>,
+    _authorities: Vec<ResourceRecord>,
+    _additionals: Vec<ResourceRecord>,
+    response: Option<DnsResponse>,
+    // bytes before the message, the length prefix over TCP
+    prefix_len: usize,
+    len: usize,
+}
+
+fn type_name(rtype: u16) -> String {
+    let name = match rtype {
+        1 => "A",
+        2 => "NS",
+        5 => "CNAME",
+        6 => "SOA",
+        12 => "PTR",
+        15 => "MX",
+        16 => "TXT",
+        28 => "AAAA",
+        33 => "SRV",
+        41 => "OPT",
+        255 => "ANY",
+        _ => return format!("TYPE{}", rtype),
+    };
+    name.to_string()
+}
+
+fn rcode_name(rcode: u16) -> String {
+    let name = match rcode {
+        0 => "No error",
+        1 => "Format error",
+        2 => "Server failure",
+        3 => "No such name",
+        4 => "Not implemented",
+        5 => "Refused",
+        _ => return format!("RCODE{}", rcode),
+    };
+    name.to_string()
+}
+
+impl L7data for Dns {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("{:?}", self.header)];
+        ans.extend(self.questions.iter().map(|x| format!("{:?}", x)));
+        ans.extend(self.answers.iter().map(|x| format!("{:?}", x)));
+        ans.extend(self._authorities.iter().map(|x| format!("{:?}", x)));
+        ans.extend(self._additionals.iter().map(|x| format!("{:?}", x)));
+        if let Some(x) = &self.response {
+            ans.push(format!("Response to {}", x));
+        }
+        ans
+    }
+    fn line(&self) -> String {
+        let question = match self.questions.first() {
+            Some(x) => format!("{} {}", type_name(x.qtype), x.name),
+            None => "".to_string(),
+        };
+        if !self.header.is_response() {
+            return format!("DNS query {}", question);
+        }
+        let response = match &self.response {
+            Some(x) => format!(" ({})", x),
+            None => "".to_string(),
+        };
+        if self.header.rcode() != 0 || self.answers.is_empty() {
+            return format!(
+                "DNS response {} {}{}",
+                rcode_name(self.header.rcode()),
+                question,
+                response
+            );
+        }
+        let answers: Vec<String> = self
+            .answers
+            .iter()
+            .map(|x| format!("{} {}", type_name(x.rtype), x.rdata))
+            .collect();
+        format!("DNS response {}{}", answers.join(" "), response)
+    }
+    fn fields(&self) -> Vec<Field> {
+        let mut children = vec![
+            Field::new("dns.id", self.header._id).at(0, 2),
+            Field::new("dns.flags.response", self.header.is_response()).at(2, 2),
+            Field::new("dns.flags.rcode", self.header.rcode()).at(2, 2),
+        ];
+        for question in &self.questions {
+            let offset = question.offset;
+            children.push(
+                Field::new("dns.qry.name", question.name.as_str()).at(offset, question.name_len),
+            );
+            children
+                .push(Field::new("dns.qry.type", question.qtype).at(offset + question.name_len, 2));
+        }
+        for record in &self.answers {
+            let offset = record.offset;
+            let rdata_offset = offset + record.name_len + 10;
+            children.push(
+                Field::new("dns.resp.name", record._name.as_str()).at(offset, record.name_len),
+            );
+            children
+                .push(Field::new("dns.resp.type", record.rtype).at(offset + record.name_len, 2));
+            let rdata = match &record.rdata {
+                RData::A(x) => Field::new("dns.a", *x),
+                RData::Aaaa(x) => Field::new("dns.aaaa", *x),
+                RData::Cname(x) => Field::new("dns.cname", x.as_str()),
+                _ => continue,
+            };
+            children.push(rdata.at(rdata_offset, record.rdata_len));
+        }
+        shifted(
+            vec![Field::protocol("dns")
+                .at(0, self.len)
+                .with_children(children)],
+            self.prefix_len,
+        )
+    }
+}
+
+fn read_name(message: &[u8], offset: &mut usize) -> Result<String> {
+    let mut labels = vec![];
+    let mut position = *offset;
+    let mut jumped = false;
+    let mut pointers = 0;
+    loop {
+        let len = *message.get(position).context("name overruns message")? as usize;
+        match len & 0xc0 {
+            0x00 => {
+                position += 1;
+                if len == 0 {
+                    break;
+                }
+                let label = message
+                    .get(position..position + len)
+                    .context("label overruns message")?;
+                labels.push(String::from_utf8_lossy(label).to_string());
+                position += len;
+            }
+            0xc0 => {
+                let low = *message
+                    .get(position + 1)
+                    .context("pointer overruns message")?;
+                if !jumped {
+                    *offset = position + 2;
+                }
+                jumped = true;
+                pointers += 1;
+                if pointers > MAX_POINTERS {
+                    bail!("too many compression pointers");
+                }
+                position = (len & 0x3f) << 8 | low as usize;
+            }
+            _ => bail!("unsupported label type {:#x}", len),
+        }
+    }
+    if !jumped {
+        *offset = position;
+    }
+    if labels.is_empty() {
+        return Ok("<Root>".to_string());
+    }
+    Ok(labels.join("."))
+}
+
+fn read_rdata(
+    message: &[u8],
+    offset: usize,
+    rtype: u16,
+    class: u16,
+    ttl: u32,
+    len: usize,
+) -> Result<RData> {
+    let mut data = message
+        .get(offset..offset + len)
+        .context("rdata overruns message")?;
+    let mut name_offset = offset;
+    let rdata = match rtype {
+        1 if len == 4 => {
+            let mut octets = [0u8; 4];
+            data.read_exact(&mut octets)?;
+            RData::A(octets.into())
+        }
+        28 if len == 16 => {
+            let mut octets = [0u8; 16];
+            data.read_exact(&mut octets)?;
+            RData::Aaaa(octets.into())
+        }
+        2 => RData::Ns(read_name(message, &mut name_offset)?),
+        5 => RData::Cname(read_name(message, &mut name_offset)?),
+        12 => RData::Ptr(read_name(message, &mut name_offset)?),
+        15 => {
+            let preference = data.read_u16::<NetworkEndian>()?;
+            name_offset += 2;
+            RData::Mx {
+                preference,
+                exchange: read_name(message, &mut name_offset)?,
+            }
+        }
+        16 => {
+            let mut strings = vec![];
+            while !data.is_empty() {
+                let len = data.read_u8()? as usize;
+                let string = data.get(..len).context("txt overruns rdata")?;
+                strings.push(String::from_utf8_lossy(string).to_string());
+                data = &data[len..];
+            }
+            RData::Txt(strings)
+        }
+        33 => {
+            let priority = data.read_u16::<NetworkEndian>()?;
+            let weight = data.read_u16::<NetworkEndian>()?;
+            let port = data.read_u16::<NetworkEndian>()?;
+            name_offset += 6;
+            RData::Srv {
+                priority,
+                weight,
+                port,
+                target: read_name(message, &mut name_offset)?,
+            }
+        }
+        6 => {
+            let mname = read_name(message, &mut name_offset)?;
+            let rname = read_name(message, &mut name_offset)?;
+            let mut rest = message
+                .get(name_offset..offset + len)
+                .context("soa overruns rdata")?;
+            RData::Soa {
+                mname,
+                rname,
+                serial: rest.read_u32::<NetworkEndian>()?,
+                _refresh: rest.read_u32::<NetworkEndian>()?,
+                _retry: rest.read_u32::<NetworkEndian>()?,
+                _expire: rest.read_u32::<NetworkEndian>()?,
+                _minimum: rest.read_u32::<NetworkEndian>()?,
+            }
+        }
+        // OPT reuses class and ttl for EDNS parameters
+        41 => RData::Opt {
+            udp_payload_size: class,
+            _extended_rcode: (ttl >> 24) as u8,
+            _version: (ttl >> 16) as u8,
+            _flags: ttl as u16,
+            _options: data.to_vec(),
+        },
+        _ => RData::Other(data.to_vec()),
+    };
+    Ok(rdata)
+}
+
+fn read_question(message: &[u8], offset: &mut usize) -> Result<Question> {
+    let start = *offset;
+    let name = read_name(message, offset)?;
+    let name_len = *offset - start;
+    let mut fixed = message
+        .get(*offset..*offset + 4)
+        .context("question overruns message")?;
+    *offset += 4;
+    Ok(Question {
+        name,
+        qtype: fixed.read_u16::<NetworkEndian>()?,
+        // the top bit is the mDNS unicast-response flag
+        _qclass: fixed.read_u16::<NetworkEndian>()? & 0x7fff,
+        offset: start,
+        name_len,
+    })
+}
+
+fn read_resource_record(message: &[u8], offset: &mut usize) -> Result<ResourceRecord> {
+    let start = *offset;
+    let name = read_name(message, offset)?;
+    let name_len = *offset - start;
+    let mut fixed = message
+        .get(*offset..*offset + 10)
+        .context("resource record overruns message")?;
+    *offset += 10;
+    let rtype = fixed.read_u16::<NetworkEndian>()?;
+    let class = fixed.read_u16::<NetworkEndian>()?;
+    let ttl = fixed.read_u32::<NetworkEndian>()?;
+    let len = fixed.read_u16::<NetworkEndian>()? as usize;
+    let rdata = read_rdata(message, *offset, rtype, class, ttl, len)
+        .with_context(|| format!("read {} rdata", type_name(rtype)))?;
+    *offset += len;
+    Ok(ResourceRecord {
+        _name: name,
+        rtype,
+        // the top bit is the mDNS cache-flush flag, except for OPT
+        _class: if rtype == 41 { class } else { class & 0x7fff },
+        _ttl: ttl,
+        rdata,
+        offset: start,
+        name_len,
+        rdata_len: len,
+    })
+}
+
+fn track_query(ctx: &mut Context, header: &DnsHeader) -> Option<DnsResponse> {
+    let (src, dst) = (ctx.src?, ctx.dst?);
+    let (src_port, dst_port) = (ctx.src_port?, ctx.dst_port?);
+    if !header.is_response() {
+        let (number, timestamp) = (ctx.number(), ctx.timestamp());
+        ctx.dns
+            .queries
+            .insert((src, src_port, dst, header._id), number, timestamp);
+        return None;
+    }
+    let (query_frame, query_time) = ctx.dns.queries.take(&(dst, dst_port, src, header._id))?;
+    Some(DnsResponse {
+        query_frame,
+        latency: ctx.timestamp() - query_time,
+    })
+}
+
+pub fn read_dns(message: &[u8], ctx: &mut Context) -> Result<Dns> {
+    let mut read = message;
+    let header = DnsHeader {
+        _id: read.read_u16::<NetworkEndian>().context("read id")?,
+        flags: read.read_u16::<NetworkEndian>().context("read flags")?,
+        _qdcount: read.read_u16::<NetworkEndian>().context("read qdcount")?,
+        _ancount: read.read_u16::<NetworkEndian>().context("read ancount")?,
+        _nscount: read.read_u16::<NetworkEndian>().context("read nscount")?,
+        _arcount: read.read_u16::<NetworkEndian>().context("read arcount")?,
+    };
+    let mut offset = 12;
+    let mut questions = vec![];
+    for _ in 0..header._qdcount {
+        questions.push(read_question(message, &mut offset).context("read question")?);
+    }
+    let mut sections = [vec![], vec![], vec![]];
+    let counts = [header._ancount, header._nscount, header._arcount];
+    for (section, count) in sections.iter_mut().zip(counts) {
+        for _ in 0..count {
+            section.push(read_resource_record(message, &mut offset).context("read record")?);
+        }
+    }
+    let [answers, authorities, additionals] = sections;
+    let response = track_query(ctx, &header);
+    Ok(Dns {
+        header,
+        questions,
+        answers,
+        _authorities: authorities,
+        _additionals: additionals,
+        response,
+        prefix_len: 0,
+        len: message.len(),
+    })
+}
+
+/// DNS over TCP prefixes every message with its length.
+fn read_dns_tcp(segment: &[u8], ctx: &mut Context) -> Result<Dns> {
+    let mut read = segment;
+    let len = read.read_u16::<NetworkEndian>().context("read length")? as usize;
+    if read.len() < len {
+        bail!("dns message spans several segments");
+    }
+    let mut dns = read_dns(&read[..len], ctx)?;
+    dns.prefix_len = 2;
+    Ok(dns)
+}
+
+pub struct DnsDissector;
+
+impl Dissector for DnsDissector {
+    fn name(&self) -> &str {
+        "dns"
+    }
+    fn dissect(&self, data: &[u8], ctx: &mut Context) -> Result<Box<dyn L7data>> {
+        let dns = match ctx.transport() {
+            Some(Transport::Tcp) => read_dns_tcp(data, ctx)?,
+            _ => read_dns(data, ctx)?,
+        };
+        Ok(Box::new(dns))
+    }
+    fn fields(&self) -> &'static [FieldInfo] {
+        FIELDS
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use chrono::{Local, TimeZone};
+
+    #[test]
+    fn test_read_dns_query_and_response() {
+        let query = [
+            0x12u8, 0x34, 0x01, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x07, b'e',
+            b'x', b'a', b'm', b'p', b'l', b'e', 0x03, b'c', b'o', b'm', 0x00, 0x00, 0x01, 0x00,
+            0x01,
+        ];
+        let mut response = query.to_vec();
+        response[2] = 0x81;
+        response[3] = 0x80;
+        response[7] = 0x01;
+        // answer with a compression pointer to the question name
+        response.extend_from_slice(&[0xc0, 0x0c, 0x00, 0x01, 0x00, 0x01, 0x00, 0x00, 0x0e, 0x10]);
+        response.extend_from_slice(&[0x00, 0x04, 93, 184, 216, 34]);
+
+        let client: IpAddr = "10.0.0.2".parse().unwrap();
+        let server: IpAddr = "10.0.0.1".parse().unwrap();
+        let mut ctx = Context::default();
+        ctx.begin(Local.timestamp_opt(100, 0).unwrap());
+        (ctx.src, ctx.dst) = (Some(client), Some(server));
+        (ctx.src_port, ctx.dst_port) = (Some(40000), Some(53));
+        let query = read_dns(&query, &mut ctx).unwrap();
+        assert_eq!(query.line(), "DNS query A example.com");
+
+        ctx.begin(Local.timestamp_opt(100, 20_000_000).unwrap());
+        (ctx.src, ctx.dst) = (Some(server), Some(client));
+        (ctx.src_port, ctx.dst_port) = (Some(53), Some(40000));
+        let first = read_dns(&response, &mut ctx).unwrap();
+        assert_eq!(
+            first.line(),
+            "DNS response A 93.184.216.34 (query in 1, latency=20.000ms)"
+        );
+
+        // a retransmitted response isn't linked again
+        ctx.begin(Local.timestamp_opt(100, 30_000_000).unwrap());
+        (ctx.src, ctx.dst) = (Some(server), Some(client));
+        (ctx.src_port, ctx.dst_port) = (Some(53), Some(40000));
+        let again = read_dns(&response, &mut ctx).unwrap();
+        assert_eq!(again.line(), "DNS response A 93.184.216.34");
+    }
+
+    #[test]
+    fn test_read_name_pointer_loop() {
+        let message = [0xc0u8, 0x00];
+        assert!(read_name(&message, &mut 0).is_err());
+    }
+}
@@ -0,0 +1,136 @@
+use crate::context::Context;
+use crate::field::{Field, FieldInfo, FieldType};
+use crate::l7data::L7data;
+use crate::registry::Dissector;
+use anyhow::{bail, Context as _, Result};
+
+/// Fields the dissector emits.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("http", "Hypertext Transfer Protocol", FieldType::Protocol),
+    FieldInfo::new("http.request.method", "Request Method", FieldType::Str),
+    FieldInfo::new("http.request.uri", "Request URI", FieldType::Str),
+    FieldInfo::new("http.response.code", "Status Code", FieldType::Int),
+    FieldInfo::new("http.host", "Host", FieldType::Str),
+    FieldInfo::new("http.user_agent", "User-Agent", FieldType::Str),
+];
+
+const METHODS: [&str; 9] = [
+    "GET", "HEAD", "POST", "PUT", "DELETE", "CONNECT", "OPTIONS", "TRACE", "PATCH",
+];
+
+#[derive(Debug)]
+pub struct Http {
+    start_line: String,
+    headers: Vec<(String, String)>,
+    // (offset, len) of each header value
+    value_spans: Vec<(usize, usize)>,
+    head_len: usize,
+    _body: Vec<u8>,
+}
+
+impl L7data for Http {
+    fn text(&self) -> Vec<String> {
+        let mut ans = vec![format!("Http {:?}", self.start_line)];
+        ans.extend(self.headers.iter().map(|(k, v)| format!("{}: {}", k, v)));
+        ans.push(format!("HttpBody {} bytes", self._body.len()));
+        ans
+    }
+    fn line(&self) -> String {
+        format!("HTTP {}", self.start_line)
+    }
+    fn fields(&self) -> Vec<Field> {
+        let mut children = vec![];
+        let mut parts = self.start_line.splitn(3, ' ');
+        match (parts.next(), parts.next()) {
+            (Some(version), Some(code)) if version.starts_with("HTTP/") => {
+                if let Ok(value) = code.parse::<u16>() {
+                    children.push(
+                        Field::new("http.response.code", value).at(version.len() + 1, code.len()),
+                    );
+                }
+            }
+            (Some(method), Some(uri)) => {
+                children.push(Field::new("http.request.method", method).at(0, method.len()));
+                children.push(Field::new("http.request.uri", uri).at(method.len() + 1, uri.len()));
+            }
+            _ => {}
+        }
+        for ((name, value), (offset, len)) in self.headers.iter().zip(&self.value_spans) {
+            let name = match name.to_ascii_lowercase().as_str() {
+                "host" => "http.host",
+                "user-agent" => "http.user_agent",
+                _ => continue,
+            };
+            children.push(Field::new(name, value.as_str()).at(*offset, *len));
+        }
+        vec![Field::protocol("http")
+            .at(0, self.head_len)
+            .with_children(children)]
+    }
+}
+
+fn is_start_line(line: &str) -> bool {
+    if line.starts_with("HTTP/1.") {
+        return true;
+    }
+    match line.split_once(' ') {
+        Some((method, rest)) => METHODS.contains(&method) && rest.contains(" HTTP/1."),
+        None => false,
+    }
+}
+
+/// Reads a request or response head, the body is kept as is.
+pub fn read_http(data: &[u8]) -> Result<Http> {
+    let end = data
+        .windows(4)
+        .position(|x| x == b"\r\n\r\n")
+        .context("header is not complete")?;
+    let head = std::str::from_utf8(&data[..end]).context("header is not text")?;
+    let mut lines = head.split("\r\n");
+    let start_line = lines.next().unwrap_or_default();
+    if !is_start_line(start_line) {
+        bail!("not an http start line {:?}", start_line);
+    }
+    let mut headers = vec![];
+    let mut value_spans = vec![];
+    let mut offset = start_line.len() + 2;
+    for line in lines {
+        let (name, value) = line
+            .split_once(':')
+            .with_context(|| format!("invalid header {:?}", line))?;
+        let trimmed = value.trim_start();
+        let value_offset = offset + line.len() - trimmed.len();
+        let trimmed = trimmed.trim_end();
+        headers.push((name.to_string(), trimmed.to_string()));
+        value_spans.push((value_offset, trimmed.len()));
+        offset += line.len() + 2;
+    }
+    Ok(Http {
+        start_line: start_line.to_string(),
+        headers,
+        value_spans,
+        head_len: end + 4,
+        _body: data[end + 4..].to_vec(),
+    })
+}
+
+pub struct HttpDissector;
+
+impl Dissector for HttpDissector {
+    fn name(&self) -> &str {
+        "http"
+    }
+    fn dissect(&self, data: &[u8], _ctx: &mut Context) -> Result<Box<dyn L7data>> {
+        Ok(Box::new(read_http(data)?))
+    }
+    fn fields(&self) -> &'static [FieldInfo] {
+        FIELDS
+    }
+    fn probe(&self, data: &[u8]) -> bool {
+        let line_end = data.iter().position(|x| *x == b'\r').unwrap_or(data.len());
+        match std::str::from_utf8(&data[..line_end]) {
+            Ok(line) => is_start_line(line),
+            Err(_) => false,
+        }
+    }
+}
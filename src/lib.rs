@@ -1,4 +1,4 @@
-use anyhow::Result;
+use anyhow::{Context as _, Result};
 use async_std::io::ReadExt;
 use async_std::task::{self, JoinHandle};
 use crossterm::cursor::{Hide, Show};
@@ -10,24 +10,59 @@ use crossterm::{
 use futures_util::{FutureExt, StreamExt};
 use std::thread::sleep;
 use std::{
+    fs::File,
     io::*,
-    sync::{Arc, Mutex},
-    time::Duration,
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex, RwLock},
+    time::{Duration, Instant},
 };
 use tui::backend::CrosstermBackend;
 use tui::Terminal;
 
 pub mod app;
+pub mod bpf;
+pub mod capture;
+pub mod capture_file;
+mod checksum;
+pub mod context;
+pub mod display_filter;
+pub mod export;
+pub mod field;
 pub mod filtable;
+pub mod hex_view;
 mod input_action;
+mod l2data;
 mod l3data;
+pub mod l7data;
+#[cfg(target_os = "linux")]
+pub mod live;
 pub mod packet;
 pub mod pcap;
+pub mod pcapng;
+pub mod protocol_tree;
+pub mod registry;
 pub mod ui;
 use app::App;
 use app::InputMode;
-use packet::{read_packet, Packet};
-use pcap::read_pcap_header;
+use bpf::CaptureFilter;
+use capture::{CaptureReader, Frame};
+use capture_file::CaptureFile;
+use context::Context;
+use display_filter::DisplayFilter;
+use export::{Format, PacketWriter, TextWriter};
+use packet::Packet;
+use registry::Registry;
+
+/// Where packets are read from.
+pub enum Input<T> {
+    /// A pcap or pcapng stream read as it comes, e.g. stdin.
+    Stream(T),
+    /// Capture files, indexed first and dissected as the view needs them.
+    Files(Vec<PathBuf>),
+    /// A network interface captured from directly.
+    #[cfg(target_os = "linux")]
+    Live(live::LiveCapture),
+}
 
 pub struct AlternateTerminal<T: Write> {
     terminal: Terminal<CrosstermBackend<T>>,
@@ -48,15 +83,297 @@ impl<T: Write> Drop for AlternateTerminal<T> {
     }
 }
 
+/// Dissects `frame` unless the capture filter drops it.
+fn dissect_frame(
+    frame: Frame,
+    filter: &mut Option<CaptureFilter>,
+    ctx: &mut Context,
+) -> Option<Packet> {
+    if filter
+        .as_mut()
+        .is_some_and(|x| !x.is_match(&frame.header, &frame.data))
+    {
+        return None;
+    }
+    Some(packet::dissect_packet(frame.header, frame.data, ctx))
+}
+
+/// Reads up to the next frame the capture filter keeps and dissects it, or
+/// returns `None` at the end of the input.
+async fn next_packet(
+    capture: &mut CaptureReader,
+    read: &mut (impl ReadExt + Unpin),
+    filter: &mut Option<CaptureFilter>,
+    ctx: &mut Context,
+) -> Result<Option<Packet>> {
+    while let Some(frame) = capture.read_frame(read).await? {
+        if let Some(packet) = dissect_frame(frame, filter, ctx) {
+            return Ok(Some(packet));
+        }
+    }
+    Ok(None)
+}
+
 fn run_read_packets(
     app: Arc<Mutex<App>>,
     mut read: impl ReadExt + Unpin + Send + 'static,
+    mut capture: CaptureReader,
+    registry: Arc<RwLock<Registry>>,
+    mut filter: Option<CaptureFilter>,
 ) -> JoinHandle<()> {
     task::spawn(async move {
-        while let Ok(packet) = read_packet(&mut read).await {
-            app.lock().unwrap().add_packet(packet);
+        let mut ctx = Context::new(registry);
+        loop {
+            match next_packet(&mut capture, &mut read, &mut filter, &mut ctx).await {
+                Ok(Some(packet)) => app.lock().unwrap().add_packet(packet),
+                Ok(None) => break,
+                Err(e) => {
+                    app.lock().unwrap().set_status(format!("{:#}", e));
+                    break;
+                }
+            }
+        }
+    })
+}
+
+fn run_read_files(
+    app: Arc<Mutex<App>>,
+    paths: Vec<PathBuf>,
+    registry: Arc<RwLock<Registry>>,
+    mut filter: Option<CaptureFilter>,
+) -> JoinHandle<()> {
+    task::spawn(async move {
+        app.lock().unwrap().set_loading(true);
+        // packets are numbered across the files but linked only within
+        // their own
+        let mut ctx = Context::new(registry);
+        for path in paths {
+            ctx.next_file();
+            let mut file = match CaptureFile::open_filtered(&path, filter.as_mut()).await {
+                Ok(x) => x,
+                Err(e) => {
+                    app.lock().unwrap().set_status(format!("{:#}", e));
+                    continue;
+                }
+            };
+            let status = match file.error() {
+                Some(e) => format!("{} packets, {:#}", file.len(), e),
+                None => format!("{}: {} packets", file.path().display(), file.len()),
+            };
+            app.lock().unwrap().set_status(status);
+            loop {
+                let wanted = {
+                    let app = app.lock().unwrap();
+                    app.packet_count() < app.wanted_packets()
+                };
+                if !wanted {
+                    task::sleep(Duration::from_millis(50)).await;
+                    continue;
+                }
+                match file.read_packet(&mut ctx).await {
+                    Ok(Some(packet)) => app.lock().unwrap().add_packet(packet),
+                    Ok(None) => break,
+                    Err(e) => app.lock().unwrap().set_status(format!("{:#}", e)),
+                }
+            }
+        }
+        app.lock().unwrap().set_loading(false);
+    })
+}
+
+#[cfg(target_os = "linux")]
+fn run_live_capture(
+    app: Arc<Mutex<App>>,
+    mut capture: live::LiveCapture,
+    registry: Arc<RwLock<Registry>>,
+    mut filter: Option<CaptureFilter>,
+) -> JoinHandle<()> {
+    task::spawn_blocking(move || {
+        let mut ctx = Context::new(registry);
+        let mut stats_time = Instant::now();
+        while app.lock().unwrap().is_running() {
+            match capture.read_frame() {
+                // the kernel filters too, this catches frames queued before
+                // its filter was attached
+                Ok(Some(frame)) => {
+                    if let Some(packet) = dissect_frame(frame, &mut filter, &mut ctx) {
+                        app.lock().unwrap().add_packet(packet);
+                    }
+                }
+                Ok(None) => {}
+                Err(e) => {
+                    app.lock().unwrap().set_status(format!("{:#}", e));
+                    break;
+                }
+            }
+            if stats_time.elapsed() >= Duration::from_secs(1) {
+                if let Ok(stats) = capture.stats() {
+                    app.lock().unwrap().set_capture_stats(stats);
+                }
+                stats_time = Instant::now();
+            }
+        }
+    })
+}
+
+async fn read_stream(
+    read: &mut (impl ReadExt + Unpin),
+    ctx: &mut Context,
+    filter: &mut Option<CaptureFilter>,
+    f: &mut impl FnMut(Packet) -> Result<()>,
+) -> Result<()> {
+    let mut capture = CaptureReader::open(read).await?;
+    while let Some(packet) = next_packet(&mut capture, read, filter, ctx).await? {
+        f(packet)?;
+    }
+    Ok(())
+}
+
+/// Dissects every packet of the input in order without a terminal, until
+/// the input ends or `f` fails.
+pub async fn for_each_packet<T: ReadExt + Unpin>(
+    input: Input<T>,
+    registry: Registry,
+    mut filter: Option<CaptureFilter>,
+    mut f: impl FnMut(Packet) -> Result<()>,
+) -> Result<()> {
+    let registry = Arc::new(RwLock::new(registry));
+    match input {
+        Input::Stream(mut read) => {
+            let mut ctx = Context::new(registry);
+            read_stream(&mut read, &mut ctx, &mut filter, &mut f).await
         }
+        Input::Files(paths) => {
+            let mut ctx = Context::new(registry);
+            for path in paths {
+                ctx.next_file();
+                let file = async_std::fs::File::open(&path)
+                    .await
+                    .with_context(|| format!("open {}", path.display()))?;
+                let mut read = async_std::io::BufReader::new(file);
+                read_stream(&mut read, &mut ctx, &mut filter, &mut f)
+                    .await
+                    .with_context(|| format!("read {}", path.display()))?;
+            }
+            Ok(())
+        }
+        #[cfg(target_os = "linux")]
+        Input::Live(mut capture) => {
+            let mut ctx = Context::new(registry);
+            loop {
+                let Some(frame) = capture.read_frame()? else {
+                    continue;
+                };
+                if let Some(packet) = dissect_frame(frame, &mut filter, &mut ctx) {
+                    f(packet)?;
+                }
+            }
+        }
+    }
+}
+
+/// Writes the packets of the input that `display_filter` matches to
+/// `path` and returns how many were written.
+pub async fn write_capture<T: ReadExt + Unpin>(
+    input: Input<T>,
+    registry: Registry,
+    filter: Option<CaptureFilter>,
+    display_filter: Option<DisplayFilter>,
+    path: &Path,
+) -> Result<usize> {
+    // a live capture never ends, so everything is flushed as it comes
+    #[cfg(target_os = "linux")]
+    let live = matches!(input, Input::Live(_));
+    #[cfg(not(target_os = "linux"))]
+    let live = false;
+    let file = File::create(path).with_context(|| format!("create {}", path.display()))?;
+    let mut writer = PacketWriter::new(BufWriter::new(file), Format::from_path(path))?;
+    let mut count = 0;
+    for_each_packet(input, registry, filter, |packet| {
+        if display_filter
+            .as_ref()
+            .is_none_or(|x| x.is_match(&packet.fields()))
+        {
+            writer.write_packet(&packet)?;
+            count += 1;
+            if live {
+                writer.flush()?;
+            }
+        }
+        Ok(())
+    })
+    .await?;
+    writer.finish()?;
+    Ok(count)
+}
+
+/// Prints a summary line of each packet the display filter matches, or
+/// its full detail when `verbose`, until the input ends.
+pub async fn print_packets<T: ReadExt + Unpin>(
+    input: Input<T>,
+    registry: Registry,
+    filter: Option<CaptureFilter>,
+    display_filter: Option<DisplayFilter>,
+    verbose: bool,
+    mut write: impl Write,
+) -> Result<()> {
+    let result = for_each_packet(input, registry, filter, |packet| {
+        if display_filter
+            .as_ref()
+            .is_some_and(|x| !x.is_match(&packet.fields()))
+        {
+            return Ok(());
+        }
+        if verbose {
+            writeln!(write, "Frame {}", packet.number())?;
+            for line in packet.text() {
+                writeln!(write, "    {}", line)?;
+            }
+            writeln!(write)?;
+        } else {
+            writeln!(write, "{:5} {}", packet.number(), packet.line())?;
+        }
+        // a pipe reader like `grep` sees each packet as it comes
+        write.flush()?;
+        Ok(())
+    })
+    .await;
+    ignore_broken_pipe(result)
+}
+
+/// Exports the packets the display filter matches as JSON, NDJSON or CSV.
+pub async fn export_packets<T: ReadExt + Unpin, W: Write>(
+    input: Input<T>,
+    registry: Registry,
+    filter: Option<CaptureFilter>,
+    display_filter: Option<DisplayFilter>,
+    mut writer: TextWriter<W>,
+) -> Result<()> {
+    let result = for_each_packet(input, registry, filter, |packet| {
+        if display_filter
+            .as_ref()
+            .is_none_or(|x| x.is_match(&packet.fields()))
+        {
+            writer.write_packet(&packet)?;
+            writer.flush()?;
+        }
+        Ok(())
     })
+    .await;
+    ignore_broken_pipe(result.and_then(|_| writer.finish().map(|_| ())))
+}
+
+// the reader went away, e.g. `head` had enough
+fn ignore_broken_pipe(result: Result<()>) -> Result<()> {
+    match result {
+        Err(e)
+            if e.downcast_ref::<Error>()
+                .is_some_and(|x| x.kind() == ErrorKind::BrokenPipe) =>
+        {
+            Ok(())
+        }
+        x => x,
+    }
 }
 
 fn run_view_tick(app: Arc<Mutex<App>>, write: impl Write + Send + 'static) -> JoinHandle<()> {
@@ -75,13 +392,23 @@ fn run_view_tick(app: Arc<Mutex<App>>, write: impl Write + Send + 'static) -> Jo
 }
 
 pub async fn run_app<T: ReadExt + Unpin + Send + 'static, U: Write + Send + 'static>(
-    mut read: T,
+    input: Input<T>,
     write: U,
     mut event_stream: EventStream,
+    registry: Registry,
+    filter: Option<CaptureFilter>,
 ) -> Result<()> {
-    read_pcap_header(&mut read).await?;
-    let app = Arc::new(Mutex::new(App::default()));
-    let read_packets_handle = run_read_packets(Arc::clone(&app), read);
+    let registry = Arc::new(RwLock::new(registry));
+    let app = Arc::new(Mutex::new(App::new(Arc::clone(&registry))));
+    let read_packets_handle = match input {
+        Input::Stream(mut read) => {
+            let capture = CaptureReader::open(&mut read).await?;
+            run_read_packets(Arc::clone(&app), read, capture, registry, filter)
+        }
+        Input::Files(paths) => run_read_files(Arc::clone(&app), paths, registry, filter),
+        #[cfg(target_os = "linux")]
+        Input::Live(capture) => run_live_capture(Arc::clone(&app), capture, registry, filter),
+    };
     let view_tick_handle = run_view_tick(Arc::clone(&app), write);
     while let Some(Ok(event)) = event_stream.next().fuse().await {
         let mut app = app.lock().unwrap();
@@ -90,10 +417,15 @@ pub async fn run_app<T: ReadExt + Unpin + Send + 'static, U: Write + Send + 'sta
         } else {
             continue;
         };
+        if app.get_command().is_some() {
+            input_action::commandmode_input(&mut app, key.code);
+            continue;
+        }
         input_action::allmode_input(&mut app, key.code);
         match app.get_input_mode() {
             InputMode::List => input_action::listmode_input(&mut app, key.code),
             InputMode::View => input_action::viewmode_input(&mut app, key.code),
+            InputMode::Bytes => input_action::bytesmode_input(&mut app, key.code),
             InputMode::Filter => input_action::filtermode_input(&mut app, key.code),
         }
         if !app.is_running() {
@@ -104,3 +436,62 @@ pub async fn run_app<T: ReadExt + Unpin + Send + 'static, U: Write + Send + 'sta
     view_tick_handle.await;
     Ok(())
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    // pcap header and one udp packet from 10.0.0.3:6000 to 10.0.0.4:7000
+    fn udp_pcap() -> Vec<u8> {
+        let mut pcap = vec![
+            0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0xff, 0xff, 0, 0, 1, 0, 0,
+            0,
+        ];
+        pcap.extend([0, 0, 0, 0, 0, 0, 0, 0, 42, 0, 0, 0, 42, 0, 0, 0]);
+        pcap.extend([0; 12]);
+        pcap.extend([0x08, 0x00, 0x45, 0, 0, 28, 0, 1, 0, 0, 64, 17, 0, 0]);
+        pcap.extend([10, 0, 0, 3, 10, 0, 0, 4, 0x17, 0x70, 0x1b, 0x58, 0, 8, 0, 0]);
+        pcap
+    }
+
+    #[test]
+    fn test_print_packets() {
+        let pcap = udp_pcap();
+        let print = |display_filter: &str, verbose| {
+            let mut out = vec![];
+            let display_filter = Some(DisplayFilter::parse(display_filter).unwrap());
+            let input = Input::Stream(&pcap[..]);
+            let result = print_packets(
+                input,
+                Registry::default(),
+                None,
+                display_filter,
+                verbose,
+                &mut out,
+            );
+            task::block_on(result).unwrap();
+            String::from_utf8(out).unwrap()
+        };
+        let out = print("udp", false);
+        assert_eq!(out.lines().count(), 1);
+        assert!(out.contains("10.0.0.3:6000 → 10.0.0.4:7000"));
+        assert!(print("udp", true).lines().count() > 2);
+        assert_eq!(print("tcp", false), "");
+    }
+
+    #[test]
+    fn test_for_each_packet_files() {
+        let path = std::env::temp_dir().join(format!("wirewhale-lib-{}.pcap", std::process::id()));
+        std::fs::write(&path, udp_pcap()).unwrap();
+        let mut numbers = vec![];
+        let input = Input::<&[u8]>::Files(vec![path.clone(), path.clone()]);
+        let result = for_each_packet(input, Registry::default(), None, |packet| {
+            numbers.push(packet.number());
+            Ok(())
+        });
+        task::block_on(result).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        // one numbering runs through all the files
+        assert_eq!(numbers, [1, 2]);
+    }
+}
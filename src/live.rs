@@ -0,0 +1,280 @@
+use crate::bpf::{Instruction, Program};
+use crate::capture::{CaptureStats, Frame};
+use crate::packet::PacketHeader;
+use anyhow::{Context as _, Result};
+use chrono::Local;
+use std::ffi::CString;
+use std::io;
+use std::mem::{size_of, zeroed};
+use std::os::fd::{AsRawFd, FromRawFd, OwnedFd};
+
+const LINKTYPE_ETHERNET: u32 = 1;
+const LINKTYPE_RAW: u32 = 101;
+const LINKTYPE_IEEE802_11_RADIOTAP: u32 = 127;
+const ARPHRD_IEEE80211_RADIOTAP: u16 = 803;
+// from linux/if_packet.h, missing in libc
+const PACKET_STATISTICS: libc::c_int = 6;
+const PACKET_OUTGOING: u8 = 4;
+
+#[repr(C)]
+#[derive(Default)]
+struct TpacketStats {
+    tp_packets: u32,
+    tp_drops: u32,
+}
+
+#[derive(Debug, Clone)]
+pub struct LiveOptions {
+    pub promiscuous: bool,
+    /// Frames are cut to this many bytes.
+    pub snaplen: u32,
+    /// Run by the kernel, only frames it accepts are read.
+    pub filter: Option<Program>,
+}
+
+impl Default for LiveOptions {
+    fn default() -> Self {
+        Self {
+            promiscuous: true,
+            snaplen: 262144,
+            filter: None,
+        }
+    }
+}
+
+/// Frames of a network interface read through an AF_PACKET socket.
+#[derive(Debug)]
+pub struct LiveCapture {
+    fd: OwnedFd,
+    name: String,
+    ifindex: u32,
+    snaplen: usize,
+    stats: CaptureStats,
+}
+
+fn check(ret: libc::c_int) -> io::Result<libc::c_int> {
+    if ret < 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(ret)
+}
+
+fn setsockopt<T>(fd: &OwnedFd, level: libc::c_int, name: libc::c_int, value: &T) -> io::Result<()> {
+    let ret = unsafe {
+        libc::setsockopt(
+            fd.as_raw_fd(),
+            level,
+            name,
+            value as *const T as *const libc::c_void,
+            size_of::<T>() as libc::socklen_t,
+        )
+    };
+    check(ret).map(|_| ())
+}
+
+fn to_link_type(hatype: u16) -> u32 {
+    match hatype {
+        libc::ARPHRD_NONE => LINKTYPE_RAW,
+        ARPHRD_IEEE80211_RADIOTAP => LINKTYPE_IEEE802_11_RADIOTAP,
+        _ => LINKTYPE_ETHERNET,
+    }
+}
+
+/// The link type frames of the interface `name` will be read as, e.g. to
+/// compile a capture filter for it before opening it.
+pub fn link_type(name: &str) -> Result<u32> {
+    let path = format!("/sys/class/net/{}/type", name);
+    let hatype = std::fs::read_to_string(&path)
+        .with_context(|| format!("no interface {}", name))?
+        .trim()
+        .parse()
+        .with_context(|| format!("invalid {}", path))?;
+    Ok(to_link_type(hatype))
+}
+
+impl LiveCapture {
+    /// Opens the interface `name`, which needs CAP_NET_RAW.
+    pub fn open(name: &str, options: &LiveOptions) -> Result<Self> {
+        let c_name = CString::new(name).context("invalid interface name")?;
+        let ifindex = unsafe { libc::if_nametoindex(c_name.as_ptr()) };
+        if ifindex == 0 {
+            return Err(io::Error::last_os_error())
+                .with_context(|| format!("no interface {}", name));
+        }
+        let protocol = (libc::ETH_P_ALL as u16).to_be();
+        let fd = check(unsafe { libc::socket(libc::AF_PACKET, libc::SOCK_RAW, protocol.into()) })
+            .context("open packet socket")?;
+        let fd = unsafe { OwnedFd::from_raw_fd(fd) };
+        let capture = Self {
+            fd,
+            name: name.to_string(),
+            ifindex,
+            snaplen: options.snaplen.max(1) as usize,
+            stats: CaptureStats::default(),
+        };
+        // attach the filter before binding so no unfiltered frame gets queued
+        if let Some(filter) = &options.filter {
+            capture.set_filter(filter)?;
+        }
+        let mut addr: libc::sockaddr_ll = unsafe { zeroed() };
+        addr.sll_family = libc::AF_PACKET as u16;
+        addr.sll_protocol = protocol;
+        addr.sll_ifindex = ifindex as i32;
+        let ret = unsafe {
+            libc::bind(
+                capture.fd.as_raw_fd(),
+                &addr as *const libc::sockaddr_ll as *const libc::sockaddr,
+                size_of::<libc::sockaddr_ll>() as libc::socklen_t,
+            )
+        };
+        check(ret).with_context(|| format!("bind to {}", name))?;
+        if options.promiscuous {
+            let mut mreq: libc::packet_mreq = unsafe { zeroed() };
+            mreq.mr_ifindex = ifindex as i32;
+            mreq.mr_type = libc::PACKET_MR_PROMISC as u16;
+            setsockopt(
+                &capture.fd,
+                libc::SOL_PACKET,
+                libc::PACKET_ADD_MEMBERSHIP,
+                &mreq,
+            )
+            .context("enable promiscuous mode")?;
+        }
+        // wake up regularly so the reader can notice the app quitting
+        let timeout = libc::timeval {
+            tv_sec: 0,
+            tv_usec: 100_000,
+        };
+        setsockopt(&capture.fd, libc::SOL_SOCKET, libc::SO_RCVTIMEO, &timeout)
+            .context("set receive timeout")?;
+        Ok(capture)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    /// Replaces the kernel filter of the socket.
+    pub fn set_filter(&self, program: &Program) -> Result<()> {
+        let instructions: &[Instruction] = program.instructions();
+        let fprog = libc::sock_fprog {
+            len: u16::try_from(instructions.len()).context("bpf program too long")?,
+            filter: instructions.as_ptr() as *mut libc::sock_filter,
+        };
+        setsockopt(&self.fd, libc::SOL_SOCKET, libc::SO_ATTACH_FILTER, &fprog)
+            .context("attach bpf filter")?;
+        Ok(())
+    }
+
+    /// Counters since the capture was opened.
+    pub fn stats(&mut self) -> Result<CaptureStats> {
+        let mut stats = TpacketStats::default();
+        let mut len = size_of::<TpacketStats>() as libc::socklen_t;
+        let ret = unsafe {
+            libc::getsockopt(
+                self.fd.as_raw_fd(),
+                libc::SOL_PACKET,
+                PACKET_STATISTICS,
+                &mut stats as *mut TpacketStats as *mut libc::c_void,
+                &mut len,
+            )
+        };
+        check(ret).context("read packet statistics")?;
+        // the kernel resets its counters on every read
+        self.stats.received += stats.tp_packets as u64;
+        self.stats.dropped += stats.tp_drops as u64;
+        Ok(self.stats)
+    }
+
+    /// Waits a moment for the next frame, None when none came.
+    pub(crate) fn read_frame(&mut self) -> Result<Option<Frame>> {
+        let mut data = vec![0u8; self.snaplen];
+        let mut addr: libc::sockaddr_ll = unsafe { zeroed() };
+        let mut addr_len = size_of::<libc::sockaddr_ll>() as libc::socklen_t;
+        // with MSG_TRUNC the length on the wire is returned even when longer
+        let ret = unsafe {
+            libc::recvfrom(
+                self.fd.as_raw_fd(),
+                data.as_mut_ptr() as *mut libc::c_void,
+                data.len(),
+                libc::MSG_TRUNC,
+                &mut addr as *mut libc::sockaddr_ll as *mut libc::sockaddr,
+                &mut addr_len,
+            )
+        };
+        if ret < 0 {
+            let e = io::Error::last_os_error();
+            return match e.kind() {
+                io::ErrorKind::WouldBlock
+                | io::ErrorKind::TimedOut
+                | io::ErrorKind::Interrupted => Ok(None),
+                _ => Err(e).with_context(|| format!("read from {}", self.name)),
+            };
+        }
+        // loopback frames show up once going out and once coming in
+        if addr.sll_hatype == libc::ARPHRD_LOOPBACK && addr.sll_pkttype == PACKET_OUTGOING {
+            return Ok(None);
+        }
+        let len = ret as usize;
+        data.truncate(len.min(self.snaplen));
+        let link_type = to_link_type(addr.sll_hatype);
+        let now = Local::now();
+        let mut header = PacketHeader::new(
+            now.timestamp(),
+            now.timestamp_subsec_nanos(),
+            data.len() as u32,
+            len as u32,
+            link_type,
+        )?;
+        header.set_interface(self.ifindex, Some(self.name.clone()));
+        Ok(Some(Frame {
+            header,
+            data,
+            offset: 0,
+        }))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::bpf::CaptureFilter;
+    use crate::context::Context;
+    use crate::packet::dissect_packet;
+    use std::net::UdpSocket;
+
+    // needs CAP_NET_RAW, run with `cargo test -- --ignored`
+    #[test]
+    #[ignore]
+    fn test_loopback() {
+        let filter = CaptureFilter::parse("udp and host 127.0.0.1")
+            .unwrap()
+            .compile(link_type("lo").unwrap())
+            .unwrap();
+        let options = LiveOptions {
+            filter: Some(filter),
+            ..Default::default()
+        };
+        let mut capture = LiveCapture::open("lo", &options).unwrap();
+        let socket = UdpSocket::bind("127.0.0.1:0").unwrap();
+        let port = socket.local_addr().unwrap().port();
+        socket.send_to(b"wirewhale", ("127.0.0.1", port)).unwrap();
+        let mut ctx = Context::default();
+        let mut found = false;
+        for _ in 0..50 {
+            let frame = match capture.read_frame().unwrap() {
+                Some(x) => x,
+                None => continue,
+            };
+            let packet = dissect_packet(frame.header, frame.data, &mut ctx);
+            assert!(packet.field("udp").is_some());
+            if packet.field("udp.dstport").map(|x| x.value().to_string()) == Some(port.to_string())
+            {
+                found = true;
+                break;
+            }
+        }
+        assert!(found);
+        assert!(capture.stats().unwrap().received >= 1);
+    }
+}
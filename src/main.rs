@@ -1,20 +1,150 @@
-use anyhow::Result;
+use anyhow::{bail, Result};
 use async_std::io::stdin;
-use clap::Parser;
+use clap::{ArgAction, Parser};
 use crossterm::event::EventStream;
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
-use std::io::stdout;
-use wirewhale::run_app;
+use std::io::{stdout, IsTerminal};
+use std::path::PathBuf;
+use wirewhale::bpf::CaptureFilter;
+use wirewhale::display_filter::DisplayFilter;
+use wirewhale::export::{TextFormat, TextWriter};
+use wirewhale::registry::Registry;
+use wirewhale::{export_packets, print_packets, run_app, write_capture, Input};
 
 #[derive(Parser)]
-#[command(author, version, about, long_about = None)]
-struct Cli {}
+#[command(author, version, about, long_about = None, disable_version_flag = true)]
+struct Cli {
+    /// Print version, -V is taken by --verbose
+    #[arg(long, action = ArgAction::Version)]
+    version: Option<bool>,
+    /// pcap or pcapng files to open, stdin is read when none are given
+    #[arg(value_name = "FILE")]
+    files: Vec<PathBuf>,
+    /// Read a pcap or pcapng file, like FILE
+    #[arg(short = 'r', long = "read", value_name = "FILE")]
+    read: Vec<PathBuf>,
+    /// Decode traffic as a protocol, e.g. tcp.port==8443,http
+    #[arg(short = 'd', long = "decode-as", value_name = "RULE")]
+    decode_as: Vec<String>,
+    /// Don't verify IPv4, UDP and TCP checksums, for captures taken with
+    /// checksum offload
+    #[arg(long = "no-checksums")]
+    no_checksums: bool,
+    /// Capture from a network interface instead of reading a capture (Linux)
+    #[arg(short = 'i', long, value_name = "NAME", conflicts_with_all = ["files", "read"])]
+    interface: Option<String>,
+    /// Cut captured frames to this many bytes
+    #[arg(short = 's', long, value_name = "BYTES", default_value_t = 262144)]
+    snaplen: u32,
+    /// Don't put the interface into promiscuous mode
+    #[arg(short = 'p', long = "no-promiscuous-mode")]
+    no_promiscuous_mode: bool,
+    /// Kernel filter in the format printed by `tcpdump -ddd`
+    #[arg(long, value_name = "PROGRAM", requires = "interface")]
+    bpf: Option<String>,
+    /// Capture filter, e.g. "tcp port 80 and host 10.0.0.1"
+    #[arg(short = 'f', long, value_name = "EXPR", conflicts_with = "bpf")]
+    filter: Option<String>,
+    /// Display filter applied to the packets written by -w or printed in
+    /// batch mode
+    #[arg(short = 'Y', long = "display-filter", value_name = "FILTER")]
+    display_filter: Option<String>,
+    /// Write the packets to a file instead of showing them, pcapng if it
+    /// ends with .pcapng
+    #[arg(short = 'w', long, value_name = "FILE")]
+    write: Option<PathBuf>,
+    /// Print packet summaries to stdout instead of showing them, the
+    /// default when stdout is not a terminal
+    #[arg(short = 'B', long)]
+    batch: bool,
+    /// Print the full detail of each packet in batch mode
+    #[arg(short = 'V', long)]
+    verbose: bool,
+    /// Print the packets as json, ndjson or csv in batch mode
+    #[arg(short = 'T', long, value_name = "FORMAT")]
+    format: Option<TextFormat>,
+    /// Field of a csv column, e.g. -e frame.number -e ip.src
+    #[arg(
+        short = 'e',
+        long = "column",
+        value_name = "FIELD",
+        value_delimiter = ','
+    )]
+    columns: Vec<String>,
+}
+
+#[cfg(target_os = "linux")]
+fn open_interface(
+    cli: &Cli,
+    name: &str,
+    filter: Option<&CaptureFilter>,
+) -> Result<Input<async_std::io::Stdin>> {
+    use wirewhale::bpf::Program;
+    use wirewhale::live::{self, LiveCapture, LiveOptions};
+    let program = match filter {
+        Some(x) => Some(x.compile(live::link_type(name)?)?),
+        None => cli.bpf.as_deref().map(Program::parse).transpose()?,
+    };
+    let options = LiveOptions {
+        promiscuous: !cli.no_promiscuous_mode,
+        snaplen: cli.snaplen,
+        filter: program,
+    };
+    Ok(Input::Live(LiveCapture::open(name, &options)?))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn open_interface(
+    _cli: &Cli,
+    _name: &str,
+    _filter: Option<&CaptureFilter>,
+) -> Result<Input<async_std::io::Stdin>> {
+    bail!("live capture is only supported on Linux")
+}
 
 #[async_std::main]
 async fn main() -> Result<()> {
-    Cli::parse();
+    let cli = Cli::parse();
+    let mut registry = Registry::default();
+    registry.set_verify_checksums(!cli.no_checksums);
+    for rule in &cli.decode_as {
+        registry.decode_as(rule)?;
+    }
+    let filter = cli
+        .filter
+        .as_deref()
+        .map(CaptureFilter::parse)
+        .transpose()?;
+    let input = if let Some(name) = &cli.interface {
+        open_interface(&cli, name, filter.as_ref())?
+    } else if cli.files.is_empty() && cli.read.is_empty() {
+        Input::Stream(stdin())
+    } else {
+        Input::Files([cli.read, cli.files].concat())
+    };
+    let display_filter = cli
+        .display_filter
+        .as_deref()
+        .map(|x| DisplayFilter::parse_with(x, &registry))
+        .transpose()?;
+    if let Some(path) = &cli.write {
+        let count = write_capture(input, registry, filter, display_filter, path).await?;
+        eprintln!("{} packets written to {}", count, path.display());
+        return Ok(());
+    }
+    if let Some(format) = cli.format {
+        let writer = TextWriter::new(stdout().lock(), format, cli.columns)?;
+        return export_packets(input, registry, filter, display_filter, writer).await;
+    }
+    if cli.batch || !stdout().is_terminal() {
+        let write = stdout().lock();
+        return print_packets(input, registry, filter, display_filter, cli.verbose, write).await;
+    }
+    if display_filter.is_some() {
+        bail!("-Y needs -w or batch mode, type the filter into the filter bar instead");
+    }
     enable_raw_mode()?;
-    let ret = run_app(stdin(), stdout(), EventStream::new()).await;
+    let ret = run_app(input, stdout(), EventStream::new(), registry, filter).await;
     disable_raw_mode()?;
     ret
 }
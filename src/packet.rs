@@ -1,71 +1,195 @@
-use crate::l3data::{read_l3data, L3data};
-use anyhow::{Context, Result};
+use crate::l2data::{read_l2data, L2data};
+use anyhow::{bail, Context, Result};
 use async_std::io::ReadExt;
-use byteorder::LittleEndian;
-use byteorder::ReadBytesExt;
-use chrono::{DateTime, Local, TimeZone};
+use chrono::{DateTime, Local, SecondsFormat, TimeZone};
 //use std::fmt::Display;
+use crate::display_filter::DisplayFilter;
+use crate::field::{Field, FieldInfo, FieldType};
 use crate::filtable::Filtable;
+use crate::pcap::PcapHeader;
+use crate::registry::Registry;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::{Arc, OnceLock};
 
-#[derive(Debug, Default, Clone, Copy)]
-struct PacketHeader {
+/// Fields of the frame itself rather than of a protocol in it.
+pub(crate) const FIELDS: &[FieldInfo] = &[
+    FieldInfo::new("frame", "Frame", FieldType::Protocol),
+    FieldInfo::new("frame.time", "Arrival Time", FieldType::Str),
+    FieldInfo::new("frame.number", "Frame Number", FieldType::Int),
+    FieldInfo::new("frame.len", "Frame Length", FieldType::Int),
+    FieldInfo::new("frame.cap_len", "Capture Length", FieldType::Int),
+    FieldInfo::new("frame.interface_id", "Interface id", FieldType::Int),
+    FieldInfo::new("frame.interface_name", "Interface name", FieldType::Str),
+    FieldInfo::new("malformed", "[Malformed Packet]", FieldType::Protocol),
+    FieldInfo::new("malformed.error", "Error", FieldType::Str),
+];
+
+#[derive(Debug, Default, Clone)]
+pub(crate) struct PacketHeader {
+    number: usize,
     _timestamp: DateTime<Local>,
-    _caplen: u32,
+    caplen: u32,
     len: u32,
+    link_type: u32,
+    interface_id: Option<u32>,
+    interface_name: Option<String>,
+    // why the record header couldn't be read, outermost first
+    errors: Vec<String>,
+    // names the capture file resolved addresses to by this packet
+    names: Arc<HashMap<IpAddr, String>>,
 }
 
-#[derive(Clone, Copy)]
-struct Macaddr([u8; 6]);
-impl std::fmt::Debug for Macaddr {
-    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
-        write!(
-            f,
-            "[{:0>2x}:{:0>2x}:{:0>2x}:{:0>2x}:{:0>2x}:{:0>2x}]",
-            self.0[0], self.0[1], self.0[2], self.0[3], self.0[4], self.0[5]
-        )
+impl PacketHeader {
+    pub(crate) fn new(
+        secs: i64,
+        nanos: u32,
+        caplen: u32,
+        len: u32,
+        link_type: u32,
+    ) -> Result<Self> {
+        let _timestamp = Local
+            .timestamp_opt(secs, nanos)
+            .earliest()
+            .context("time parse err")?;
+        Ok(Self {
+            number: 0,
+            _timestamp,
+            caplen,
+            len,
+            link_type,
+            interface_id: None,
+            interface_name: None,
+            errors: vec![],
+            names: Arc::default(),
+        })
+    }
+    /// A header whose timestamp or interface couldn't be read, at the epoch.
+    /// Its packet is kept as malformed so that reading goes on.
+    pub(crate) fn malformed(error: anyhow::Error, caplen: u32, len: u32, link_type: u32) -> Self {
+        Self {
+            caplen,
+            len,
+            link_type,
+            errors: error.chain().map(|x| x.to_string()).collect(),
+            ..Default::default()
+        }
+    }
+    pub(crate) fn link_type(&self) -> u32 {
+        self.link_type
+    }
+    pub(crate) fn len(&self) -> u32 {
+        self.len
+    }
+    pub(crate) fn timestamp(&self) -> DateTime<Local> {
+        self._timestamp
+    }
+    pub(crate) fn interface_id(&self) -> Option<u32> {
+        self.interface_id
+    }
+    pub(crate) fn interface_name(&self) -> Option<&str> {
+        self.interface_name.as_deref()
+    }
+    pub(crate) fn set_interface(&mut self, id: u32, name: Option<String>) {
+        self.interface_id = Some(id);
+        self.interface_name = name;
+    }
+    pub(crate) fn set_names(&mut self, names: Arc<HashMap<IpAddr, String>>) {
+        self.names = names;
+    }
+    fn interface(&self) -> Option<String> {
+        match (&self.interface_name, self.interface_id) {
+            (Some(name), _) => Some(name.clone()),
+            (None, Some(id)) => Some(format!("if{}", id)),
+            (None, None) => None,
+        }
     }
-}
-#[derive(Debug, Clone, Copy)]
-struct FrameHeader {
-    _src: Macaddr,
-    _dst: Macaddr,
-    _type_len: u16,
-}
-
-#[derive(Debug)]
-struct PacketBody {
-    _header: FrameHeader,
-    _data: Box<dyn L3data>,
 }
 
 #[derive(Debug)]
 pub struct Packet {
     header: PacketHeader,
-    _body: PacketBody,
+    body: Box<dyn L2data>,
+    // the frame as captured, the dissectors only keep parts of it
+    data: Vec<u8>,
+    // fields built once for filtering, unless later packets may add some
+    filter_fields: OnceLock<Vec<Field>>,
+    pending: bool,
 }
 
 impl Packet {
+    pub fn number(&self) -> usize {
+        self.header.number
+    }
+    pub(crate) fn header(&self) -> &PacketHeader {
+        &self.header
+    }
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
     pub fn text(&self) -> Vec<String> {
-        let mut ans = vec![
-            format!("{:?}", self.header),
-            format!("{:?}", self._body._header),
+        let mut ans = vec![format!("{:?}", self.header)];
+        ans.append(self.body.text().as_mut());
+        ans
+    }
+    /// The protocol tree of the packet, one node per layer.
+    pub fn fields(&self) -> Vec<Field> {
+        let time = self
+            .header
+            ._timestamp
+            .to_rfc3339_opts(SecondsFormat::Nanos, false);
+        let mut children = vec![
+            Field::new("frame.time", time),
+            Field::new("frame.number", self.header.number),
+            Field::new("frame.len", self.header.len),
+            Field::new("frame.cap_len", self.header.caplen),
         ];
-        ans.append(self._body._data.text().as_mut());
+        if let Some(x) = self.header.interface_id {
+            children.push(Field::new("frame.interface_id", x));
+        }
+        if let Some(x) = &self.header.interface_name {
+            children.push(Field::new("frame.interface_name", x.as_str()));
+        }
+        let mut ans = vec![Field::protocol("frame")
+            .at(0, self.header.caplen as usize)
+            .with_children(children)];
+        ans.append(self.body.fields().as_mut());
         ans
     }
+    /// The first field named `name`, e.g. "udp.srcport".
+    pub fn field(&self, name: &str) -> Option<Field> {
+        self.fields()
+            .iter()
+            .flat_map(|x| x.iter())
+            .find(|x| x.name() == name)
+            .cloned()
+    }
     pub fn line(&self) -> String {
+        let interface = match self.header.interface() {
+            Some(x) => format!(" {}", x),
+            None => "".to_string(),
+        };
         format!(
-            "{}{:5} {}",
+            "{}{:5}{} {}",
             self.header._timestamp.format("%H:%M:%S"),
             self.header.len,
-            self._body._data.line()
+            interface,
+            self.body.line()
         )
     }
 }
 
 impl Filtable for Packet {
-    fn is_match(&self, s: &str) -> bool {
-        self.line().contains(s)
+    type Filter = DisplayFilter;
+    type Env = Registry;
+    fn compile(s: &str, registry: &Registry) -> Result<DisplayFilter> {
+        DisplayFilter::parse_with(s, registry)
+    }
+    fn is_match(&self, filter: &DisplayFilter) -> bool {
+        if self.pending {
+            return filter.is_match(&self.fields());
+        }
+        filter.is_match(self.filter_fields.get_or_init(|| self.fields()))
     }
 }
 
@@ -81,56 +205,403 @@ impl Filtable for Packet {
 //    }
 //}
 
-fn read_packet_body(mut bytes: std::collections::VecDeque<u8>) -> Result<PacketBody> {
-    let mut src = [0u8; 6];
-    let mut dst = [0u8; 6];
-    std::io::Read::read_exact(&mut bytes, &mut src).context("read src")?;
-    std::io::Read::read_exact(&mut bytes, &mut dst).context("read dst")?;
-    let type_len = bytes.read_u16::<LittleEndian>().context("read type_len")?;
-    let header = FrameHeader {
-        _src: Macaddr(src),
-        _dst: Macaddr(dst),
-        _type_len: type_len,
+fn read_packet_header(buf: &[u8], pcap_header: &PcapHeader) -> Result<PacketHeader> {
+    let mut slice: &[u8] = buf;
+    let endian = pcap_header.endian();
+    let unix_time = endian.read_u32(&mut slice)?;
+    let fraction = endian.read_u32(&mut slice)?;
+    let caplen = endian.read_u32(&mut slice)?;
+    let len = endian.read_u32(&mut slice)?;
+    let link_type = pcap_header.link_type();
+    let header = PacketHeader::new(
+        unix_time as i64,
+        pcap_header.precision().to_nanos(fraction),
+        caplen,
+        len,
+        link_type,
+    )
+    .unwrap_or_else(|e| PacketHeader::malformed(e, caplen, len, link_type));
+    Ok(header)
+}
+
+/// A packet no dissector could make sense of, kept with why it failed.
+#[derive(Debug)]
+struct Malformed {
+    data: Vec<u8>,
+    // the error and its causes, outermost first
+    errors: Vec<String>,
+}
+
+impl L2data for Malformed {
+    fn text(&self) -> Vec<String> {
+        vec![format!(
+            "Malformed {} {:x?}",
+            self.errors.join(": "),
+            self.data
+        )]
+    }
+    fn line(&self) -> String {
+        format!("[Malformed Packet] {}", self.errors.join(": "))
+    }
+    fn fields(&self) -> Vec<Field> {
+        let mut children: Vec<_> = self
+            .errors
+            .iter()
+            .map(|x| Field::new("malformed.error", x.as_str()))
+            .collect();
+        children.push(Field::new("data.data", self.data.as_slice()).at(0, self.data.len()));
+        vec![Field::protocol("malformed")
+            .at(0, self.data.len())
+            .with_children(children)]
+    }
+}
+
+/// Dissects a frame. A frame the dissectors fail on still makes a packet,
+/// marked malformed.
+pub(crate) fn dissect_packet(
+    mut header: PacketHeader,
+    body: Vec<u8>,
+    ctx: &mut crate::context::Context,
+) -> Packet {
+    header.number = ctx.begin(header._timestamp);
+    ctx.names = Arc::clone(&header.names);
+    ctx.truncated = body.len() < header.len as usize;
+    let data = body.clone();
+    // a record header that couldn't be read leaves nothing to dissect
+    let body = if header.errors.is_empty() {
+        read_l2data(body.into(), header.link_type, ctx)
+            .map_err(|e| e.chain().map(|x| x.to_string()).collect())
+    } else {
+        Err(header.errors.clone())
     };
-    let data = read_l3data(bytes, type_len).context("read data")?;
-    let ans = PacketBody {
-        _header: header,
-        _data: data,
+    let body = match body {
+        Ok(x) => x,
+        Err(errors) => Box::new(Malformed {
+            data: data.clone(),
+            errors,
+        }),
     };
-    Ok(ans)
+    Packet {
+        header,
+        body,
+        data,
+        filter_fields: OnceLock::new(),
+        pending: ctx.pending,
+    }
 }
 
-fn read_packet_header(buf: &[u8]) -> Result<PacketHeader> {
-    let mut slice: &[u8] = buf;
-    let unix_time = slice.read_u32::<LittleEndian>()?;
-    let micro_sec = slice.read_u32::<LittleEndian>()?;
-    let _timestamp = Local
-        .timestamp_opt(unix_time as i64, micro_sec)
-        .earliest()
-        .context("time parse err")?;
-    let _caplen = slice.read_u32::<LittleEndian>()?;
-    let len = slice.read_u32::<LittleEndian>()?;
-    Ok(PacketHeader {
-        _timestamp,
-        _caplen,
-        len,
-    })
+/// Reads and dissects the next packet, or returns `None` at the end of
+/// the input.
+pub async fn read_packet(
+    read: &mut (impl ReadExt + Unpin),
+    pcap_header: &PcapHeader,
+    ctx: &mut crate::context::Context,
+) -> Result<Option<Packet>> {
+    let packet = read_frame(read, pcap_header)
+        .await?
+        .map(|(header, body)| dissect_packet(header, body, ctx));
+    Ok(packet)
 }
 
-pub async fn read_packet(read: &mut (impl ReadExt + Unpin)) -> Result<Packet> {
+/// Reads the next pcap record without dissecting it, or returns `None` at
+/// the end of the input.
+pub(crate) async fn read_frame(
+    read: &mut (impl ReadExt + Unpin),
+    pcap_header: &PcapHeader,
+) -> Result<Option<(PacketHeader, Vec<u8>)>> {
     let header_buf_len = 16;
     let mut header_buf = Vec::with_capacity(header_buf_len);
     read.take(header_buf_len as u64)
         .read_to_end(&mut header_buf)
         .await?;
-    let header = read_packet_header(header_buf.as_slice())?;
-    let mut body_buf = Vec::with_capacity(header.len.try_into()?);
-    read.take(header.len.try_into()?)
+    if header_buf.is_empty() {
+        return Ok(None);
+    }
+    if header_buf.len() < header_buf_len {
+        bail!("record header cut short after {} bytes", header_buf.len());
+    }
+    let header = read_packet_header(header_buf.as_slice(), pcap_header)?;
+    // only caplen bytes are stored, len is the length on the wire. The
+    // buffer grows as bytes arrive so that a bogus caplen can't exhaust
+    // memory.
+    let mut body_buf = vec![];
+    read.take(header.caplen.into())
         .read_to_end(&mut body_buf)
-        .await?;
-    let body = read_packet_body(body_buf.into())?;
-    Ok(Packet {
-        header,
-        _body: body,
-    })
+        .await
+        .context("read packet body failed")?;
+    if body_buf.len() < header.caplen as usize {
+        bail!(
+            "packet body cut short after {} of {} bytes",
+            body_buf.len(),
+            header.caplen
+        );
+    }
+    Ok(Some((header, body_buf)))
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::capture::CaptureReader;
+    use crate::field::FieldValue;
+    use proptest::collection::vec;
+    use proptest::prelude::*;
+    use proptest::sample::Index;
+
+    const CORPUS: [&[u8]; 7] = [
+        include_bytes!("../fuzz/corpus/read_packet/ethernet.pcap"),
+        include_bytes!("../fuzz/corpus/read_packet/ethernet.pcapng"),
+        include_bytes!("../fuzz/corpus/read_packet/null.pcap"),
+        include_bytes!("../fuzz/corpus/read_packet/radiotap.pcap"),
+        include_bytes!("../fuzz/corpus/read_packet/raw.pcap"),
+        include_bytes!("../fuzz/corpus/read_packet/sll.pcap"),
+        include_bytes!("../fuzz/corpus/read_packet/sll2.pcap"),
+    ];
+
+    /// Dissects every packet of a pcap file and checks that each field
+    /// lies within the captured bytes.
+    fn read_all(mut read: &[u8]) -> usize {
+        async_std::task::block_on(async {
+            let mut ctx = crate::context::Context::default();
+            let mut capture = match CaptureReader::open(&mut read).await {
+                Ok(x) => x,
+                Err(_) => return 0,
+            };
+            let mut count = 0;
+            while let Ok(Some(packet)) = capture.read_packet(&mut read, &mut ctx).await {
+                packet.text();
+                packet.line();
+                for field in packet.fields().iter().flat_map(|x| x.iter()) {
+                    field.label();
+                    assert!(
+                        field.offset() + field.len() <= packet.data().len(),
+                        "{} overruns packet {}",
+                        field.name(),
+                        packet.number()
+                    );
+                }
+                count += 1;
+            }
+            count
+        })
+    }
+
+    fn pcap_file(link_type: u32, frames: &[Vec<u8>]) -> Vec<u8> {
+        let mut bytes = vec![0xd4, 0xc3, 0xb2, 0xa1, 2, 0, 4, 0];
+        bytes.extend_from_slice(&[0; 8]);
+        bytes.extend_from_slice(&65535u32.to_le_bytes());
+        bytes.extend_from_slice(&link_type.to_le_bytes());
+        for frame in frames {
+            bytes.extend_from_slice(&[0; 8]);
+            bytes.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+            bytes.extend_from_slice(frame);
+        }
+        bytes
+    }
+
+    #[test]
+    fn test_corpus() {
+        let counts: Vec<_> = CORPUS.iter().map(|x| read_all(x)).collect();
+        assert_eq!(counts, [13, 13, 2, 1, 2, 1, 1]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_random_frames(
+            link_type in prop::sample::select(vec![0u32, 1, 12, 14, 101, 105, 108, 113, 127, 228, 229, 276]),
+            frames in vec(vec(any::<u8>(), 0..128), 1..8),
+        ) {
+            prop_assert_eq!(read_all(&pcap_file(link_type, &frames)), frames.len());
+        }
+
+        #[test]
+        fn test_mutated_corpus(
+            file in prop::sample::select(CORPUS.to_vec()),
+            edits in vec((any::<Index>(), any::<u8>()), 1..8),
+            cut in any::<Index>(),
+        ) {
+            // the file header is kept so that mutations reach the dissectors
+            let mut bytes = file.to_vec();
+            for (i, x) in edits {
+                let i = 24 + i.index(bytes.len() - 24);
+                bytes[i] = x;
+            }
+            bytes.truncate(24 + cut.index(bytes.len() - 23));
+            read_all(&bytes);
+        }
+    }
+
+    #[test]
+    fn test_field() {
+        // ethernet, ipv4 and udp from 192.168.11.1:60272 to 239.0.0.1:6000
+        let mut udp_packet: &[u8] = &[
+            0x21, 0x21, 0xad, 0x63, 0x59, 0xcd, 0x05, 0x00, 0x2e, 0x00, 0x00, 0x00, 0x2e, 0x00,
+            0x00, 0x00, 0x01, 0x00, 0x5e, 0x00, 0x00, 0x01, 0xc4, 0x3c, 0xea, 0x06, 0xdd, 0x00,
+            0x08, 0x00, 0x45, 0x00, 0x00, 0x20, 0xd9, 0xa6, 0x40, 0x00, 0x01, 0x11, 0xe5, 0x4d,
+            0xc0, 0xa8, 0x0b, 0x01, 0xef, 0x00, 0x00, 0x01, 0xeb, 0x70, 0x17, 0x70, 0x00, 0x0c,
+            0x6c, 0xa2, b't', b'i', b'm', b'e',
+        ];
+        let mut ctx = crate::context::Context::default();
+        let packet = async_std::task::block_on(read_packet(
+            &mut udp_packet,
+            &PcapHeader::default(),
+            &mut ctx,
+        ))
+        .unwrap()
+        .unwrap();
+        let srcport = packet.field("udp.srcport").unwrap();
+        assert_eq!(srcport.value(), &FieldValue::Int(60272));
+        assert_eq!((srcport.offset(), srcport.len()), (34, 2));
+        let dst = packet.field("ip.dst").unwrap();
+        assert_eq!(dst.value().to_string(), "239.0.0.1");
+        assert_eq!((dst.offset(), dst.len()), (30, 4));
+        let udp = packet.field("udp").unwrap();
+        assert_eq!(udp.children().len(), 7);
+        assert!(packet.field("tcp.srcport").is_none());
+        let filter = DisplayFilter::parse("frame.time matches \"^[0-9]{4}-[0-9]{2}-[0-9]{2}T\"");
+        assert!(filter.unwrap().is_match(&packet.fields()));
+    }
+
+    #[test]
+    fn test_truncated() {
+        // ethernet, ipv4 and tcp cut to 40 of 60 bytes, then an untruncated frame
+        let mut tcp_packet = vec![
+            0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0x08, 0x00,
+            0x45, 0x00, 0x00, 0x2e, 0x00, 0x01, 0x00, 0x00, 0x40, 0x06, 0x00, 0x00, 0x0a, 0x00,
+            0x00, 0x01, 0x0a, 0x00, 0x00, 0x02, 0x04, 0xd2, 0x00, 0x50, 0x00, 0x00,
+        ];
+        let mut stream = vec![0; 8];
+        stream.extend_from_slice(&40u32.to_le_bytes());
+        stream.extend_from_slice(&60u32.to_le_bytes());
+        stream.append(&mut tcp_packet);
+        stream.extend_from_slice(&[0; 8]);
+        stream.extend_from_slice(&14u32.to_le_bytes());
+        stream.extend_from_slice(&14u32.to_le_bytes());
+        stream.extend_from_slice(&[0xff; 12]);
+        stream.extend_from_slice(&[0x88, 0xb5]);
+        let mut read = stream.as_slice();
+        let mut ctx = crate::context::Context::default();
+        let header = PcapHeader::default();
+        let packet = async_std::task::block_on(read_packet(&mut read, &header, &mut ctx))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            packet.field("frame.len").unwrap().value(),
+            &FieldValue::Int(60)
+        );
+        assert_eq!(
+            packet.field("frame.cap_len").unwrap().value(),
+            &FieldValue::Int(40)
+        );
+        assert_eq!(
+            packet.field("ip.dst").unwrap().value().to_string(),
+            "10.0.0.2"
+        );
+        let truncated = packet.field("truncated").unwrap();
+        assert_eq!((truncated.offset(), truncated.len()), (34, 6));
+        assert_eq!(truncated.label(), "[truncated]");
+        assert!(DisplayFilter::parse("truncated && ip")
+            .unwrap()
+            .is_match(&packet.fields()));
+        assert!(packet.line().ends_with("[truncated]"));
+        let packet = async_std::task::block_on(read_packet(&mut read, &header, &mut ctx))
+            .unwrap()
+            .unwrap();
+        assert_eq!(
+            packet.field("frame.number").unwrap().value(),
+            &FieldValue::Int(2)
+        );
+        assert!(packet.field("truncated").is_none());
+        assert!(read.is_empty());
+    }
+
+    #[test]
+    fn test_malformed() {
+        // ethernet carrying 10 bytes of an ipv4 header, then a frame after it
+        let mut stream = vec![0; 8];
+        stream.extend_from_slice(&24u32.to_le_bytes());
+        stream.extend_from_slice(&24u32.to_le_bytes());
+        stream.extend_from_slice(&[0xff; 12]);
+        stream.extend_from_slice(&[0x08, 0x00, 0x45, 0x00, 0x00, 0x1c, 0, 0, 0, 0, 0x40, 0x11]);
+        stream.extend_from_slice(&[0; 8]);
+        stream.extend_from_slice(&14u32.to_le_bytes());
+        stream.extend_from_slice(&14u32.to_le_bytes());
+        stream.extend_from_slice(&[0xff; 12]);
+        stream.extend_from_slice(&[0x88, 0xb5]);
+        let mut read = stream.as_slice();
+        let mut ctx = crate::context::Context::default();
+        let header = PcapHeader::default();
+        let packet = async_std::task::block_on(read_packet(&mut read, &header, &mut ctx))
+            .unwrap()
+            .unwrap();
+        let malformed = packet.field("malformed").unwrap();
+        assert_eq!((malformed.offset(), malformed.len()), (0, 24));
+        let errors: Vec<_> = malformed.children().iter().map(|x| x.label()).collect();
+        assert_eq!(errors[0], "Error: read ethernet");
+        assert!(packet.line().contains("[Malformed Packet] read ethernet: "));
+        let filter = DisplayFilter::parse("malformed.error contains \"ipv4\"").unwrap();
+        assert!(filter.is_match(&packet.fields()));
+        let packet = async_std::task::block_on(read_packet(&mut read, &header, &mut ctx))
+            .unwrap()
+            .unwrap();
+        assert!(packet.field("malformed").is_none());
+        let end = async_std::task::block_on(read_packet(&mut read, &header, &mut ctx)).unwrap();
+        assert!(end.is_none());
+    }
+
+    #[test]
+    fn test_bad_timestamp() {
+        // a record with 3 seconds of microseconds, then a valid one
+        let mut stream = vec![0; 4];
+        stream.extend_from_slice(&3_000_000u32.to_le_bytes());
+        stream.extend_from_slice(&14u32.to_le_bytes());
+        stream.extend_from_slice(&14u32.to_le_bytes());
+        stream.extend_from_slice(&[0xff; 12]);
+        stream.extend_from_slice(&[0x88, 0xb5]);
+        stream.extend_from_within(..);
+        stream[34..38].copy_from_slice(&[0; 4]);
+        let mut read = stream.as_slice();
+        let mut ctx = crate::context::Context::default();
+        let header = PcapHeader::default();
+        let packet = async_std::task::block_on(read_packet(&mut read, &header, &mut ctx))
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.header().timestamp().timestamp(), 0);
+        assert!(packet
+            .line()
+            .ends_with(" [Malformed Packet] time parse err"));
+        assert_eq!(packet.field("data.data").unwrap().len(), 14);
+        let packet = async_std::task::block_on(read_packet(&mut read, &header, &mut ctx))
+            .unwrap()
+            .unwrap();
+        assert_eq!(packet.number(), 2);
+        assert!(packet.field("malformed").is_none());
+    }
+
+    #[test]
+    fn test_filter_pending_fragment() {
+        // the dns query of the fuzzing corpus, split after 16 bytes of udp
+        let file = include_bytes!("../fuzz/corpus/read_packet/ethernet.pcap");
+        let (ethernet, datagram) = (&file[40..54], &file[54..115]);
+        let fragment = |flags: u16, payload: &[u8]| {
+            let mut bytes = [ethernet, &datagram[..20]].concat();
+            bytes[16..18].copy_from_slice(&(20 + payload.len() as u16).to_be_bytes());
+            bytes[20..22].copy_from_slice(&flags.to_be_bytes());
+            bytes.extend(payload);
+            let len = bytes.len() as u32;
+            (PacketHeader::new(0, 0, len, len, 1).unwrap(), bytes)
+        };
+        let mut ctx = crate::context::Context::default();
+        let (header, body) = fragment(0x2000, &datagram[20..36]);
+        let first = dissect_packet(header, body, &mut ctx);
+        let filter = DisplayFilter::parse("ip.reassembled_in == 2").unwrap();
+        assert!(!Filtable::is_match(&first, &filter));
+        let (header, body) = fragment(2, &datagram[36..]);
+        let last = dissect_packet(header, body, &mut ctx);
+        assert!(Filtable::is_match(&first, &filter));
+        let filter = DisplayFilter::parse("dns").unwrap();
+        assert!(Filtable::is_match(&last, &filter));
+        assert!(Filtable::is_match(&last, &filter));
+    }
 }
@@ -1,3 +1,4 @@
+use byteorder::{BigEndian, LittleEndian, ReadBytesExt};
 use thiserror::Error;
 
 #[derive(Error, Debug)]
@@ -10,44 +11,129 @@ pub enum PcapError {
 
 type Result<T> = std::result::Result<T, PcapError>;
 
-#[derive(Default)]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Endian {
+    #[default]
+    Little,
+    Big,
+}
+
+impl Endian {
+    pub fn read_u16(&self, read: &mut impl std::io::Read) -> std::io::Result<u16> {
+        match self {
+            Endian::Little => read.read_u16::<LittleEndian>(),
+            Endian::Big => read.read_u16::<BigEndian>(),
+        }
+    }
+    pub fn read_u32(&self, read: &mut impl std::io::Read) -> std::io::Result<u32> {
+        match self {
+            Endian::Little => read.read_u32::<LittleEndian>(),
+            Endian::Big => read.read_u32::<BigEndian>(),
+        }
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum TimestampPrecision {
+    #[default]
+    Micro,
+    Nano,
+}
+
+impl TimestampPrecision {
+    pub fn to_nanos(&self, fraction: u32) -> u32 {
+        match self {
+            TimestampPrecision::Micro => fraction.saturating_mul(1000),
+            TimestampPrecision::Nano => fraction,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
 pub struct PcapHeader {
-    tcpdump_magic: [u8; 4],
-    major_version: [u8; 2],
-    minor_version: [u8; 2],
-    time_zone: [u8; 4],
-    sigfigs: [u8; 4],
-    scaplen: [u8; 4],
-    link_type: [u8; 4],
+    endian: Endian,
+    precision: TimestampPrecision,
+    major_version: u16,
+    minor_version: u16,
+    _time_zone: u32,
+    _sigfigs: u32,
+    snaplen: u32,
+    link_type: u32,
+}
+
+impl Default for PcapHeader {
+    fn default() -> Self {
+        Self {
+            endian: Endian::Little,
+            precision: TimestampPrecision::Micro,
+            major_version: 2,
+            minor_version: 4,
+            _time_zone: 0,
+            _sigfigs: 0,
+            snaplen: 65535,
+            link_type: 1,
+        }
+    }
+}
+
+impl PcapHeader {
+    pub fn endian(&self) -> Endian {
+        self.endian
+    }
+    pub fn precision(&self) -> TimestampPrecision {
+        self.precision
+    }
+    pub fn version(&self) -> (u16, u16) {
+        (self.major_version, self.minor_version)
+    }
+    pub fn snaplen(&self) -> u32 {
+        self.snaplen
+    }
+    pub fn link_type(&self) -> u32 {
+        self.link_type
+    }
 }
 
 pub async fn read_pcap_header<T: async_std::io::ReadExt + Unpin>(
     read: &mut T,
 ) -> Result<PcapHeader> {
-    let mut ans: PcapHeader = Default::default();
-    read.read_exact(&mut ans.tcpdump_magic).await?;
-    if !is_pcap_magic(&ans.tcpdump_magic) {
-        return Err(PcapError::NotPcap);
-    }
-    read.read_exact(&mut ans.major_version).await?;
-    read.read_exact(&mut ans.minor_version).await?;
-    read.read_exact(&mut ans.time_zone).await?;
-    read.read_exact(&mut ans.sigfigs).await?;
-    read.read_exact(&mut ans.scaplen).await?;
-    read.read_exact(&mut ans.link_type).await?;
-    Ok(ans)
+    let mut magic = [0u8; 4];
+    read.read_exact(&mut magic).await?;
+    read_pcap_header_after_magic(magic, read).await
 }
 
-fn is_bigendian_pcap_magic(buf: &[u8; 4]) -> bool {
-    *buf == [0xd4, 0xc3, 0xb2, 0xa1]
+pub(crate) async fn read_pcap_header_after_magic<T: async_std::io::ReadExt + Unpin>(
+    magic: [u8; 4],
+    read: &mut T,
+) -> Result<PcapHeader> {
+    let (endian, precision) = parse_pcap_magic(&magic).ok_or(PcapError::NotPcap)?;
+    let mut buf = [0u8; 20];
+    read.read_exact(&mut buf).await?;
+    let mut slice = &buf[..];
+    Ok(PcapHeader {
+        endian,
+        precision,
+        major_version: endian.read_u16(&mut slice)?,
+        minor_version: endian.read_u16(&mut slice)?,
+        _time_zone: endian.read_u32(&mut slice)?,
+        _sigfigs: endian.read_u32(&mut slice)?,
+        snaplen: endian.read_u32(&mut slice)?,
+        link_type: endian.read_u32(&mut slice)?,
+    })
 }
 
-fn is_littleendian_pcap_magic(buf: &[u8; 4]) -> bool {
-    *buf == [0xa1, 0xb2, 0xc3, 0xd4]
+fn parse_pcap_magic(buf: &[u8; 4]) -> Option<(Endian, TimestampPrecision)> {
+    match buf {
+        [0xd4, 0xc3, 0xb2, 0xa1] => Some((Endian::Little, TimestampPrecision::Micro)),
+        [0xa1, 0xb2, 0xc3, 0xd4] => Some((Endian::Big, TimestampPrecision::Micro)),
+        [0x4d, 0x3c, 0xb2, 0xa1] => Some((Endian::Little, TimestampPrecision::Nano)),
+        [0xa1, 0xb2, 0x3c, 0x4d] => Some((Endian::Big, TimestampPrecision::Nano)),
+        _ => None,
+    }
 }
 
-fn is_pcap_magic(buf: &[u8; 4]) -> bool {
-    is_bigendian_pcap_magic(buf) || is_littleendian_pcap_magic(buf)
+pub fn is_pcap_magic(buf: &[u8; 4]) -> bool {
+    parse_pcap_magic(buf).is_some()
 }
 
 #[cfg(test)]
@@ -61,4 +147,42 @@ mod test {
         let b = [0xd5, 0xc3, 0xb2, 0xa1];
         assert!(!is_pcap_magic(&b));
     }
+
+    #[test]
+    fn test_parse_pcap_magic() {
+        for (magic, expected) in [
+            (
+                [0xd4, 0xc3, 0xb2, 0xa1],
+                (Endian::Little, TimestampPrecision::Micro),
+            ),
+            (
+                [0xa1, 0xb2, 0xc3, 0xd4],
+                (Endian::Big, TimestampPrecision::Micro),
+            ),
+            (
+                [0x4d, 0x3c, 0xb2, 0xa1],
+                (Endian::Little, TimestampPrecision::Nano),
+            ),
+            (
+                [0xa1, 0xb2, 0x3c, 0x4d],
+                (Endian::Big, TimestampPrecision::Nano),
+            ),
+        ] {
+            assert_eq!(parse_pcap_magic(&magic), Some(expected));
+        }
+        assert_eq!(parse_pcap_magic(&[0xd5, 0xc3, 0xb2, 0xa1]), None);
+    }
+
+    #[test]
+    fn test_read_bigendian_pcap_header() {
+        let buf = [
+            0xa1u8, 0xb2, 0xc3, 0xd4, 0x00, 0x02, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+            0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01,
+        ];
+        let header = async_std::task::block_on(read_pcap_header(&mut &buf[..])).unwrap();
+        assert_eq!(header.endian(), Endian::Big);
+        assert_eq!(header.version(), (2, 4));
+        assert_eq!(header.snaplen(), 262144);
+        assert_eq!(header.link_type(), 1);
+    }
 }
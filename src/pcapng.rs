@@ -0,0 +1,570 @@
+use crate::capture::Frame;
+use crate::context::Context;
+use crate::packet::{dissect_packet, Packet, PacketHeader};
+use crate::pcap::Endian;
+use anyhow::{bail, Context as _, Result};
+use async_std::io::ReadExt;
+use std::collections::HashMap;
+use std::net::IpAddr;
+use std::sync::Arc;
+
+const SECTION_HEADER_BLOCK: u32 = 0x0a0d0d0a;
+const INTERFACE_DESCRIPTION_BLOCK: u32 = 0x00000001;
+const OBSOLETE_PACKET_BLOCK: u32 = 0x00000002;
+const SIMPLE_PACKET_BLOCK: u32 = 0x00000003;
+const NAME_RESOLUTION_BLOCK: u32 = 0x00000004;
+const ENHANCED_PACKET_BLOCK: u32 = 0x00000006;
+const CUSTOM_BLOCK: u32 = 0x00000bad;
+const CUSTOM_BLOCK_NO_COPY: u32 = 0x40000bad;
+
+const BYTE_ORDER_MAGIC: u32 = 0x1a2b3c4d;
+
+pub fn is_pcapng_magic(buf: &[u8; 4]) -> bool {
+    u32::from_le_bytes(*buf) == SECTION_HEADER_BLOCK
+}
+
+#[derive(Debug, Clone)]
+pub struct Interface {
+    link_type: u16,
+    snaplen: u32,
+    name: Option<String>,
+    _description: Option<String>,
+    // timestamp units per second
+    ts_resolution: u64,
+    ts_offset: i64,
+}
+
+impl Interface {
+    pub fn link_type(&self) -> u16 {
+        self.link_type
+    }
+    pub fn snaplen(&self) -> u32 {
+        self.snaplen
+    }
+    pub fn name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct PcapngReader {
+    endian: Endian,
+    major_version: u16,
+    minor_version: u16,
+    interfaces: Vec<Interface>,
+    // shared with the headers of the packets read since
+    names: Arc<HashMap<IpAddr, String>>,
+    custom_blocks: usize,
+    // bytes read from the start of the input
+    position: u64,
+}
+
+struct Block {
+    block_type: u32,
+    body: Vec<u8>,
+    // where the block starts in the input
+    offset: u64,
+}
+
+fn read_options(endian: Endian, mut read: &[u8]) -> Result<Vec<(u16, Vec<u8>)>> {
+    let mut options = vec![];
+    while read.len() >= 4 {
+        let code = endian.read_u16(&mut read)?;
+        let len = endian.read_u16(&mut read)? as usize;
+        if code == 0 {
+            break;
+        }
+        let padded = (len + 3) & !3;
+        if read.len() < len {
+            bail!("option {} overruns block", code);
+        }
+        options.push((code, read[..len].to_vec()));
+        read = &read[padded.min(read.len())..];
+    }
+    Ok(options)
+}
+
+fn option_string(value: &[u8]) -> String {
+    String::from_utf8_lossy(value)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+fn ts_resolution(value: u8) -> Result<u64> {
+    let exponent = (value & 0x7f) as u32;
+    let resolution = if value & 0x80 == 0 {
+        10u64.checked_pow(exponent)
+    } else {
+        2u64.checked_pow(exponent)
+    };
+    resolution.context("if_tsresol out of range")
+}
+
+impl PcapngReader {
+    pub fn endian(&self) -> Endian {
+        self.endian
+    }
+    pub fn version(&self) -> (u16, u16) {
+        (self.major_version, self.minor_version)
+    }
+    pub fn interfaces(&self) -> &[Interface] {
+        &self.interfaces
+    }
+    pub fn custom_block_count(&self) -> usize {
+        self.custom_blocks
+    }
+
+    /// Reads the Section Header Block whose block type was already consumed.
+    pub async fn open_after_magic(read: &mut (impl ReadExt + Unpin)) -> Result<Self> {
+        let mut ans = Self {
+            position: 4,
+            ..Default::default()
+        };
+        let body = ans.read_section_header_body(read).await?;
+        ans.handle_section_header(&body)?;
+        Ok(ans)
+    }
+
+    async fn read_section_header_body(
+        &mut self,
+        read: &mut (impl ReadExt + Unpin),
+    ) -> Result<Vec<u8>> {
+        let mut buf = [0u8; 8];
+        read.read_exact(&mut buf)
+            .await
+            .context("read section header")?;
+        let len_buf = [buf[0], buf[1], buf[2], buf[3]];
+        let magic_buf = [buf[4], buf[5], buf[6], buf[7]];
+        self.endian = if u32::from_le_bytes(magic_buf) == BYTE_ORDER_MAGIC {
+            Endian::Little
+        } else if u32::from_be_bytes(magic_buf) == BYTE_ORDER_MAGIC {
+            Endian::Big
+        } else {
+            bail!("invalid byte order magic {:x?}", magic_buf);
+        };
+        let total_len = self.endian.read_u32(&mut &len_buf[..])? as usize;
+        if total_len < 28 || !total_len.is_multiple_of(4) {
+            bail!("invalid section header length {}", total_len);
+        }
+        let mut body = vec![];
+        read.take(total_len as u64 - 12)
+            .read_to_end(&mut body)
+            .await
+            .context("read section header body")?;
+        if body.len() < total_len - 12 {
+            bail!("section header cut short after {} bytes", body.len());
+        }
+        body.truncate(total_len - 16);
+        self.position += total_len as u64 - 4;
+        Ok(body)
+    }
+
+    fn handle_section_header(&mut self, mut body: &[u8]) -> Result<()> {
+        self.major_version = self.endian.read_u16(&mut body)?;
+        self.minor_version = self.endian.read_u16(&mut body)?;
+        if self.major_version != 1 {
+            bail!("unsupported pcapng version {}", self.major_version);
+        }
+        // interface ids and resolved names are scoped to their section
+        self.interfaces.clear();
+        self.names = Arc::default();
+        Ok(())
+    }
+
+    /// Reads the next block, or returns `None` at the end of the input.
+    async fn read_block(&mut self, read: &mut (impl ReadExt + Unpin)) -> Result<Option<Block>> {
+        let mut type_buf = vec![];
+        read.take(4).read_to_end(&mut type_buf).await?;
+        let type_buf: [u8; 4] = match type_buf.len() {
+            0 => return Ok(None),
+            4 => type_buf.try_into().unwrap(),
+            x => bail!("block type cut short after {} bytes", x),
+        };
+        let offset = self.position;
+        if is_pcapng_magic(&type_buf) {
+            self.position += 4;
+            let body = self.read_section_header_body(read).await?;
+            return Ok(Some(Block {
+                block_type: SECTION_HEADER_BLOCK,
+                body,
+                offset,
+            }));
+        }
+        let block_type = self.endian.read_u32(&mut &type_buf[..])?;
+        let mut len_buf = [0u8; 4];
+        read.read_exact(&mut len_buf).await?;
+        let total_len = self.endian.read_u32(&mut &len_buf[..])? as usize;
+        if total_len < 12 || !total_len.is_multiple_of(4) {
+            bail!("invalid block length {}", total_len);
+        }
+        // grown as bytes arrive so that a bogus length can't exhaust memory
+        let mut body = vec![];
+        read.take(total_len as u64 - 8)
+            .read_to_end(&mut body)
+            .await
+            .context("read block body")?;
+        if body.len() < total_len - 8 {
+            bail!("block body cut short after {} bytes", body.len());
+        }
+        body.truncate(total_len - 12);
+        self.position += total_len as u64;
+        Ok(Some(Block {
+            block_type,
+            body,
+            offset,
+        }))
+    }
+
+    fn handle_interface_description(&mut self, mut body: &[u8]) -> Result<()> {
+        let link_type = self.endian.read_u16(&mut body)?;
+        let _reserved = self.endian.read_u16(&mut body)?;
+        let snaplen = self.endian.read_u32(&mut body)?;
+        let mut interface = Interface {
+            link_type,
+            snaplen,
+            name: None,
+            _description: None,
+            ts_resolution: 1_000_000,
+            ts_offset: 0,
+        };
+        for (code, value) in read_options(self.endian, body)? {
+            match (code, value.as_slice()) {
+                (2, _) => interface.name = Some(option_string(&value)),
+                (3, _) => interface._description = Some(option_string(&value)),
+                (9, [resol]) => interface.ts_resolution = ts_resolution(*resol)?,
+                (14, _) if value.len() == 8 => {
+                    let mut tmp = [0u8; 8];
+                    tmp.copy_from_slice(&value);
+                    interface.ts_offset = match self.endian {
+                        Endian::Little => i64::from_le_bytes(tmp),
+                        Endian::Big => i64::from_be_bytes(tmp),
+                    };
+                }
+                _ => {}
+            }
+        }
+        self.interfaces.push(interface);
+        Ok(())
+    }
+
+    fn handle_name_resolution(&mut self, mut body: &[u8]) -> Result<()> {
+        while body.len() >= 4 {
+            let record_type = self.endian.read_u16(&mut body)?;
+            let len = self.endian.read_u16(&mut body)? as usize;
+            if record_type == 0 || body.len() < len {
+                break;
+            }
+            let value = &body[..len];
+            let addr: Option<(IpAddr, &[u8])> = match record_type {
+                1 if len >= 4 => {
+                    let octets: [u8; 4] = value[..4].try_into()?;
+                    Some((octets.into(), &value[4..]))
+                }
+                2 if len >= 16 => {
+                    let octets: [u8; 16] = value[..16].try_into()?;
+                    Some((octets.into(), &value[16..]))
+                }
+                _ => None,
+            };
+            if let Some((addr, names)) = addr {
+                if let Some(name) = names.split(|x| *x == 0).find(|x| !x.is_empty()) {
+                    Arc::make_mut(&mut self.names)
+                        .insert(addr, String::from_utf8_lossy(name).to_string());
+                }
+            }
+            body = &body[((len + 3) & !3).min(body.len())..];
+        }
+        Ok(())
+    }
+
+    fn interface(&self, id: u32) -> Result<&Interface> {
+        self.interfaces
+            .get(id as usize)
+            .with_context(|| format!("unknown interface id {}", id))
+    }
+
+    /// The header of a packet, malformed when its interface or timestamp
+    /// can't be read.
+    fn packet_header(
+        &self,
+        interface_id: u32,
+        timestamp: u64,
+        caplen: u32,
+        len: u32,
+    ) -> PacketHeader {
+        let interface = match self.interface(interface_id) {
+            Ok(x) => x,
+            Err(e) => return PacketHeader::malformed(e, caplen, len, 0),
+        };
+        let link_type = interface.link_type as u32;
+        let secs = i64::try_from(timestamp / interface.ts_resolution)
+            .ok()
+            .and_then(|x| x.checked_add(interface.ts_offset))
+            .context("timestamp out of range");
+        let fraction = (timestamp % interface.ts_resolution) as u128;
+        let nanos = (fraction * 1_000_000_000 / interface.ts_resolution as u128) as u32;
+        let mut header = secs
+            .and_then(|secs| PacketHeader::new(secs, nanos, caplen, len, link_type))
+            .unwrap_or_else(|e| PacketHeader::malformed(e, caplen, len, link_type));
+        header.set_interface(interface_id, interface.name.clone());
+        header.set_names(Arc::clone(&self.names));
+        header
+    }
+
+    fn handle_enhanced_packet(&self, block: &Block) -> Result<Frame> {
+        let mut body = block.body.as_slice();
+        let interface_id = self.endian.read_u32(&mut body)?;
+        let ts_high = self.endian.read_u32(&mut body)? as u64;
+        let ts_low = self.endian.read_u32(&mut body)? as u64;
+        let caplen = self.endian.read_u32(&mut body)?;
+        let len = self.endian.read_u32(&mut body)?;
+        if body.len() < caplen as usize {
+            bail!("captured length {} overruns block", caplen);
+        }
+        let header = self.packet_header(interface_id, ts_high << 32 | ts_low, caplen, len);
+        Ok(Frame {
+            header,
+            data: body[..caplen as usize].to_vec(),
+            offset: block.offset + 28,
+        })
+    }
+
+    fn handle_obsolete_packet(&self, block: &Block) -> Result<Frame> {
+        let mut body = block.body.as_slice();
+        let interface_id = self.endian.read_u16(&mut body)? as u32;
+        let _drops_count = self.endian.read_u16(&mut body)?;
+        let ts_high = self.endian.read_u32(&mut body)? as u64;
+        let ts_low = self.endian.read_u32(&mut body)? as u64;
+        let caplen = self.endian.read_u32(&mut body)?;
+        let len = self.endian.read_u32(&mut body)?;
+        if body.len() < caplen as usize {
+            bail!("captured length {} overruns block", caplen);
+        }
+        let header = self.packet_header(interface_id, ts_high << 32 | ts_low, caplen, len);
+        Ok(Frame {
+            header,
+            data: body[..caplen as usize].to_vec(),
+            offset: block.offset + 28,
+        })
+    }
+
+    fn handle_simple_packet(&self, block: &Block) -> Result<Frame> {
+        let mut body = block.body.as_slice();
+        let len = self.endian.read_u32(&mut body)?;
+        let mut caplen = std::cmp::min(len as usize, body.len());
+        let header = match self.interface(0) {
+            Ok(interface) => {
+                if interface.snaplen != 0 {
+                    caplen = std::cmp::min(caplen, interface.snaplen as usize);
+                }
+                // simple packet blocks carry no timestamp
+                let link_type = interface.link_type as u32;
+                let mut header = PacketHeader::new(0, 0, caplen as u32, len, link_type)?;
+                header.set_interface(0, interface.name.clone());
+                header
+            }
+            Err(e) => PacketHeader::malformed(e, caplen as u32, len, 0),
+        };
+        Ok(Frame {
+            header,
+            data: body[..caplen].to_vec(),
+            offset: block.offset + 12,
+        })
+    }
+
+    pub async fn read_packet(
+        &mut self,
+        read: &mut (impl ReadExt + Unpin),
+        ctx: &mut Context,
+    ) -> Result<Option<Packet>> {
+        let packet = self
+            .read_frame(read)
+            .await?
+            .map(|x| dissect_packet(x.header, x.data, ctx));
+        Ok(packet)
+    }
+
+    /// Reads up to the next packet block and returns its frame undissected,
+    /// or `None` at the end of the input.
+    pub(crate) async fn read_frame(
+        &mut self,
+        read: &mut (impl ReadExt + Unpin),
+    ) -> Result<Option<Frame>> {
+        loop {
+            let block = match self.read_block(read).await? {
+                Some(x) => x,
+                None => return Ok(None),
+            };
+            match block.block_type {
+                SECTION_HEADER_BLOCK => self.handle_section_header(&block.body)?,
+                INTERFACE_DESCRIPTION_BLOCK => self
+                    .handle_interface_description(&block.body)
+                    .context("read interface description block")?,
+                NAME_RESOLUTION_BLOCK => self
+                    .handle_name_resolution(&block.body)
+                    .context("read name resolution block")?,
+                ENHANCED_PACKET_BLOCK => {
+                    return self
+                        .handle_enhanced_packet(&block)
+                        .context("read enhanced packet block")
+                        .map(Some)
+                }
+                SIMPLE_PACKET_BLOCK => {
+                    return self
+                        .handle_simple_packet(&block)
+                        .context("read simple packet block")
+                        .map(Some)
+                }
+                OBSOLETE_PACKET_BLOCK => {
+                    return self
+                        .handle_obsolete_packet(&block)
+                        .context("read packet block")
+                        .map(Some)
+                }
+                CUSTOM_BLOCK | CUSTOM_BLOCK_NO_COPY => self.custom_blocks += 1,
+                _ => {}
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::field::FieldValue;
+
+    fn block(block_type: u32, body: &[u8]) -> Vec<u8> {
+        let len = (12 + body.len()) as u32;
+        let mut ans = vec![];
+        ans.extend_from_slice(&block_type.to_le_bytes());
+        ans.extend_from_slice(&len.to_le_bytes());
+        ans.extend_from_slice(body);
+        ans.extend_from_slice(&len.to_le_bytes());
+        ans
+    }
+
+    #[test]
+    fn test_read_multiple_interfaces() {
+        let mut shb = vec![];
+        shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend_from_slice(&[1, 0, 0, 0]);
+        shb.extend_from_slice(&(-1i64).to_le_bytes());
+        let mut idb0 = vec![1, 0, 0, 0, 0, 0, 4, 0];
+        idb0.extend_from_slice(&[2, 0, 4, 0, b'e', b't', b'h', b'0', 0, 0, 0, 0]);
+        let mut idb1 = vec![1, 0, 0, 0, 0, 0, 4, 0];
+        idb1.extend_from_slice(&[2, 0, 2, 0, b'l', b'o', 0, 0, 9, 0, 1, 0, 9, 0, 0, 0]);
+        idb1.extend_from_slice(&[0, 0, 0, 0]);
+        let frame = [
+            0x00u8, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02, 0x12, 0x34,
+        ];
+        let mut epb = vec![];
+        epb.extend_from_slice(&1u32.to_le_bytes());
+        epb.extend_from_slice(&0u32.to_le_bytes());
+        epb.extend_from_slice(&1_500_000_000u32.to_le_bytes());
+        epb.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        epb.extend_from_slice(&frame);
+        epb.extend_from_slice(&[0, 0]);
+        let mut bytes = block(SECTION_HEADER_BLOCK, &shb);
+        bytes.extend(block(INTERFACE_DESCRIPTION_BLOCK, &idb0));
+        bytes.extend(block(INTERFACE_DESCRIPTION_BLOCK, &idb1));
+        bytes.extend(block(CUSTOM_BLOCK, &[0, 0, 0, 0]));
+        bytes.extend(block(ENHANCED_PACKET_BLOCK, &epb));
+
+        async_std::task::block_on(async {
+            let mut read = &bytes[..];
+            let mut magic = [0u8; 4];
+            read.read_exact(&mut magic).await.unwrap();
+            assert!(is_pcapng_magic(&magic));
+            let mut reader = PcapngReader::open_after_magic(&mut read).await.unwrap();
+            let mut ctx = Context::default();
+            let packet = reader
+                .read_packet(&mut read, &mut ctx)
+                .await
+                .unwrap()
+                .unwrap();
+            assert_eq!(reader.interfaces().len(), 2);
+            assert_eq!(reader.interfaces()[0].name(), Some("eth0"));
+            assert_eq!(reader.custom_block_count(), 1);
+            assert!(packet.line().contains("lo"));
+            assert!(reader
+                .read_packet(&mut read, &mut ctx)
+                .await
+                .unwrap()
+                .is_none());
+        });
+    }
+
+    #[test]
+    fn test_names_scoped_to_section() {
+        let mut shb = vec![];
+        shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend_from_slice(&[1, 0, 0, 0]);
+        shb.extend_from_slice(&(-1i64).to_le_bytes());
+        let idb = [1, 0, 0, 0, 0, 0, 4, 0];
+        let nrb = [1, 0, 7, 0, 10, 0, 0, 1, b'd', b'b', 0, 0, 0, 0, 0, 0];
+        let mut epb = vec![0; 20];
+        epb[12] = 34;
+        epb[16] = 34;
+        // an ethernet frame with an ipv4 header from 10.0.0.1 to 10.0.0.2
+        epb.extend_from_slice(&[0; 12]);
+        epb.extend_from_slice(&[0x08, 0x00, 0x45, 0, 0, 20, 0, 0, 0, 0, 64, 253, 0, 0]);
+        epb.extend_from_slice(&[10, 0, 0, 1, 10, 0, 0, 2, 0, 0]);
+        let mut section = block(SECTION_HEADER_BLOCK, &shb);
+        section.extend(block(INTERFACE_DESCRIPTION_BLOCK, &idb));
+        let mut bytes = section.clone();
+        bytes.extend(block(NAME_RESOLUTION_BLOCK, &nrb));
+        bytes.extend(block(ENHANCED_PACKET_BLOCK, &epb));
+        bytes.extend(section);
+        bytes.extend(block(ENHANCED_PACKET_BLOCK, &epb));
+
+        async_std::task::block_on(async {
+            let mut read = &bytes[4..];
+            let mut reader = PcapngReader::open_after_magic(&mut read).await.unwrap();
+            let mut ctx = Context::default();
+            let packet = reader.read_packet(&mut read, &mut ctx).await.unwrap();
+            let packet = packet.unwrap();
+            let src_host = packet.field("ip.src_host").unwrap();
+            assert_eq!(src_host.value(), &FieldValue::Str("db".to_string()));
+            assert!(packet.field("ip.dst_host").is_none());
+            let packet = reader.read_packet(&mut read, &mut ctx).await.unwrap();
+            assert!(packet.unwrap().field("ip.src_host").is_none());
+        });
+    }
+
+    #[test]
+    fn test_unknown_interface() {
+        let mut shb = vec![];
+        shb.extend_from_slice(&BYTE_ORDER_MAGIC.to_le_bytes());
+        shb.extend_from_slice(&[1, 0, 0, 0]);
+        shb.extend_from_slice(&(-1i64).to_le_bytes());
+        let idb = [1, 0, 0, 0, 0, 0, 4, 0];
+        let mut epb = vec![0; 20];
+        epb.extend_from_slice(&[0; 14]);
+        epb[12] = 14;
+        epb[16] = 14;
+        epb[32..34].copy_from_slice(&[0x88, 0xb5]);
+        epb.extend_from_slice(&[0, 0]);
+        let mut unknown = epb.clone();
+        unknown[0] = 3;
+        let mut bytes = block(SECTION_HEADER_BLOCK, &shb);
+        bytes.extend(block(INTERFACE_DESCRIPTION_BLOCK, &idb));
+        bytes.extend(block(ENHANCED_PACKET_BLOCK, &unknown));
+        bytes.extend(block(ENHANCED_PACKET_BLOCK, &epb));
+
+        async_std::task::block_on(async {
+            let mut read = &bytes[4..];
+            let mut reader = PcapngReader::open_after_magic(&mut read).await.unwrap();
+            let mut ctx = Context::default();
+            let packet = reader.read_packet(&mut read, &mut ctx).await.unwrap();
+            let line = packet.unwrap().line();
+            assert!(
+                line.ends_with("[Malformed Packet] unknown interface id 3"),
+                "{}",
+                line
+            );
+            let packet = reader.read_packet(&mut read, &mut ctx).await.unwrap();
+            assert!(packet.unwrap().field("malformed").is_none());
+        });
+    }
+}
@@ -0,0 +1,285 @@
+use crate::field::Field;
+use std::collections::HashSet;
+use std::ops::Range;
+
+/// A visible line of the tree.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TreeRow {
+    pub depth: usize,
+    pub label: String,
+    pub expandable: bool,
+    pub expanded: bool,
+}
+
+impl std::fmt::Display for TreeRow {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let marker = match (self.expandable, self.expanded) {
+            (false, _) => " ",
+            (true, false) => "▸",
+            (true, true) => "▾",
+        };
+        write!(f, "{}{} {}", "  ".repeat(self.depth), marker, self.label)
+    }
+}
+
+/// Which nodes of a packet's field tree are expanded and which is selected.
+/// Nodes are addressed by their path of child indices from the roots.
+#[derive(Debug, Default)]
+pub struct ProtocolTree {
+    fields: Vec<Field>,
+    expanded: HashSet<Vec<usize>>,
+    select: usize,
+    // first visible row and the number of rows last drawn
+    offset: usize,
+    height: usize,
+}
+
+impl ProtocolTree {
+    pub fn new(fields: Vec<Field>) -> Self {
+        Self {
+            fields,
+            expanded: HashSet::new(),
+            select: 0,
+            offset: 0,
+            height: 0,
+        }
+    }
+
+    fn node(&self, path: &[usize]) -> Option<&Field> {
+        let (first, rest) = path.split_first()?;
+        let mut node = self.fields.get(*first)?;
+        for i in rest {
+            node = node.children().get(*i)?;
+        }
+        Some(node)
+    }
+
+    /// Paths of the visible nodes, in display order.
+    fn visible(&self) -> Vec<Vec<usize>> {
+        let mut ans = vec![];
+        let mut stack: Vec<Vec<usize>> = (0..self.fields.len()).rev().map(|x| vec![x]).collect();
+        while let Some(path) = stack.pop() {
+            if self.expanded.contains(&path) {
+                let children = self.node(&path).map_or(0, |x| x.children().len());
+                for i in (0..children).rev() {
+                    let mut child = path.clone();
+                    child.push(i);
+                    stack.push(child);
+                }
+            }
+            ans.push(path);
+        }
+        ans
+    }
+
+    fn selected_path(&self) -> Option<Vec<usize>> {
+        self.visible().into_iter().nth(self.select)
+    }
+
+    pub fn selected(&self) -> Option<&Field> {
+        self.node(&self.selected_path()?)
+    }
+
+    /// At most `height` rows scrolled to the selection, and the index of the
+    /// selected one among them.
+    pub fn rows(&mut self, height: usize) -> (Vec<TreeRow>, Option<usize>) {
+        self.height = height;
+        let visible = self.visible();
+        if visible.is_empty() || height == 0 {
+            return (vec![], None);
+        }
+        if self.select < self.offset {
+            self.offset = self.select;
+        } else if self.select >= self.offset + height {
+            self.offset = self.select + 1 - height;
+        }
+        self.offset = self.offset.min(visible.len().saturating_sub(height));
+        let rows = visible
+            .iter()
+            .skip(self.offset)
+            .take(height)
+            .filter_map(|path| {
+                let node = self.node(path)?;
+                Some(TreeRow {
+                    depth: path.len() - 1,
+                    label: node.label(),
+                    expandable: !node.children().is_empty(),
+                    expanded: self.expanded.contains(path),
+                })
+            })
+            .collect();
+        (rows, Some(self.select - self.offset))
+    }
+
+    pub fn next(&mut self) {
+        if self.select + 1 < self.visible().len() {
+            self.select += 1;
+        }
+    }
+
+    pub fn previous(&mut self) {
+        self.select = self.select.saturating_sub(1);
+    }
+
+    pub fn page_down(&mut self) {
+        let last = self.visible().len().saturating_sub(1);
+        self.select = (self.select + self.height.max(1)).min(last);
+    }
+
+    pub fn page_up(&mut self) {
+        self.select = self.select.saturating_sub(self.height.max(1));
+    }
+
+    /// Expands the selected node, or moves into it when already expanded.
+    pub fn expand(&mut self) {
+        let path = match self.selected_path() {
+            Some(x) => x,
+            None => return,
+        };
+        if self.node(&path).is_none_or(|x| x.children().is_empty()) {
+            return;
+        }
+        if !self.expanded.insert(path) {
+            self.next();
+        }
+    }
+
+    /// Collapses the selected node, or moves to its parent when it is not
+    /// expanded.
+    pub fn collapse(&mut self) {
+        let path = match self.selected_path() {
+            Some(x) => x,
+            None => return,
+        };
+        if self.expanded.remove(&path) || path.len() == 1 {
+            return;
+        }
+        let parent = &path[..path.len() - 1];
+        if let Some(x) = self.visible().iter().position(|x| x == parent) {
+            self.select = x;
+        }
+    }
+
+    pub fn expand_all(&mut self) {
+        let selected = self.selected_path();
+        let mut stack: Vec<Vec<usize>> = (0..self.fields.len()).map(|x| vec![x]).collect();
+        while let Some(path) = stack.pop() {
+            let children = self.node(&path).map_or(0, |x| x.children().len());
+            if children == 0 {
+                continue;
+            }
+            for i in 0..children {
+                let mut child = path.clone();
+                child.push(i);
+                stack.push(child);
+            }
+            self.expanded.insert(path);
+        }
+        self.select_path(selected);
+    }
+
+    pub fn collapse_all(&mut self) {
+        // keep the layer of the selection selected
+        let selected = self.selected_path().map(|x| vec![x[0]]);
+        self.expanded.clear();
+        self.select_path(selected);
+    }
+
+    /// Selects the smallest field that holds all of `bytes`, expanding the
+    /// layers above it.
+    pub fn select_bytes(&mut self, bytes: Range<usize>) {
+        let mut best: Option<(Vec<usize>, usize)> = None;
+        let mut stack: Vec<Vec<usize>> = (0..self.fields.len()).rev().map(|x| vec![x]).collect();
+        while let Some(path) = stack.pop() {
+            let node = match self.node(&path) {
+                Some(x) => x,
+                None => continue,
+            };
+            let holds = !node.is_empty()
+                && node.offset() <= bytes.start
+                && bytes.end <= node.offset() + node.len();
+            let better = match &best {
+                None => true,
+                Some((x, len)) => node.len() < *len || (node.len() == *len && path.len() > x.len()),
+            };
+            for i in (0..node.children().len()).rev() {
+                let mut child = path.clone();
+                child.push(i);
+                stack.push(child);
+            }
+            if holds && better {
+                best = Some((path, node.len()));
+            }
+        }
+        if let Some((path, _)) = best {
+            for i in 1..path.len() {
+                self.expanded.insert(path[..i].to_vec());
+            }
+            self.select_path(Some(path));
+        }
+    }
+
+    fn select_path(&mut self, path: Option<Vec<usize>>) {
+        if let Some(path) = path {
+            self.select = self.visible().iter().position(|x| *x == path).unwrap_or(0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+
+    #[test]
+    fn test_protocol_tree() {
+        let fields = vec![
+            Field::protocol("eth").with_children(vec![
+                Field::new("eth.dst", [0u8; 6].as_slice()),
+                Field::new("eth.type", 0x0800u16),
+            ]),
+            Field::protocol("udp").with_children(vec![Field::new("udp.srcport", 53u16)]),
+        ];
+        let mut tree = ProtocolTree::new(fields);
+        let (rows, select) = tree.rows(10);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(select, Some(0));
+        assert_eq!(rows[0].to_string(), "▸ Ethernet II");
+
+        tree.expand();
+        tree.expand();
+        tree.next();
+        assert_eq!(tree.selected().unwrap().label(), "Type: 0x0800");
+        tree.collapse();
+        assert_eq!(tree.selected().unwrap().name(), "eth");
+        tree.collapse();
+        assert_eq!(tree.rows(10).0.len(), 2);
+
+        tree.next();
+        tree.expand_all();
+        assert_eq!(tree.selected().unwrap().name(), "udp");
+        let (rows, select) = tree.rows(2);
+        assert_eq!((rows.len(), select), (2, Some(1)));
+        assert_eq!(rows[0].label, "Type: 0x0800");
+        tree.collapse_all();
+        assert_eq!(tree.rows(10).1, Some(1));
+    }
+
+    #[test]
+    fn test_select_bytes() {
+        let fields = vec![
+            Field::protocol("eth").at(0, 14).with_children(vec![
+                Field::new("eth.dst", [0u8; 6].as_slice()).at(0, 6),
+                Field::new("eth.src", [0u8; 6].as_slice()).at(6, 6),
+                Field::new("eth.addr", [0u8; 6].as_slice()).at(6, 6),
+            ]),
+            Field::protocol("data").at(14, 4),
+        ];
+        let mut tree = ProtocolTree::new(fields);
+        tree.select_bytes(7..9);
+        assert_eq!(tree.selected().unwrap().name(), "eth.src");
+        tree.select_bytes(11..13);
+        assert_eq!(tree.selected().unwrap().name(), "eth");
+        tree.select_bytes(15..16);
+        assert_eq!(tree.selected().unwrap().name(), "data");
+    }
+}
@@ -0,0 +1,263 @@
+use crate::context::Context;
+use crate::field::{builtin_field, FieldInfo};
+use crate::l7data::dns::DnsDissector;
+use crate::l7data::http::HttpDissector;
+use crate::l7data::L7data;
+use anyhow::{bail, Context as _, Result};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Transport {
+    Udp,
+    Tcp,
+}
+
+/// A protocol dissector that can be plugged into the registry.
+pub trait Dissector: Send + Sync {
+    /// Short name used by decode-as rules, e.g. "dns".
+    fn name(&self) -> &str;
+    fn dissect(&self, data: &[u8], ctx: &mut Context) -> Result<Box<dyn L7data>>;
+    /// Heuristic dissectors return whether `data` looks like their protocol.
+    fn probe(&self, _data: &[u8]) -> bool {
+        false
+    }
+    /// The fields `dissect` emits, so display filters can test them.
+    fn fields(&self) -> &'static [FieldInfo] {
+        &[]
+    }
+}
+
+/// Where dissectors hook into the protocol stack, looked up before the
+/// built in dissectors of each layer.
+#[derive(Clone)]
+pub struct Registry {
+    dissectors: Vec<Arc<dyn Dissector>>,
+    ports: HashMap<(Transport, u16), Arc<dyn Dissector>>,
+    ethertypes: HashMap<u16, Arc<dyn Dissector>>,
+    ip_protocols: HashMap<u8, Arc<dyn Dissector>>,
+    heuristics: Vec<Arc<dyn Dissector>>,
+    // decode-as rules win over whatever is registered for the other port
+    forced_ports: HashMap<(Transport, u16), Arc<dyn Dissector>>,
+    verify_checksums: bool,
+}
+
+impl std::fmt::Debug for Registry {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let names: Vec<&str> = self.dissectors.iter().map(|x| x.name()).collect();
+        write!(f, "Registry {:?}", names)
+    }
+}
+
+impl Default for Registry {
+    fn default() -> Self {
+        let mut registry = Self::empty();
+        let dns: Arc<dyn Dissector> = Arc::new(DnsDissector);
+        for port in [53, 5353] {
+            registry.register_port(Transport::Udp, port, Arc::clone(&dns));
+            registry.register_port(Transport::Tcp, port, Arc::clone(&dns));
+        }
+        let http: Arc<dyn Dissector> = Arc::new(HttpDissector);
+        for port in [80, 8080] {
+            registry.register_port(Transport::Tcp, port, Arc::clone(&http));
+        }
+        registry.register_heuristic(http);
+        registry
+    }
+}
+
+impl Registry {
+    /// A registry without even the built in application dissectors.
+    pub fn empty() -> Self {
+        Self {
+            dissectors: vec![],
+            ports: HashMap::new(),
+            ethertypes: HashMap::new(),
+            ip_protocols: HashMap::new(),
+            heuristics: vec![],
+            forced_ports: HashMap::new(),
+            verify_checksums: true,
+        }
+    }
+
+    fn add(&mut self, dissector: &Arc<dyn Dissector>) {
+        if self.dissector(dissector.name()).is_none() {
+            self.dissectors.push(Arc::clone(dissector));
+        }
+    }
+
+    pub fn register_port(
+        &mut self,
+        transport: Transport,
+        port: u16,
+        dissector: Arc<dyn Dissector>,
+    ) {
+        self.add(&dissector);
+        self.ports.insert((transport, port), dissector);
+    }
+
+    pub fn register_ethertype(&mut self, ethertype: u16, dissector: Arc<dyn Dissector>) {
+        self.add(&dissector);
+        self.ethertypes.insert(ethertype, dissector);
+    }
+
+    pub fn register_ip_protocol(&mut self, protocol: u8, dissector: Arc<dyn Dissector>) {
+        self.add(&dissector);
+        self.ip_protocols.insert(protocol, dissector);
+    }
+
+    pub fn register_heuristic(&mut self, dissector: Arc<dyn Dissector>) {
+        self.add(&dissector);
+        self.heuristics.push(dissector);
+    }
+
+    /// Looks a dissector up by name, ignoring case.
+    pub fn dissector(&self, name: &str) -> Option<Arc<dyn Dissector>> {
+        self.dissectors
+            .iter()
+            .find(|x| x.name().eq_ignore_ascii_case(name))
+            .cloned()
+    }
+
+    /// Looks a field up among those of the built in dissectors and the
+    /// registered ones.
+    pub fn field(&self, name: &str) -> Option<&'static FieldInfo> {
+        builtin_field(name).or_else(|| {
+            self.dissectors
+                .iter()
+                .flat_map(|x| x.fields())
+                .find(|x| x.name == name)
+        })
+    }
+
+    /// Applies a rule like "tcp.port==8443,http". udp.port, tcp.port,
+    /// eth.type and ip.proto are accepted.
+    pub fn decode_as(&mut self, rule: &str) -> Result<()> {
+        let (selector, name) = rule
+            .split_once(',')
+            .with_context(|| format!("expected <field>==<value>,<protocol> in {:?}", rule))?;
+        let (field, value) = selector
+            .split_once("==")
+            .with_context(|| format!("expected <field>==<value> in {:?}", selector))?;
+        let dissector = self
+            .dissector(name.trim())
+            .with_context(|| format!("unknown protocol {:?}", name.trim()))?;
+        let value = value.trim();
+        let number = match value.strip_prefix("0x") {
+            Some(hex) => u32::from_str_radix(hex, 16),
+            None => value.parse(),
+        }
+        .with_context(|| format!("invalid value {:?}", value))?;
+        match field.trim() {
+            "udp.port" => {
+                let port = u16::try_from(number).context("port out of range")?;
+                self.forced_ports.insert((Transport::Udp, port), dissector);
+            }
+            "tcp.port" => {
+                let port = u16::try_from(number).context("port out of range")?;
+                self.forced_ports.insert((Transport::Tcp, port), dissector);
+            }
+            "eth.type" => {
+                let ethertype = u16::try_from(number).context("ethertype out of range")?;
+                self.ethertypes.insert(ethertype, dissector);
+            }
+            "ip.proto" => {
+                let protocol = u8::try_from(number).context("protocol out of range")?;
+                self.ip_protocols.insert(protocol, dissector);
+            }
+            x => bail!("cannot decode by {:?}", x),
+        }
+        Ok(())
+    }
+
+    /// Whether IPv4, UDP and TCP checksums are verified. Captures taken
+    /// with checksum offload carry bogus ones.
+    pub fn set_verify_checksums(&mut self, verify: bool) {
+        self.verify_checksums = verify;
+    }
+
+    pub fn verify_checksums(&self) -> bool {
+        self.verify_checksums
+    }
+
+    pub(crate) fn by_ethertype(&self, ethertype: u16) -> Option<Arc<dyn Dissector>> {
+        self.ethertypes.get(&ethertype).cloned()
+    }
+
+    pub(crate) fn by_ip_protocol(&self, protocol: u8) -> Option<Arc<dyn Dissector>> {
+        self.ip_protocols.get(&protocol).cloned()
+    }
+
+    /// Dissectors to try for a transport payload, most specific first.
+    pub(crate) fn by_ports(
+        &self,
+        transport: Transport,
+        src_port: u16,
+        dst_port: u16,
+        data: &[u8],
+    ) -> Vec<Arc<dyn Dissector>> {
+        // like the well known side of a conversation, the lower port goes first
+        let (low, high) = (src_port.min(dst_port), src_port.max(dst_port));
+        let mut ans: Vec<Arc<dyn Dissector>> = [&self.forced_ports, &self.ports]
+            .iter()
+            .flat_map(|table| [low, high].map(|port| table.get(&(transport, port)).cloned()))
+            .flatten()
+            .collect();
+        ans.extend(self.heuristics.iter().filter(|x| x.probe(data)).cloned());
+        ans
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use crate::display_filter::DisplayFilter;
+    use crate::field::FieldType;
+
+    #[test]
+    fn test_decode_as() {
+        let mut registry = Registry::default();
+        registry.decode_as("tcp.port==8443,HTTP").unwrap();
+        let dissectors = registry.by_ports(Transport::Tcp, 8443, 50000, b"\x16\x03\x01");
+        assert_eq!(dissectors.len(), 1);
+        assert_eq!(dissectors[0].name(), "http");
+        assert!(registry.decode_as("tcp.port==8443,gopher").is_err());
+        assert!(registry.decode_as("tcp.port==70000,http").is_err());
+        assert!(registry.decode_as("tcp.port=8443").is_err());
+    }
+
+    const GOPHER_FIELDS: &[FieldInfo] = &[
+        FieldInfo::new("gopher", "Gopher", FieldType::Protocol),
+        FieldInfo::new("gopher.selector", "Selector", FieldType::Str),
+    ];
+
+    struct Gopher;
+
+    impl Dissector for Gopher {
+        fn name(&self) -> &str {
+            "gopher"
+        }
+        fn dissect(&self, _data: &[u8], _ctx: &mut Context) -> Result<Box<dyn L7data>> {
+            bail!("not implemented")
+        }
+        fn fields(&self) -> &'static [FieldInfo] {
+            GOPHER_FIELDS
+        }
+    }
+
+    #[test]
+    fn test_fields() {
+        let mut registry = Registry::default();
+        assert_eq!(registry.field("udp.port").unwrap().title, "Port");
+        assert_eq!(registry.field("dns.id").unwrap().title, "Transaction ID");
+        assert!(registry.field("gopher.selector").is_none());
+        registry.register_port(Transport::Tcp, 70, Arc::new(Gopher));
+        assert_eq!(
+            registry.field("gopher.selector").unwrap().ty,
+            FieldType::Str
+        );
+        let filter = "gopher.selector contains \"/\"";
+        assert!(DisplayFilter::parse(filter).is_err());
+        assert!(DisplayFilter::parse_with(filter, &registry).is_ok());
+    }
+}
@@ -2,12 +2,12 @@ use tui::{
     backend::Backend,
     layout::{Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Spans, Text},
+    text::{Span, Spans, Text},
     widgets::{Block, Borders, List, ListItem, ListState, Paragraph},
     Frame,
 };
 
-use crate::{app::InputMode, App};
+use crate::{app::InputMode, field::Field, hex_view::BYTES_PER_LINE, App};
 
 fn list_ui<B: Backend>(
     f: &mut Frame<B>,
@@ -19,10 +19,12 @@ fn list_ui<B: Backend>(
     let (items, select) = app.get_view_list(chunk.height - 2, offset);
     let items: Vec<ListItem> = items
         .iter()
-        .map(|i| {
-            let st = i.to_string();
-            let lines = vec![Spans::from(st)];
-            ListItem::new(lines)
+        .map(|(line, marked)| {
+            let lines = vec![Spans::from(line.to_string())];
+            match marked {
+                true => ListItem::new(lines).style(Style::default().fg(Color::Yellow)),
+                false => ListItem::new(lines),
+            }
         })
         .collect();
 
@@ -41,6 +43,103 @@ fn list_ui<B: Backend>(
     f.render_stateful_widget(items, chunk, &mut state);
 }
 
+fn field_info(field: &Field) -> String {
+    let place = if field.is_empty() {
+        "generated".to_string()
+    } else {
+        format!(
+            "bytes {}-{} ({} bytes)",
+            field.offset(),
+            field.offset() + field.len() - 1,
+            field.len()
+        )
+    };
+    format!("{} == {}  {}", field.name(), field.value(), place)
+}
+
+fn tree_ui<B: Backend>(f: &mut Frame<B>, app: &mut App, chunk: Rect, style: Style) {
+    let chunks = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([Constraint::Min(0), Constraint::Length(1)].as_ref())
+        .split(chunk);
+    let (rows, select) = app.get_view_tree(chunks[0].height.saturating_sub(2));
+    let items: Vec<ListItem> = rows
+        .iter()
+        .map(|x| ListItem::new(vec![Spans::from(x.to_string())]))
+        .collect();
+    let items = List::new(items)
+        .block(Block::default().borders(Borders::ALL).title("Details"))
+        .highlight_style(
+            Style::default()
+                .bg(Color::LightGreen)
+                .add_modifier(Modifier::BOLD),
+        )
+        .style(style);
+    let mut state = ListState::default();
+    state.select(select);
+    f.render_stateful_widget(items, chunks[0], &mut state);
+    let info = app.get_view_field().map(field_info).unwrap_or_default();
+    f.render_widget(Paragraph::new(Text::raw(info)).style(style), chunks[1]);
+}
+
+fn hex_ui<B: Backend>(f: &mut Frame<B>, app: &mut App, chunk: Rect, style: Style) {
+    let is_active = matches!(app.get_input_mode(), InputMode::Bytes);
+    let field = app
+        .get_view_field()
+        .filter(|x| !x.is_empty())
+        .map(|x| x.offset()..x.offset() + x.len());
+    let selection = app.hex_mut().selection();
+    let focus = match (&field, is_active) {
+        (_, true) => app.hex_mut().cursor(),
+        (Some(x), false) => x.start,
+        (None, false) => 0,
+    };
+    let height = chunk.height.saturating_sub(2) as usize;
+    let first = app.hex_mut().scroll(focus, height);
+    let field_style = Style::default().bg(Color::LightGreen).fg(Color::Black);
+    let byte_style = |i: usize| {
+        if is_active && selection.contains(&i) {
+            style.add_modifier(Modifier::REVERSED)
+        } else if field.as_ref().is_some_and(|x| x.contains(&i)) {
+            field_style
+        } else {
+            style
+        }
+    };
+    let data = app.get_view_bytes();
+    let lines: Vec<Spans> = data
+        .chunks(BYTES_PER_LINE)
+        .enumerate()
+        .skip(first)
+        .take(height)
+        .map(|(line, bytes)| {
+            let start = line * BYTES_PER_LINE;
+            let mut spans = vec![Span::raw(format!("{:04x}  ", start))];
+            for (i, x) in bytes.iter().enumerate() {
+                spans.push(Span::styled(format!("{:02x}", x), byte_style(start + i)));
+                spans.push(Span::raw(if i == 7 { "  " } else { " " }));
+            }
+            let missing = BYTES_PER_LINE - bytes.len();
+            spans.push(Span::raw(
+                " ".repeat(missing * 3 + usize::from(bytes.len() < 8) + 1),
+            ));
+            for (i, x) in bytes.iter().enumerate() {
+                let c = if x.is_ascii_graphic() || *x == b' ' {
+                    *x as char
+                } else {
+                    '.'
+                };
+                spans.push(Span::styled(c.to_string(), byte_style(start + i)));
+            }
+            Spans::from(spans)
+        })
+        .collect();
+    let hex = Paragraph::new(lines)
+        .block(Block::default().borders(Borders::ALL).title("Bytes"))
+        .style(style);
+    f.render_widget(hex, chunk);
+}
+
 pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App, offset: &mut usize) {
     let _active_style = Style::default().bg(Color::Rgb(50, 50, 50));
     let _deactive_style = Style::default().bg(Color::Rgb(0, 0, 0));
@@ -59,23 +158,46 @@ pub fn ui<B: Backend>(f: &mut Frame<B>, app: &mut App, offset: &mut usize) {
     } else {
         _deactive_style
     };
+    let bytes_style = if let InputMode::Bytes = app.get_input_mode() {
+        _active_style
+    } else {
+        _deactive_style
+    };
 
     let chunks = Layout::default()
         .direction(Direction::Vertical)
         .constraints(
             [
                 Constraint::Length(1),
-                Constraint::Percentage(50),
-                Constraint::Percentage(50),
+                Constraint::Percentage(40),
+                Constraint::Percentage(30),
+                Constraint::Percentage(30),
+                Constraint::Length(1),
             ]
             .as_ref(),
         )
         .split(f.size());
-    let view = app.get_view_text();
-    let text = Paragraph::new(Text::raw(view)).style(text_style);
-    let filter =
-        Paragraph::new(Text::raw("filter:".to_string() + &app.get_filter())).style(filter_style);
+    let mut filter = vec![Span::raw("filter:".to_string() + &app.get_filter())];
+    if let Some(x) = app.get_filter_error() {
+        filter.push(Span::styled(
+            format!("  {}", x),
+            Style::default().fg(Color::LightRed),
+        ));
+    }
+    let filter = Paragraph::new(Spans::from(filter)).style(filter_style);
     f.render_widget(filter, chunks[0]);
     list_ui(f, app, chunks[1], offset, list_style);
-    f.render_widget(text, chunks[2]);
+    tree_ui(f, app, chunks[2], text_style);
+    hex_ui(f, app, chunks[3], bytes_style);
+    let status = match app.get_command() {
+        Some(x) => Paragraph::new(Text::raw(":".to_string() + &x)).style(_active_style),
+        None => {
+            let status = match app.get_capture_stats() {
+                Some(x) => format!("{}  [{}]", app.get_status(), x),
+                None => app.get_status(),
+            };
+            Paragraph::new(Text::raw(status)).style(_deactive_style)
+        }
+    };
+    f.render_widget(status, chunks[4]);
 }